@@ -0,0 +1,244 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, spanned::Spanned, DeriveInput, Error, Ident, Lit, Meta, NestedMeta, Path,
+};
+
+/// Settings read from `#[importer(...)]` attributes
+struct ImporterAttrs {
+    name: Option<Ident>,
+    uuid: Option<[u8; 16]>,
+    extensions: Vec<String>,
+    version: u32,
+    format: Option<Ident>,
+    validate: Option<Path>,
+}
+
+fn parse_format(lit: &syn::LitStr) -> syn::Result<Ident> {
+    let name = match lit.value().to_lowercase().as_str() {
+        "ron" => "Ron",
+        "json" => "Json",
+        "toml" => "Toml",
+        other => {
+            return Err(Error::new(
+                lit.span(),
+                format!(
+                    "unknown format {:?}, expected \"ron\", \"json\" or \"toml\"",
+                    other
+                ),
+            ))
+        }
+    };
+    Ok(Ident::new(name, lit.span()))
+}
+
+fn parse_extension(lit: &Lit) -> syn::Result<String> {
+    match lit {
+        Lit::Str(ext) => {
+            let value = ext.value().trim_start_matches('.').to_lowercase();
+            if value.contains('.') {
+                return Err(Error::new(
+                    ext.span(),
+                    "importers are chosen by the last extension of a file, so extensions can't contain '.'",
+                ));
+            }
+            Ok(value)
+        }
+        lit => Err(Error::new(lit.span(), "expected an extension string")),
+    }
+}
+
+fn parse_attrs(input: &DeriveInput) -> syn::Result<ImporterAttrs> {
+    let mut attrs = ImporterAttrs {
+        name: None,
+        uuid: None,
+        extensions: Vec::new(),
+        version: 1,
+        format: None,
+        validate: None,
+    };
+    for attr in input.attrs.iter().filter(|a| a.path.is_ident("importer")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new(meta.span(), "expected #[importer(...)]")),
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(pair)) => {
+                    let key = pair
+                        .path
+                        .get_ident()
+                        .map(ToString::to_string)
+                        .unwrap_or_default();
+                    match (key.as_str(), &pair.lit) {
+                        ("name", Lit::Str(name)) => attrs.name = Some(name.parse()?),
+                        ("uuid", Lit::Str(uuid)) => {
+                            let parsed = uuid::Uuid::parse_str(&uuid.value())
+                                .map_err(|err| Error::new(uuid.span(), err))?;
+                            attrs.uuid = Some(*parsed.as_bytes());
+                        }
+                        ("extension", lit) => attrs.extensions.push(parse_extension(lit)?),
+                        ("version", Lit::Int(version)) => attrs.version = version.base10_parse()?,
+                        ("format", Lit::Str(format)) => attrs.format = Some(parse_format(format)?),
+                        ("validate", Lit::Str(path)) => attrs.validate = Some(path.parse()?),
+                        _ => {
+                            return Err(Error::new(
+                                pair.span(),
+                                format!("unexpected importer attribute `{}`", key),
+                            ))
+                        }
+                    }
+                }
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("extensions") => {
+                    for ext in list.nested {
+                        match ext {
+                            NestedMeta::Lit(lit) => attrs.extensions.push(parse_extension(&lit)?),
+                            meta => {
+                                return Err(Error::new(meta.span(), "expected an extension string"))
+                            }
+                        }
+                    }
+                }
+                nested => return Err(Error::new(nested.span(), "unexpected importer attribute")),
+            }
+        }
+    }
+    Ok(attrs)
+}
+
+/// Generates an importer for a serde asset type, named after it with an `Importer` suffix.
+///
+/// ```ignore
+/// #[derive(TypeUuid, Serialize, Deserialize, Importer)]
+/// #[uuid = "43b8d830-3da6-4cc2-999d-4d62fad1a1bb"]
+/// #[importer(extensions("item", "items"), format = "ron", version = 2, validate = "Item::validate")]
+/// struct Item { ... }
+///
+/// app.add_asset::<Item>().add_importer_with_extensions::<ItemImporter>();
+/// ```
+///
+/// Attributes, all optional:
+/// - `extensions("a", "b")` or `extension = "a"`: the extensions the importer is registered for
+/// - `format`: `"ron"` (the default), `"json"` or `"toml"`
+/// - `version`: the importer version, bumped to reimport files already imported. Defaults to 1.
+/// - `uuid`: the importer's type UUID. Derived from the asset type's and the format's if not set.
+/// - `name`: the name of the importer type
+/// - `validate`: a function taking `&T` and returning `Result<(), E>`, where `E` is an error
+///   type that is `Send + Sync`. Files that fail it don't import.
+///
+/// The asset's UUID is kept in the importer state, so it stays the same across imports.
+#[proc_macro_derive(Importer, attributes(importer))]
+pub fn derive_importer(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    importer(&input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+fn importer(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "Importer can't be derived for generic types, use SerdeImporter instead",
+        ));
+    }
+    let attrs = parse_attrs(input)?;
+    let krate = quote!(::bevy_atelier);
+    let private = quote!(#krate::__private);
+    let asset = &input.ident;
+    let vis = &input.vis;
+    let importer = attrs
+        .name
+        .unwrap_or_else(|| format_ident!("{}Importer", asset));
+    let format = attrs
+        .format
+        .unwrap_or_else(|| Ident::new("Ron", asset.span()));
+    let version = attrs.version;
+    let extensions = &attrs.extensions;
+    let uuid = match attrs.uuid {
+        Some(bytes) => quote!([#(#bytes),*]),
+        None => quote! {
+            #private::xor_uuid(
+                #private::xor_uuid(
+                    <#asset as #private::type_uuid::TypeUuid>::UUID,
+                    <#krate::#format as #krate::DataFormat>::UUID,
+                ),
+                *b"atelier/derive  ",
+            )
+        },
+    };
+    let validate = attrs.validate.map(|validate| {
+        quote! {
+            #validate(&asset).map_err(|e| #private::atelier_importer::Error::Boxed(Box::new(e)))?;
+        }
+    });
+    let doc = format!(
+        "Imports [{}] assets from {} files",
+        asset,
+        format.to_string().to_uppercase()
+    );
+
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(Default, Clone, Copy, Debug)]
+        #vis struct #importer;
+
+        impl #private::type_uuid::TypeUuid for #importer {
+            const UUID: #private::type_uuid::Bytes = #uuid;
+        }
+
+        impl #krate::ImporterExtensions for #importer {
+            const EXTENSIONS: &'static [&'static str] = &[#(#extensions),*];
+        }
+
+        impl #private::atelier_importer::AsyncImporter for #importer {
+            fn version_static() -> u32
+            where
+                Self: Sized,
+            {
+                #version
+            }
+            fn version(&self) -> u32 {
+                Self::version_static()
+            }
+
+            type Options = ();
+
+            type State = #krate::NamedState;
+
+            fn import<'a>(
+                &'a self,
+                _op: &'a mut #private::atelier_importer::ImportOp,
+                source: &'a mut (dyn #private::futures_io::AsyncRead + Unpin + Send + Sync),
+                _options: &Self::Options,
+                state: &'a mut Self::State,
+            ) -> #private::futures_core::future::BoxFuture<
+                'a,
+                #private::atelier_importer::Result<#private::atelier_importer::ImporterValue>,
+            > {
+                Box::pin(async move {
+                    use #private::futures_util::AsyncReadExt;
+                    let id = state.id("asset");
+                    let mut text = String::new();
+                    source.read_to_string(&mut text).await?;
+                    let asset: #asset = <#krate::#format as #krate::DataFormat>::parse(&text)
+                        .map_err(|e| #private::atelier_importer::Error::Boxed(Box::new(e)))?;
+                    #validate
+                    Ok(#private::atelier_importer::ImporterValue {
+                        assets: vec![#private::atelier_importer::ImportedAsset {
+                            id,
+                            search_tags: vec![],
+                            build_deps: vec![],
+                            load_deps: vec![],
+                            build_pipeline: None,
+                            asset_data: Box::new(asset),
+                        }],
+                    })
+                })
+            }
+        }
+    })
+}
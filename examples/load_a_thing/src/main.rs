@@ -8,13 +8,13 @@ use bevy::{
     utils::Duration,
 };
 use bevy_atelier::AssetPlugin;
-use bevy_atelier::{image::Image, AddAsset, AssetServer, AssetServerSettings, Assets};
+use bevy_atelier::{image::Image, AddAsset, AssetServer, AssetServerSettings, Assets, RonImporter};
 
 fn main() {
     let mut app = App::build();
     app
-        // Try creating a packfile using atelier-cli and uncommenting this line
-        // .add_resource(AssetServerSettings::default_packfile())
+        // Try creating a packfile with `cargo run --bin atelier_pack -- assets` and uncommenting this line
+        // .add_resource(AssetServerSettings::default_mapped_packfile())
         .add_plugins(MinimalPlugins)
         .add_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
             1.0 / 60.0,
@@ -26,6 +26,7 @@ fn main() {
         .add_plugin(AssetPlugin)
         .add_asset::<bevy_atelier::image::Image>()
         .add_asset::<MyCustomAsset>()
+        .add_importer::<RonImporter<MyCustomAsset>, _>("custom")
         .add_startup_system(load_the_thing.system())
         .add_system(use_the_thing.system());
     if cfg!(feature = "atelier-daemon-headless") {
@@ -41,13 +42,13 @@ struct MyCustomAsset(u32);
 
 struct ThingHandle(Handle<Image>);
 struct MyCustomHandle(Handle<MyCustomAsset>);
-fn load_the_thing(commands: &mut Commands, asset_server: Res<AssetServer>, mut custom_assets: ResMut<Assets<MyCustomAsset>>) {
+fn load_the_thing(commands: &mut Commands, asset_server: Res<AssetServer>) {
     std::thread::sleep(std::time::Duration::from_millis(100));
     let handle: Handle<Image> = asset_server.load("bevy_logo.png");
     println!("{:?}", handle);
     commands.insert_resource(ThingHandle(handle));
 
-    let handle = custom_assets.add(MyCustomAsset(10));
+    let handle: Handle<MyCustomAsset> = asset_server.load("thing.custom");
     commands.insert_resource(MyCustomHandle(handle));
 }
 
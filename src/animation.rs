@@ -0,0 +1,733 @@
+use crate::{
+    image::{Image, ImageImportError, ImageImporterOptions},
+    texture_atlas::{self, AtlasRect},
+    AssetServer, NamedState,
+};
+use atelier_core::{AssetRef, AssetUuid};
+use atelier_importer::{AsyncImporter, Error, ImportOp, ImportedAsset, ImporterValue, Result};
+use atelier_loader::handle::Handle;
+use futures_core::future::BoxFuture;
+use futures_io::AsyncRead;
+use futures_util::AsyncReadExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use thiserror::Error;
+use type_uuid::*;
+
+/// The order in which the frames of a tag play
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimationDirection {
+    Forward,
+    Reverse,
+    /// Forward, then back again without repeating the end frames
+    PingPong,
+    /// Backward, then forward again without repeating the end frames
+    PingPongReverse,
+}
+
+/// A frame of an [AnimationClip]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct AnimationFrame {
+    /// Where the frame is in the sheet image
+    pub rect: AtlasRect,
+    /// Where the rect goes within the untrimmed frame, for sheets exported with trimming
+    pub offset: [u32; 2],
+    /// The size of the untrimmed frame
+    pub source_size: [u32; 2],
+    pub duration: Duration,
+}
+
+/// A named range of frames, such as "idle" or "run"
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AnimationTag {
+    pub name: String,
+    /// Index of the first frame, inclusive
+    pub from: usize,
+    /// Index of the last frame, inclusive
+    pub to: usize,
+    pub direction: AnimationDirection,
+}
+
+/// A frame animation over a sprite sheet
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[uuid = "bb0916a3-2803-446a-b3c5-7996644cbdd6"]
+pub struct AnimationClip {
+    /// The sheet [Image], which is loaded along with the clip
+    pub image: AssetUuid,
+    pub frames: Vec<AnimationFrame>,
+    pub tags: Vec<AnimationTag>,
+}
+
+impl AnimationClip {
+    pub fn image_handle(&self, asset_server: &AssetServer) -> Handle<Image> {
+        asset_server.load_by_id(self.image)
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&AnimationTag> {
+        self.tags.iter().find(|tag| tag.name == name)
+    }
+
+    /// Frame indices of one loop of a tag, in playback order. Every frame plays forward if `tag` is `None`.
+    pub fn sequence(&self, tag: Option<&str>) -> Option<Vec<usize>> {
+        let (from, to, direction) = match tag {
+            Some(name) => {
+                let tag = self.tag(name)?;
+                (
+                    tag.from,
+                    tag.to.min(self.frames.len().checked_sub(1)?),
+                    tag.direction,
+                )
+            }
+            None => (
+                0,
+                self.frames.len().checked_sub(1)?,
+                AnimationDirection::Forward,
+            ),
+        };
+        let forward = from..=to;
+        let back = (from + 1..to).rev();
+        Some(match direction {
+            AnimationDirection::Forward => forward.collect(),
+            AnimationDirection::Reverse => forward.rev().collect(),
+            AnimationDirection::PingPong => forward.chain(back).collect(),
+            AnimationDirection::PingPongReverse => {
+                let mut sequence: Vec<_> = forward.rev().collect();
+                sequence.extend(from + 1..to);
+                sequence
+            }
+        })
+    }
+
+    /// The total duration of one loop of a tag, or of every frame if `tag` is `None`
+    pub fn duration(&self, tag: Option<&str>) -> Option<Duration> {
+        Some(
+            self.sequence(tag)?
+                .into_iter()
+                .map(|index| self.frames[index].duration)
+                .sum(),
+        )
+    }
+
+    /// The index of the frame showing `elapsed` into a looping playback of a tag
+    pub fn frame_at(&self, tag: Option<&str>, elapsed: Duration) -> Option<usize> {
+        let sequence = self.sequence(tag)?;
+        let total: Duration = sequence
+            .iter()
+            .map(|&index| self.frames[index].duration)
+            .sum();
+        if total.as_nanos() == 0 {
+            return sequence.first().copied();
+        }
+        let mut remaining = Duration::from_nanos((elapsed.as_nanos() % total.as_nanos()) as u64);
+        for index in sequence {
+            let duration = self.frames[index].duration;
+            if remaining < duration {
+                return Some(index);
+            }
+            remaining -= duration;
+        }
+        None
+    }
+}
+
+/// Import settings for Aseprite files, editable in their `.meta` files
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug)]
+#[uuid = "e445329c-8e15-4ac6-910d-3711c414f616"]
+#[serde(default)]
+pub struct AsepriteImporterOptions {
+    /// The directory of a JSON export relative to the asset root, used to find its sheet image
+    pub directory: String,
+    /// Pixels between frames when packing the frames of an `.aseprite` file into a sheet
+    pub padding: u32,
+    /// Applied to the sheet image. Its `sheet` field is ignored, and frame rects stay in the
+    /// coordinates of the unscaled sheet.
+    pub image: ImageImporterOptions,
+}
+
+impl Default for AsepriteImporterOptions {
+    fn default() -> Self {
+        AsepriteImporterOptions {
+            directory: String::new(),
+            padding: 1,
+            image: ImageImporterOptions::default(),
+        }
+    }
+}
+
+/// Errors that occur while importing an Aseprite file or JSON export
+#[derive(Error, Debug)]
+pub enum AsepriteError {
+    #[error("Invalid Aseprite file: {0}")]
+    Aseprite(#[from] asefile::AsepriteParseError),
+    #[error("Invalid Aseprite JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to read sheet image {path:?}: {error}")]
+    Io { path: PathBuf, error: io::Error },
+    #[error(transparent)]
+    Image(#[from] ImageImportError),
+    #[error("Frames do not fit into a {max_size}x{max_size} sheet.")]
+    TooLarge { max_size: u32 },
+    #[error("Tag {name:?} refers to frames that don't exist.")]
+    TagOutOfBounds { name: String },
+}
+
+const MAX_SHEET_SIZE: u32 = 8192;
+
+mod json {
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct Rect {
+        pub x: u32,
+        pub y: u32,
+        pub w: u32,
+        pub h: u32,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Size {
+        pub w: u32,
+        pub h: u32,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Frame {
+        pub frame: Rect,
+        pub sprite_source_size: Rect,
+        pub source_size: Size,
+        pub duration: u64,
+    }
+
+    /// Aseprite exports frames either as an array or as an object keyed by file name
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    pub enum Frames {
+        Array(Vec<Frame>),
+        Hash(serde_json::Map<String, serde_json::Value>),
+    }
+
+    #[derive(Deserialize)]
+    pub struct Tag {
+        pub name: String,
+        pub from: usize,
+        pub to: usize,
+        #[serde(default)]
+        pub direction: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Meta {
+        pub image: String,
+        #[serde(default)]
+        pub frame_tags: Vec<Tag>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Export {
+        pub frames: Frames,
+        pub meta: Meta,
+    }
+}
+
+impl From<json::Frame> for AnimationFrame {
+    fn from(frame: json::Frame) -> Self {
+        AnimationFrame {
+            rect: AtlasRect {
+                x: frame.frame.x,
+                y: frame.frame.y,
+                width: frame.frame.w,
+                height: frame.frame.h,
+            },
+            offset: [frame.sprite_source_size.x, frame.sprite_source_size.y],
+            source_size: [frame.source_size.w, frame.source_size.h],
+            duration: Duration::from_millis(frame.duration),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AsepriteFormat {
+    /// `.aseprite` and `.ase` files, whose frames are flattened and packed into a sheet on import
+    Binary,
+    /// The JSON data exported alongside a sprite sheet by Aseprite
+    Json,
+}
+
+/// Imports Aseprite animations as a sheet [Image] and an [AnimationClip].
+///
+/// `.aseprite` and `.ase` files are registered by default. JSON exports share their extension with
+/// plain data, so register `AsepriteImporter::new(asset_root, AsepriteFormat::Json)` for the extension
+/// your exports use. Their sheet image is a build dependency of the clip.
+#[derive(TypeUuid)]
+#[uuid = "2112b006-90b4-41f4-9def-1327865760b4"]
+pub struct AsepriteImporter {
+    asset_root: PathBuf,
+    format: AsepriteFormat,
+}
+
+impl AsepriteImporter {
+    /// An importer that resolves sheet image paths relative to `asset_root`
+    pub fn new<P: AsRef<Path>>(asset_root: P, format: AsepriteFormat) -> Self {
+        AsepriteImporter {
+            asset_root: asset_root.as_ref().to_path_buf(),
+            format,
+        }
+    }
+
+    fn import_binary(
+        &self,
+        bytes: &[u8],
+        options: &AsepriteImporterOptions,
+    ) -> std::result::Result<(Image, Vec<AnimationFrame>, Vec<AnimationTag>), AsepriteError> {
+        let ase = asefile::AsepriteFile::read(bytes)?;
+        let (width, height) = (ase.width() as u32, ase.height() as u32);
+        let frame_count = ase.num_frames();
+        let sizes = vec![(width, height); frame_count as usize];
+        let (sheet_width, sheet_height, positions) =
+            texture_atlas::pack(&sizes, options.padding, MAX_SHEET_SIZE).ok_or(
+                AsepriteError::TooLarge {
+                    max_size: MAX_SHEET_SIZE,
+                },
+            )?;
+
+        let mut sheet = image::RgbaImage::new(sheet_width, sheet_height);
+        let mut frames = Vec::new();
+        for (index, &(x, y)) in (0..frame_count).zip(&positions) {
+            let frame = ase.frame(index);
+            image::imageops::replace(&mut sheet, &frame.image(), x as i64, y as i64);
+            frames.push(AnimationFrame {
+                rect: AtlasRect {
+                    x,
+                    y,
+                    width,
+                    height,
+                },
+                offset: [0, 0],
+                source_size: [width, height],
+                duration: Duration::from_millis(frame.duration() as u64),
+            });
+        }
+        let tags = (0..ase.num_tags())
+            .map(|index| {
+                let tag = ase.tag(index);
+                AnimationTag {
+                    name: tag.name().to_string(),
+                    from: tag.from_frame() as usize,
+                    to: tag.to_frame() as usize,
+                    direction: match tag.animation_direction() {
+                        asefile::AnimationDirection::Forward => AnimationDirection::Forward,
+                        asefile::AnimationDirection::Reverse => AnimationDirection::Reverse,
+                        _ => AnimationDirection::PingPong,
+                    },
+                }
+            })
+            .collect();
+        let image = options.image.apply(image::DynamicImage::ImageRgba8(sheet));
+        Ok((image, frames, tags))
+    }
+
+    fn import_json(
+        &self,
+        bytes: &[u8],
+        options: &AsepriteImporterOptions,
+    ) -> std::result::Result<(Image, Vec<AnimationFrame>, Vec<AnimationTag>, PathBuf), AsepriteError>
+    {
+        let export: json::Export = serde_json::from_slice(bytes)?;
+        let frames = match export.frames {
+            json::Frames::Array(frames) => frames.into_iter().map(AnimationFrame::from).collect(),
+            json::Frames::Hash(frames) => frames
+                .into_iter()
+                .map(|(_, frame)| serde_json::from_value::<json::Frame>(frame).map(Into::into))
+                .collect::<std::result::Result<_, _>>()?,
+        };
+        let tags = export
+            .meta
+            .frame_tags
+            .into_iter()
+            .map(|tag| AnimationTag {
+                direction: match tag.direction.as_str() {
+                    "reverse" => AnimationDirection::Reverse,
+                    "pingpong" => AnimationDirection::PingPong,
+                    "pingpong_reverse" => AnimationDirection::PingPongReverse,
+                    _ => AnimationDirection::Forward,
+                },
+                name: tag.name,
+                from: tag.from,
+                to: tag.to,
+            })
+            .collect();
+
+        let path = self
+            .asset_root
+            .join(&options.directory)
+            .join(&export.meta.image);
+        let image_bytes = std::fs::read(&path).map_err(|error| AsepriteError::Io {
+            path: path.clone(),
+            error,
+        })?;
+        let image = options.image.process(&image_bytes, None)?;
+        Ok((image, frames, tags, path))
+    }
+}
+
+impl AsyncImporter for AsepriteImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        2
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = AsepriteImporterOptions;
+
+    type State = NamedState;
+
+    /// Produces the sheet image and the animation clip.
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        let options = options.clone();
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            source.read_to_end(&mut bytes).await?;
+            let (image, frames, tags, build_deps) = match self.format {
+                AsepriteFormat::Binary => self
+                    .import_binary(&bytes, &options)
+                    .map(|(image, frames, tags)| (image, frames, tags, vec![])),
+                AsepriteFormat::Json => {
+                    self.import_json(&bytes, &options)
+                        .map(|(image, frames, tags, path)| {
+                            (image, frames, tags, vec![AssetRef::Path(path)])
+                        })
+                }
+            }
+            .map_err(|e| Error::Boxed(Box::new(e)))?;
+            if let Some(tag) = tags
+                .iter()
+                .find(|tag| tag.from > tag.to || tag.to >= frames.len())
+            {
+                return Err(Error::Boxed(Box::new(AsepriteError::TagOutOfBounds {
+                    name: tag.name.clone(),
+                })));
+            }
+
+            state.retain_names(vec!["image", "clip"]);
+            let image_id = state.id("image");
+            // A path resolves to the first asset imported from it, so the clip goes first
+            Ok(ImporterValue {
+                assets: vec![
+                    ImportedAsset {
+                        id: state.id("clip"),
+                        search_tags: vec![],
+                        build_deps: build_deps.clone(),
+                        load_deps: vec![AssetRef::Uuid(image_id)],
+                        build_pipeline: None,
+                        asset_data: Box::new(AnimationClip {
+                            image: image_id,
+                            frames,
+                            tags,
+                        }),
+                    },
+                    ImportedAsset {
+                        id: image_id,
+                        search_tags: vec![],
+                        build_deps,
+                        load_deps: vec![],
+                        build_pipeline: None,
+                        asset_data: Box::new(image),
+                    },
+                ],
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imported_data;
+    use std::fs;
+
+    fn frame(x: u32, millis: u64) -> AnimationFrame {
+        AnimationFrame {
+            rect: AtlasRect {
+                x,
+                y: 0,
+                width: 2,
+                height: 2,
+            },
+            offset: [0, 0],
+            source_size: [2, 2],
+            duration: Duration::from_millis(millis),
+        }
+    }
+
+    fn clip(direction: AnimationDirection) -> AnimationClip {
+        AnimationClip {
+            image: AssetUuid([0; 16]),
+            frames: (0..5).map(|index| frame(index * 2, 100)).collect(),
+            tags: vec![AnimationTag {
+                name: "walk".to_string(),
+                from: 1,
+                to: 3,
+                direction,
+            }],
+        }
+    }
+
+    #[test]
+    fn sequences_follow_the_tag_direction() {
+        let sequence = |direction| clip(direction).sequence(Some("walk")).unwrap();
+        assert_eq!(sequence(AnimationDirection::Forward), vec![1, 2, 3]);
+        assert_eq!(sequence(AnimationDirection::Reverse), vec![3, 2, 1]);
+        assert_eq!(sequence(AnimationDirection::PingPong), vec![1, 2, 3, 2]);
+        assert_eq!(
+            sequence(AnimationDirection::PingPongReverse),
+            vec![3, 2, 1, 2]
+        );
+        let clip = clip(AnimationDirection::PingPong);
+        assert_eq!(clip.sequence(None).unwrap(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(clip.sequence(Some("run")), None);
+    }
+
+    #[test]
+    fn frames_are_found_by_elapsed_time() {
+        let clip = clip(AnimationDirection::PingPong);
+        assert_eq!(
+            clip.duration(Some("walk")),
+            Some(Duration::from_millis(400))
+        );
+        let frame_at = |millis| clip.frame_at(Some("walk"), Duration::from_millis(millis));
+        assert_eq!(frame_at(0), Some(1));
+        assert_eq!(frame_at(250), Some(3));
+        assert_eq!(frame_at(399), Some(2));
+        assert_eq!(frame_at(400), Some(1));
+    }
+
+    fn import(
+        importer: &AsepriteImporter,
+        bytes: &[u8],
+    ) -> std::result::Result<ImporterValue, atelier_importer::Error> {
+        let mut source = bytes;
+        futures_executor::block_on(importer.import(
+            &mut ImportOp::default(),
+            &mut source,
+            &AsepriteImporterOptions {
+                directory: "sprites".to_string(),
+                ..Default::default()
+            },
+            &mut NamedState::default(),
+        ))
+    }
+
+    /// An asset root with a 6x2 sheet image at `sprites/hero.png`
+    fn asset_root() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("aseprite-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(root.join("sprites")).unwrap();
+        image::RgbaImage::new(6, 2)
+            .save(root.join("sprites/hero.png"))
+            .unwrap();
+        root
+    }
+
+    fn json_frame(x: u32, duration: u64) -> String {
+        format!(
+            r#"{{ "frame": {{ "x": {}, "y": 0, "w": 2, "h": 2 }},
+                 "spriteSourceSize": {{ "x": 0, "y": 0, "w": 2, "h": 2 }},
+                 "sourceSize": {{ "w": 2, "h": 2 }}, "duration": {} }}"#,
+            x, duration
+        )
+    }
+
+    const JSON_META: &str = r#"{
+        "image": "hero.png",
+        "frameTags": [
+            { "name": "idle", "from": 0, "to": 0, "direction": "forward" },
+            { "name": "walk", "from": 1, "to": 2, "direction": "pingpong_reverse" },
+            { "name": "back", "from": 0, "to": 2, "direction": "reverse" }
+        ]
+    }"#;
+
+    fn check_json_clip(value: &ImporterValue, root: &Path) {
+        let clip: AnimationClip = imported_data(&value.assets[0]);
+        assert_eq!(clip.image, value.assets[1].id);
+        assert_eq!(value.assets[1].asset_data.uuid(), Image::UUID);
+        assert_eq!(
+            value.assets[0].build_deps,
+            vec![AssetRef::Path(root.join("sprites").join("hero.png"))]
+        );
+        let frames: Vec<_> = clip
+            .frames
+            .iter()
+            .map(|frame| (frame.rect.x, frame.duration.as_millis()))
+            .collect();
+        assert_eq!(frames, vec![(0, 100), (2, 150), (4, 200)]);
+        let directions: Vec<_> = clip.tags.iter().map(|tag| tag.direction).collect();
+        assert_eq!(
+            directions,
+            vec![
+                AnimationDirection::Forward,
+                AnimationDirection::PingPongReverse,
+                AnimationDirection::Reverse,
+            ]
+        );
+        assert_eq!(clip.tag("walk").map(|tag| (tag.from, tag.to)), Some((1, 2)));
+    }
+
+    #[test]
+    fn imports_json_array_exports() {
+        let root = asset_root();
+        let importer = AsepriteImporter::new(&root, AsepriteFormat::Json);
+        let export = format!(
+            r#"{{ "frames": [{}, {}, {}], "meta": {} }}"#,
+            json_frame(0, 100),
+            json_frame(2, 150),
+            json_frame(4, 200),
+            JSON_META
+        );
+        check_json_clip(&import(&importer, export.as_bytes()).unwrap(), &root);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn imports_json_hash_exports_in_document_order() {
+        let root = asset_root();
+        let importer = AsepriteImporter::new(&root, AsepriteFormat::Json);
+        // Sorting the keys would put "hero 10" before "hero 8"
+        let export = format!(
+            r#"{{ "frames": {{ "hero 8.ase": {}, "hero 9.ase": {}, "hero 10.ase": {} }}, "meta": {} }}"#,
+            json_frame(0, 100),
+            json_frame(2, 150),
+            json_frame(4, 200),
+            JSON_META
+        );
+        check_json_clip(&import(&importer, export.as_bytes()).unwrap(), &root);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn rejects_tags_past_the_last_frame() {
+        let root = asset_root();
+        let importer = AsepriteImporter::new(&root, AsepriteFormat::Json);
+        let export = format!(
+            r#"{{ "frames": [{}], "meta": {{ "image": "hero.png",
+                "frameTags": [{{ "name": "walk", "from": 0, "to": 1 }}] }} }}"#,
+            json_frame(0, 100)
+        );
+        assert!(import(&importer, export.as_bytes()).is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    fn chunk(kind: u16, data: Vec<u8>) -> Vec<u8> {
+        let mut chunk = (data.len() as u32 + 6).to_le_bytes().to_vec();
+        chunk.extend_from_slice(&kind.to_le_bytes());
+        chunk.extend(data);
+        chunk
+    }
+
+    fn string(value: &str) -> Vec<u8> {
+        let mut bytes = (value.len() as u16).to_le_bytes().to_vec();
+        bytes.extend_from_slice(value.as_bytes());
+        bytes
+    }
+
+    const COLORS: [[u8; 4]; 3] = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+
+    /// A 2x2 RGBA sprite with one layer, a solid red, green and blue frame lasting 100, 150 and
+    /// 200ms, and a ping-pong "walk" tag over all three
+    fn aseprite_file() -> Vec<u8> {
+        let durations = [100u16, 150, 200];
+        let mut frames = Vec::new();
+        for (index, (color, duration)) in COLORS.iter().zip(&durations).enumerate() {
+            let mut chunks = Vec::new();
+            if index == 0 {
+                // Visible normal layer at full opacity
+                let mut layer = vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 0, 0, 0];
+                layer.extend(string("body"));
+                chunks.push(chunk(0x2004, layer));
+                // Frames 0 to 2, ping-pong
+                let mut tags = vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+                tags.extend(&[0, 0, 2, 0, 2]);
+                tags.extend(vec![0; 12]);
+                tags.extend(string("walk"));
+                chunks.push(chunk(0x2018, tags));
+            }
+            // Raw cel on layer 0 at the origin
+            let mut cel = vec![0; 16];
+            cel[6] = 255;
+            cel.extend(&[2, 0, 2, 0]);
+            for _ in 0..4 {
+                cel.extend(color);
+            }
+            chunks.push(chunk(0x2005, cel));
+
+            let body: Vec<u8> = chunks.concat();
+            frames.extend((body.len() as u32 + 16).to_le_bytes().iter());
+            frames.extend(&[0xFA, 0xF1]);
+            frames.extend((chunks.len() as u16).to_le_bytes().iter());
+            frames.extend(duration.to_le_bytes().iter());
+            frames.extend(&[0, 0]);
+            frames.extend((chunks.len() as u32).to_le_bytes().iter());
+            frames.extend(body);
+        }
+
+        let mut header = ((128 + frames.len()) as u32).to_le_bytes().to_vec();
+        // Magic, frame count, width, height, color depth, flags and speed
+        for word in &[0xA5E0u16, 3, 2, 2, 32, 1, 0, 100] {
+            header.extend_from_slice(&word.to_le_bytes());
+        }
+        header.extend(vec![0; 8 + 4]);
+        // All 256 colors, square pixels and a 16x16 grid
+        header.extend(&[0, 0, 1, 1, 0, 0, 0, 0, 16, 0, 16, 0]);
+        header.resize(128, 0);
+        header.extend(frames);
+        header
+    }
+
+    #[test]
+    fn imports_aseprite_files() {
+        let importer = AsepriteImporter::new(std::env::temp_dir(), AsepriteFormat::Binary);
+        let value = import(&importer, &aseprite_file()).unwrap();
+        let clip: AnimationClip = imported_data(&value.assets[0]);
+        let image: Image = imported_data(&value.assets[1]);
+        assert_eq!(clip.image, value.assets[1].id);
+        assert!(value.assets[0].build_deps.is_empty());
+
+        let durations: Vec<_> = clip
+            .frames
+            .iter()
+            .map(|frame| frame.duration.as_millis())
+            .collect();
+        assert_eq!(durations, vec![100, 150, 200]);
+        assert_eq!(
+            clip.tags,
+            vec![AnimationTag {
+                name: "walk".to_string(),
+                from: 0,
+                to: 2,
+                direction: AnimationDirection::PingPong,
+            }]
+        );
+
+        // Each frame was packed into the sheet with its own color
+        let pixels = image.as_bytes();
+        for (frame, color) in clip.frames.iter().zip(&COLORS) {
+            assert_eq!((frame.rect.width, frame.rect.height), (2, 2));
+            let start = ((frame.rect.y * image.width() + frame.rect.x) * 4) as usize;
+            assert_eq!(&pixels[start..start + 4], color);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use memmap2::Mmap;
+use std::{
+    ops::{Deref, Range},
+    sync::Arc,
+};
+
+/// The serialized bytes of an asset's artifact.
+///
+/// Artifacts served from a memory-mapped packfile borrow their bytes straight from the mapping,
+/// everything else owns a buffer.
+#[derive(Clone)]
+pub enum ArtifactBytes {
+    Owned(Vec<u8>),
+    Mapped { map: Arc<Mmap>, range: Range<usize> },
+}
+
+impl ArtifactBytes {
+    pub(crate) fn mapped(map: Arc<Mmap>, range: Range<usize>) -> Self {
+        debug_assert!(range.end <= map.len());
+        ArtifactBytes::Mapped { map, range }
+    }
+
+    /// Returns true if the bytes are borrowed from a memory-mapped packfile.
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, ArtifactBytes::Mapped { .. })
+    }
+
+    /// Returns a sub-range of these bytes. Mapped bytes are not copied.
+    pub fn slice(&self, range: Range<usize>) -> ArtifactBytes {
+        match self {
+            ArtifactBytes::Owned(bytes) => ArtifactBytes::Owned(bytes[range].to_vec()),
+            ArtifactBytes::Mapped { map, range: outer } => {
+                assert!(range.end <= outer.len(), "artifact slice out of bounds");
+                ArtifactBytes::Mapped {
+                    map: map.clone(),
+                    range: (outer.start + range.start)..(outer.start + range.end),
+                }
+            }
+        }
+    }
+
+    /// Converts into an owned buffer, copying if the bytes are mapped.
+    pub fn into_vec(self) -> Vec<u8> {
+        match self {
+            ArtifactBytes::Owned(bytes) => bytes,
+            ArtifactBytes::Mapped { map, range } => map[range].to_vec(),
+        }
+    }
+}
+
+impl Deref for ArtifactBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ArtifactBytes::Owned(bytes) => bytes,
+            ArtifactBytes::Mapped { map, range } => &map[range.clone()],
+        }
+    }
+}
+
+impl AsRef<[u8]> for ArtifactBytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl std::fmt::Debug for ArtifactBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ArtifactBytes")
+            .field("len", &self.len())
+            .field("mapped", &self.is_mapped())
+            .finish()
+    }
+}
+
+/// Asset types that are built directly from their artifact bytes instead of through serde.
+///
+/// Register these with [AddAsset::add_artifact_asset](crate::AddAsset::add_artifact_asset). When the
+/// artifact comes from a memory-mapped packfile the bytes are borrowed from the mapping, so a type
+/// that keeps the [ArtifactBytes] around (e.g. a large vertex or pixel buffer) never copies them.
+pub trait FromArtifact: Sized {
+    fn from_artifact(bytes: ArtifactBytes) -> Result<Self, anyhow::Error>;
+}
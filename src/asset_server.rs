@@ -1,23 +1,27 @@
 use crate::{
-    AssetLoadError, AssetLoadRequestHandler, AssetTypeId, AssetTypeRegistry, LoadRequest,
-    HANDLE_ALLOCATOR,
+    indirection::{
+        IndirectionState, LocaleResolver, VariantIndirectionResolver, VariantResolver,
+        LOCALE_RESOLVER,
+    },
+    packfile::{Packfile, PackfileError, PackfileIO, PackfileKey, PackfileStack},
+    pipeline::{BuildPipelines, PipelineImporter, PipelineRunner},
+    ArtifactBytes, ArtifactStorage, AssetLoadError, AssetLoadRequestHandler, AssetTypeId,
+    AssetTypeRegistry, LoadRequest, HANDLE_ALLOCATOR,
 };
 use anyhow::Result;
+use atelier_core::AssetUuid;
 use atelier_importer::BoxedImporter;
 pub use atelier_loader::storage::LoadStatus;
 use atelier_loader::{
     crossbeam_channel::{unbounded, Receiver, Sender},
     handle::{AssetHandle, GenericHandle, Handle, RefOp, SerdeContext},
-    packfile_io::PackfileReader,
     rpc_io::RpcIO,
-    storage::{
-        AssetLoadOp, DefaultIndirectionResolver, IndirectIdentifier, LoadHandle, LoaderInfoProvider,
-    },
+    storage::{AssetLoadOp, IndirectIdentifier, LoadHandle, LoaderInfoProvider},
     Loader,
 };
 use bevy_ecs::{Res, Resource, Resources};
 use bevy_log::*;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use std::{
     collections::{HashMap, HashSet},
     env,
@@ -52,6 +56,18 @@ pub enum AssetServerError {
     Io(#[from] io::Error),
     #[error("Failed to watch asset folder.")]
     AssetWatchError { path: PathBuf },
+    #[error("Packfile error: {0}")]
+    Packfile(#[from] PackfileError),
+    #[error("Artifact for a loaded asset is missing from the packfile.")]
+    MissingArtifact(LoadHandle),
+    #[error("Failed to deserialize a loaded asset.")]
+    AssetDeserializeError(LoadHandle),
+    #[error("Asset server is not loading from packfiles.")]
+    NotLayered,
+    #[error("No packfile layer with the given name.")]
+    MissingLayer(String),
+    #[error("Importer extension {0:?} contains a '.'; importers are chosen by the last extension of a file only.")]
+    InvalidExtension(String),
 }
 
 struct LoaderThread {
@@ -68,14 +84,47 @@ pub struct AssetInfo {
     pub load_state: LoadStatus,
 }
 
+/// The asset daemon is started on the first update, once every importer has been registered
 enum DaemonState {
-    Building(),
+    Building {
+        asset_dir: PathBuf,
+        importers: Vec<(String, Box<dyn BoxedImporter>)>,
+        pipeline: Option<PipelineRunner>,
+    },
+    Running,
+    /// Assets are loaded from packfiles, so there is no daemon
+    Disabled,
 }
 
 #[derive(Clone)]
 pub enum AssetServerSettings {
     Directory(String),
+    /// A packfile written by [PackfileWriter](crate::packfile::PackfileWriter), e.g. with `atelier_pack`.
+    /// Its header and the checksum of every artifact are checked when it is opened. Encrypted packfiles
+    /// need a key, so use [MappedPackfile](AssetServerSettings::MappedPackfile) for those.
     Packfile(String),
+    /// A packfile written by [PackfileWriter](crate::packfile::PackfileWriter). It is memory-mapped
+    /// and artifacts are borrowed from the mapping instead of being copied.
+    MappedPackfile {
+        path: String,
+        /// Key used to decrypt encrypted artifacts
+        key: Option<PackfileKey>,
+    },
+    /// An ordered stack of mapped packfiles, e.g. a base pack followed by patches and mods.
+    /// Later layers shadow assets of earlier layers with the same UUID or path.
+    Layered(PackfileSettings),
+}
+
+/// Settings for loading assets from a stack of mapped packfiles
+#[derive(Clone, Default, Debug)]
+pub struct PackfileSettings {
+    /// Paths of the packfiles, from bottom to top
+    pub layers: Vec<String>,
+    /// Key used to decrypt encrypted artifacts
+    pub key: Option<PackfileKey>,
+    /// Skips checking every artifact against its checksum when the packfiles are opened, which
+    /// reads them in full. Artifacts are then checked as they are loaded instead.
+    pub skip_verification: bool,
 }
 impl AssetServerSettings {
     pub fn default_directory() -> Self {
@@ -85,6 +134,13 @@ impl AssetServerSettings {
     pub fn default_packfile() -> Self {
         AssetServerSettings::Packfile("assets.pack".to_string())
     }
+
+    pub fn default_mapped_packfile() -> Self {
+        AssetServerSettings::MappedPackfile {
+            path: "assets.pack".to_string(),
+            key: None,
+        }
+    }
 }
 impl Default for AssetServerSettings {
     fn default() -> Self {
@@ -105,24 +161,33 @@ pub struct AssetServer {
     // TODO: this is a hack to enable retrieving generic AssetLoader<T>s. there must be a better way!
     loaders: Vec<Resources>,
     pub(crate) loader: Loader,
+    packfiles: Option<Arc<RwLock<PackfileStack>>>,
+    asset_dir: Option<PathBuf>,
+    daemon: DaemonState,
+    indirection: Mutex<IndirectionState>,
     ref_op_tx: Sender<RefOp>,
     ref_op_rx: Receiver<RefOp>,
 }
 
 impl AssetServer {
     pub fn new(settings: &AssetServerSettings) -> Result<Self> {
+        let mut packfiles = None;
+        let mut asset_dir = None;
+        let mut daemon = DaemonState::Disabled;
         let loader = match settings {
             #[cfg(feature = "assets-daemon")]
             AssetServerSettings::Directory(path) => {
-                let path = path.clone();
-                std::thread::spawn(move || {
-                    atelier_daemon::AssetDaemon::default()
-                        .with_importer("png", crate::image::ImageImporter)
-                        .with_db_path(".assets_db")
-                        .with_address("127.0.0.1:9999".parse().unwrap())
-                        .with_asset_dirs(vec![PathBuf::from(path)])
-                        .run();
-                });
+                let dir = PathBuf::from(path);
+                let importers = crate::default_importers(&dir)
+                    .into_iter()
+                    .map(|(ext, importer)| (ext.to_string(), importer))
+                    .collect();
+                asset_dir = Some(dir.clone());
+                daemon = DaemonState::Building {
+                    asset_dir: dir,
+                    importers,
+                    pipeline: None,
+                };
                 Loader::new_with_handle_allocator(
                     Box::new(RpcIO::default()),
                     Arc::new(&HANDLE_ALLOCATOR),
@@ -133,9 +198,33 @@ impl AssetServer {
                 anyhow::bail!("asset-daemon is required in order to load assets from a directory");
             }
             AssetServerSettings::Packfile(path) => {
-                println!("packfile: {:?}", path);
+                let stack = Self::open_layers(&PackfileSettings {
+                    layers: vec![path.clone()],
+                    ..Default::default()
+                })?;
+                packfiles = Some(stack.clone());
                 Loader::new_with_handle_allocator(
-                    Box::new(PackfileReader::new(std::fs::File::open(path)?).unwrap()),
+                    Box::new(PackfileIO::new(stack)),
+                    Arc::new(&HANDLE_ALLOCATOR),
+                )
+            }
+            AssetServerSettings::MappedPackfile { path, key } => {
+                let stack = Self::open_layers(&PackfileSettings {
+                    layers: vec![path.clone()],
+                    key: key.clone(),
+                    ..Default::default()
+                })?;
+                packfiles = Some(stack.clone());
+                Loader::new_with_handle_allocator(
+                    Box::new(PackfileIO::new(stack)),
+                    Arc::new(&HANDLE_ALLOCATOR),
+                )
+            }
+            AssetServerSettings::Layered(packfile_settings) => {
+                let stack = Self::open_layers(packfile_settings)?;
+                packfiles = Some(stack.clone());
+                Loader::new_with_handle_allocator(
+                    Box::new(PackfileIO::new(stack)),
                     Arc::new(&HANDLE_ALLOCATOR),
                 )
             }
@@ -149,11 +238,179 @@ impl AssetServer {
             asset_handlers: Default::default(),
             loaders: Default::default(),
             loader,
+            packfiles,
+            asset_dir,
+            daemon,
+            indirection: Default::default(),
             ref_op_tx: tx,
             ref_op_rx: rx,
         })
     }
 
+    /// The directory the asset daemon imports from, which importers resolve the paths of other files
+    /// against. `None` when assets are loaded from packfiles.
+    pub fn asset_dir(&self) -> Option<&Path> {
+        self.asset_dir.as_deref()
+    }
+
+    /// Registers an importer for files with the extension, replacing any importer already registered for it.
+    ///
+    /// The asset daemon chooses importers by the last extension of a file, so `ext` can't contain a `.`:
+    /// register `"item"` for `sword.item`, not `"item.ron"` for `sword.item.ron`.
+    ///
+    /// Importers can only be added while the app is being built; the asset daemon starts on the first update.
+    pub fn add_importer<I: BoxedImporter + 'static>(
+        &mut self,
+        importer: I,
+        ext: &str,
+    ) -> Result<(), AssetServerError> {
+        let ext = ext.trim_start_matches('.').to_lowercase();
+        if ext.contains('.') {
+            return Err(AssetServerError::InvalidExtension(ext));
+        }
+        match &mut self.daemon {
+            DaemonState::Building { importers, .. } => {
+                importers.retain(|(registered, _)| *registered != ext);
+                importers.push((ext, Box::new(importer)));
+            }
+            DaemonState::Running => {
+                warn!(
+                    "importer for {:?} added after the asset daemon started, it will not be used",
+                    ext
+                );
+            }
+            DaemonState::Disabled => {}
+        }
+        Ok(())
+    }
+
+    /// Runs the build pipelines of a profile on every asset the daemon imports. Processed artifacts
+    /// are cached in `.pipeline_cache`, and assets are reimported when the profile changes between runs.
+    ///
+    /// Like importers, the profile can only be set while the app is being built.
+    pub fn set_build_profile(&mut self, pipelines: BuildPipelines, profile: &str) {
+        match &mut self.daemon {
+            DaemonState::Building { pipeline, .. } => {
+                *pipeline = Some(PipelineRunner::new(
+                    Arc::new(pipelines),
+                    profile.to_string(),
+                    Some(PathBuf::from(".pipeline_cache")),
+                ));
+            }
+            DaemonState::Running => {
+                warn!(
+                    "build profile {:?} set after the asset daemon started, it will not be used",
+                    profile
+                );
+            }
+            DaemonState::Disabled => {}
+        }
+    }
+
+    #[cfg(feature = "assets-daemon")]
+    fn start_daemon(&mut self) {
+        if let DaemonState::Building {
+            asset_dir,
+            mut importers,
+            pipeline,
+        } = std::mem::replace(&mut self.daemon, DaemonState::Running)
+        {
+            if let Some(runner) = pipeline {
+                importers = importers
+                    .into_iter()
+                    .map(|(ext, importer)| {
+                        let importer: Box<dyn BoxedImporter> =
+                            Box::new(PipelineImporter::new(importer, runner.clone()));
+                        (ext, importer)
+                    })
+                    .collect();
+            }
+            thread::spawn(move || {
+                let (exts, importers): (Vec<String>, Vec<_>) = importers.into_iter().unzip();
+                atelier_daemon::AssetDaemon::default()
+                    .with_importers_boxed(exts.iter().map(String::as_str).zip(importers))
+                    .with_db_path(".assets_db")
+                    .with_address("127.0.0.1:9999".parse().unwrap())
+                    .with_asset_dirs(vec![asset_dir])
+                    .run();
+            });
+        }
+    }
+
+    #[cfg(not(feature = "assets-daemon"))]
+    fn start_daemon(&mut self) {}
+
+    fn open_layers(
+        settings: &PackfileSettings,
+    ) -> Result<Arc<RwLock<PackfileStack>>, AssetServerError> {
+        let mut stack = PackfileStack::with_key(settings.key.clone());
+        for path in &settings.layers {
+            info!("packfile layer: {:?}", path);
+            let packfile = Packfile::open(path)?;
+            if settings.skip_verification {
+                stack.push(path.clone(), packfile);
+            } else {
+                stack.push_verified(path.clone(), packfile)?;
+            }
+        }
+        Ok(Arc::new(RwLock::new(stack)))
+    }
+
+    fn packfiles(&self) -> Result<&RwLock<PackfileStack>, AssetServerError> {
+        self.packfiles
+            .as_deref()
+            .ok_or(AssetServerError::NotLayered)
+    }
+
+    /// The names of the packfile layers, from bottom to top, and whether each is enabled.
+    pub fn layers(&self) -> Vec<(String, bool)> {
+        self.packfiles
+            .as_ref()
+            .map(|packfiles| {
+                packfiles
+                    .read()
+                    .layers()
+                    .iter()
+                    .map(|layer| (layer.name.clone(), layer.enabled))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Opens a packfile, checks every artifact against its checksum, and adds it on top of the layer
+    /// stack. Assets it contains are reloaded.
+    pub fn push_layer<P: AsRef<Path>>(&self, path: P) -> Result<(), AssetServerError> {
+        let path = path.as_ref();
+        let packfile = Packfile::open(path)?;
+        self.packfiles()?
+            .write()
+            .push_verified(path.to_string_lossy(), packfile)?;
+        Ok(())
+    }
+
+    /// Enables or disables a packfile layer. Assets it contains are reloaded from whichever layer now provides them.
+    pub fn set_layer_enabled(&self, name: &str, enabled: bool) -> Result<(), AssetServerError> {
+        if self.packfiles()?.write().set_enabled(name, enabled) {
+            Ok(())
+        } else {
+            Err(AssetServerError::MissingLayer(name.to_string()))
+        }
+    }
+
+    /// Returns the name of the packfile layer the asset behind `handle` is served from.
+    pub fn asset_layer<H: Into<LoadHandle>>(&self, handle: H) -> Option<String> {
+        let mut handle = handle.into();
+        if handle.is_indirect() {
+            handle = self.loader.indirection_table().resolve(handle)?;
+        }
+        let id = self.loader.get_load_info(handle)?.asset_id;
+        self.packfiles
+            .as_ref()?
+            .read()
+            .layer_of(&id)
+            .map(|layer| layer.name.clone())
+    }
+
     pub(crate) fn ref_op_tx(&self) -> Sender<RefOp> {
         self.ref_op_tx.clone()
     }
@@ -163,6 +420,12 @@ impl AssetServer {
         Handle::<T>::new(self.ref_op_tx(), id).into()
     }
 
+    /// Loads an asset by its UUID, e.g. one referenced by another asset
+    pub fn load_by_id<T: Resource>(&self, id: AssetUuid) -> Handle<T> {
+        let handle = self.loader.add_ref(id);
+        Handle::<T>::new(self.ref_op_tx(), handle).into()
+    }
+
     pub fn get_handle_untyped<I: Into<LoadHandle>>(&self, id: I) -> GenericHandle {
         let id: LoadHandle = id.into();
         GenericHandle::new(self.ref_op_tx(), id)
@@ -172,16 +435,120 @@ impl AssetServer {
         unimplemented!("Blocked by https://github.com/amethyst/atelier-assets/issues/77, but why do you want this? Could you please open an issue describing your usecase, thanks.");
     }
 
+    /// Loads the asset at a path. For files several assets are imported from, this is the file's main
+    /// asset, which importers produce first: e.g. the [Model](crate::mesh::Model) of a glTF file, or the
+    /// full image of a sprite sheet.
     pub fn load<T: Resource, P: ToString>(&self, path: P) -> Handle<T> {
         self.load_untyped(IndirectIdentifier::Path(path.to_string()))
             .into()
     }
 
+    /// Loads the first asset of type `T` imported from a path, e.g. the [SpriteSheet](crate::sprite_sheet::SpriteSheet)
+    /// of an image divided into frames
+    pub fn load_typed<T: Resource + TypeUuid, P: ToString>(&self, path: P) -> Handle<T> {
+        self.load_untyped(IndirectIdentifier::PathWithType(
+            path.to_string(),
+            AssetTypeId(T::UUID),
+        ))
+        .into()
+    }
+
+    /// Loads the asset at a path, or the variant of it preferred by the [VariantResolver]s if there is one,
+    /// e.g. `strings.fr.loc` for `strings.loc` when the locale is `fr`
     pub fn load_untyped<P: Into<IndirectIdentifier>>(&self, path: P) -> GenericHandle {
-        let handle = self.loader.add_ref_indirect(path.into());
+        let id = path.into();
+        let path = id.path().to_string();
+        let handle = self.loader.add_ref_indirect(id);
+        let unprobed = self.indirection.lock().request(&path, handle);
+        self.load_variants(unprobed);
         atelier_loader::handle::GenericHandle::new(self.ref_op_tx(), handle)
     }
 
+    fn load_variants(&self, paths: Vec<String>) {
+        for path in paths {
+            let handle = self
+                .loader
+                .add_ref_indirect(IndirectIdentifier::Path(path.clone()));
+            self.indirection.lock().add_probe(path, handle);
+        }
+    }
+
+    /// Adds a resolver under a name, or replaces the one already registered under it. Resolvers added
+    /// earlier take priority; the locale resolver is registered first, as `"locale"`.
+    ///
+    /// Handles to paths with a newly preferred variant are re-pointed at it once it is loaded, which
+    /// sends an [AssetEvent::Modified](crate::AssetEvent).
+    pub fn set_resolver<R: VariantResolver>(&self, name: &str, resolver: R) {
+        self.update_resolvers(|indirection| indirection.set_resolver(name, Box::new(resolver)));
+    }
+
+    /// Removes the resolver registered under a name. Returns whether there was one.
+    pub fn remove_resolver(&self, name: &str) -> bool {
+        let mut removed = false;
+        self.update_resolvers(|indirection| removed = indirection.remove_resolver(name));
+        removed
+    }
+
+    fn update_resolvers(&self, update: impl FnOnce(&mut IndirectionState)) {
+        let (unprobed, unused) = {
+            let mut indirection = self.indirection.lock();
+            update(&mut indirection);
+            (indirection.all_unprobed(), indirection.take_unused_probes())
+        };
+        self.load_variants(unprobed);
+        for probe in unused {
+            self.loader.remove_ref(probe);
+        }
+    }
+
+    /// Applies reference count changes from handles that were cloned or dropped. Once every handle
+    /// to a path is dropped, the variants loaded on its behalf are released too.
+    fn process_ref_ops(&self) {
+        for op in self.ref_op_rx.try_iter() {
+            match op {
+                RefOp::Decrease(handle) => {
+                    self.loader.remove_ref(handle);
+                    let unused = self.indirection.lock().remove_ref(handle);
+                    for probe in unused {
+                        self.loader.remove_ref(probe);
+                    }
+                }
+                RefOp::Increase(handle) => {
+                    self.loader.add_ref_handle(handle);
+                    self.indirection.lock().add_ref(handle);
+                }
+                RefOp::IncreaseUuid(uuid) => {
+                    self.loader.add_ref(uuid);
+                }
+            }
+        }
+    }
+
+    pub fn locale(&self) -> String {
+        self.indirection.lock().locale.locale.clone()
+    }
+
+    /// Switches the locale assets are loaded in. Handles to localized assets are re-pointed at the
+    /// new locale's variant once it is loaded, which sends an [AssetEvent::Modified](crate::AssetEvent).
+    pub fn set_locale<S: Into<String>>(&self, locale: S) {
+        let locale = locale.into();
+        self.update_locale(|resolver| resolver.locale = locale);
+    }
+
+    /// Sets the locale used when an asset has no variant for the current locale. Defaults to `en`.
+    pub fn set_default_locale<S: Into<String>>(&self, locale: S) {
+        let locale = locale.into();
+        self.update_locale(|resolver| resolver.default_locale = locale);
+    }
+
+    fn update_locale(&self, update: impl FnOnce(&mut LocaleResolver)) {
+        self.update_resolvers(|indirection| {
+            update(&mut indirection.locale);
+            let resolver = Box::new(indirection.locale.clone());
+            indirection.set_resolver(LOCALE_RESOLVER, resolver);
+        });
+    }
+
     pub fn load_folder<P: AsRef<Path>>(
         &self,
         path: P,
@@ -193,19 +560,62 @@ impl AssetServer {
         let mut asset_server = resources
             .get_mut::<Self>()
             .expect("AssetServer does not exist. Consider adding it as a resource.");
+        asset_server.start_daemon();
         let asset_type_registry = resources
             .get::<AssetTypeRegistry>()
             .expect("AssetTypeRegistry does not exist. Consider adding it as a resource.");
-        let resolver = AssetStorageResolver(&*asset_type_registry, resources);
+        let asset_server = &mut *asset_server;
+        asset_server.process_ref_ops();
+        let packfiles = asset_server.packfiles.clone();
+        let resolver = AssetStorageResolver(
+            &*asset_type_registry,
+            resources,
+            packfiles.as_deref(),
+            &asset_server.indirection,
+        );
         asset_server
             .loader
-            .process(&resolver, &DefaultIndirectionResolver)
+            .process(
+                &resolver,
+                &VariantIndirectionResolver(&asset_server.indirection),
+            )
             //TODO: Should this panic?
             .unwrap();
+        asset_server.update_indirection(&asset_type_registry, resources);
+    }
+
+    /// Re-resolves paths whose preferred variant changed, and sends Modified events for handles
+    /// that now point at a different asset
+    fn update_indirection(&self, asset_type_registry: &AssetTypeRegistry, resources: &Resources) {
+        let (stale, repointed) = {
+            let mut indirection = self.indirection.lock();
+            (
+                indirection.take_stale_paths(),
+                indirection.take_repointed(&self.loader.indirection_table()),
+            )
+        };
+        if !stale.is_empty() {
+            self.loader.invalidate_paths(&stale);
+        }
+        for (handle, asset_type_id) in repointed {
+            if let Some(registration) = asset_type_registry.registrations.get(&asset_type_id) {
+                (registration.get_assets_storage_fn)(
+                    resources,
+                    &mut |storage: &dyn ArtifactStorage| {
+                        storage.repointed(handle);
+                    },
+                );
+            }
+        }
     }
 }
 
-struct AssetStorageResolver<'a, 'b>(&'a AssetTypeRegistry, &'b Resources);
+struct AssetStorageResolver<'a, 'b>(
+    &'a AssetTypeRegistry,
+    &'b Resources,
+    Option<&'a RwLock<PackfileStack>>,
+    &'a Mutex<IndirectionState>,
+);
 
 impl<'a, 'b> atelier_loader::storage::AssetStorage for AssetStorageResolver<'a, 'b> {
     fn update_asset(
@@ -218,23 +628,38 @@ impl<'a, 'b> atelier_loader::storage::AssetStorage for AssetStorageResolver<'a,
         version: u32,
     ) -> Result<(), Box<dyn Error + Send + 'static>> {
         if let Some(registration) = self.0.registrations.get(asset_type_id) {
+            let bytes = match self.2 {
+                // Artifacts from a mapped packfile are borrowed from the mapping rather than sent through the loader
+                Some(packfiles) => {
+                    let artifact = loader_info
+                        .get_asset_id(load_handle)
+                        .and_then(|id| packfiles.read().verified_artifact(&id));
+                    match artifact {
+                        Some(Ok(bytes)) => bytes,
+                        Some(Err(err)) => {
+                            error!("{}", err);
+                            return Err(Box::new(AssetServerError::from(err)));
+                        }
+                        None => {
+                            return Err(Box::new(AssetServerError::MissingArtifact(load_handle)))
+                        }
+                    }
+                }
+                None => ArtifactBytes::Owned(data),
+            };
             let mut result = None;
             let result_ref = &mut result;
             let mut load_op_arg = Some(load_op);
-            (registration.get_assets_storage_fn)(
-                self.1,
-                &mut |storage: &dyn atelier_loader::storage::AssetStorage| {
-                    *result_ref = Some(storage.update_asset(
-                        loader_info,
-                        asset_type_id,
-                        //FIXME: This seems like a bad clone
-                        data.clone(),
-                        load_handle,
-                        load_op_arg.take().unwrap(),
-                        version,
-                    ));
-                },
-            );
+            let mut bytes_arg = Some(bytes);
+            (registration.get_assets_storage_fn)(self.1, &mut |storage: &dyn ArtifactStorage| {
+                *result_ref = Some(storage.update_asset(
+                    loader_info,
+                    bytes_arg.take().unwrap(),
+                    load_handle,
+                    load_op_arg.take().unwrap(),
+                    version,
+                ));
+            });
             result.unwrap()
         } else {
             error!(
@@ -253,12 +678,10 @@ impl<'a, 'b> atelier_loader::storage::AssetStorage for AssetStorageResolver<'a,
         version: u32,
     ) {
         if let Some(registration) = self.0.registrations.get(asset_type_id) {
-            (registration.get_assets_storage_fn)(
-                self.1,
-                &mut |storage: &dyn atelier_loader::storage::AssetStorage| {
-                    storage.commit_asset_version(asset_type_id, load_handle, version);
-                },
-            );
+            (registration.get_assets_storage_fn)(self.1, &mut |storage: &dyn ArtifactStorage| {
+                storage.commit_asset_version(load_handle, version);
+            });
+            self.3.lock().committed(load_handle, *asset_type_id);
         } else {
             error!(
                 "Loaded asset type ID {:?} but it was not registered",
@@ -273,12 +696,10 @@ impl<'a, 'b> atelier_loader::storage::AssetStorage for AssetStorageResolver<'a,
         version: u32,
     ) {
         if let Some(registration) = self.0.registrations.get(asset_type_id) {
-            (registration.get_assets_storage_fn)(
-                self.1,
-                &mut |storage: &dyn atelier_loader::storage::AssetStorage| {
-                    storage.free(asset_type_id, load_handle, version);
-                },
-            );
+            (registration.get_assets_storage_fn)(self.1, &mut |storage: &dyn ArtifactStorage| {
+                storage.free(load_handle, version);
+            });
+            self.3.lock().freed(load_handle);
         } else {
             error!(
                 "Loaded asset type ID {:?} but it was not registered",
@@ -287,3 +708,90 @@ impl<'a, 'b> atelier_loader::storage::AssetStorage for AssetStorageResolver<'a,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packfile::{PackfileWriter, HEADER_LEN};
+    use atelier_core::AssetMetadata;
+
+    /// Writes a packfile with one artifact, corrupting the artifact's first byte if `corrupt` is set
+    fn write_pack(corrupt: bool) -> PathBuf {
+        let path = env::temp_dir().join(format!("bevy_atelier-{}.pack", uuid::Uuid::new_v4()));
+        let mut writer = PackfileWriter::new(fs::File::create(&path).unwrap()).unwrap();
+        let metadata = AssetMetadata {
+            id: AssetUuid([1; 16]),
+            search_tags: vec![],
+            build_pipeline: None,
+            artifact: None,
+        };
+        writer
+            .add_asset("asset.bin", metadata, b"artifact bytes")
+            .unwrap();
+        writer.finish().unwrap();
+        if corrupt {
+            let mut bytes = fs::read(&path).unwrap();
+            bytes[HEADER_LEN] ^= 0xff;
+            fs::write(&path, bytes).unwrap();
+        }
+        path
+    }
+
+    fn settings(path: &Path, skip_verification: bool) -> PackfileSettings {
+        PackfileSettings {
+            layers: vec![path.to_string_lossy().into_owned()],
+            key: None,
+            skip_verification,
+        }
+    }
+
+    #[test]
+    fn opens_intact_layers() {
+        let path = write_pack(false);
+        let stack = AssetServer::open_layers(&settings(&path, false)).unwrap();
+        assert!(stack.read().layers()[0].verified);
+        assert!(matches!(
+            stack.read().verified_artifact(&AssetUuid([1; 16])),
+            Some(Ok(_))
+        ));
+        drop(stack);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_corrupt_layers_on_open() {
+        let path = write_pack(true);
+        let opened = AssetServer::open_layers(&settings(&path, false));
+        assert!(matches!(
+            opened,
+            Err(AssetServerError::Packfile(
+                PackfileError::ArtifactChecksumMismatch { .. }
+            ))
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn packfile_settings_reject_corrupt_packs() {
+        let path = write_pack(true);
+        let settings = AssetServerSettings::Packfile(path.to_string_lossy().into_owned());
+        assert!(AssetServer::new(&settings).is_err());
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, b"not a packfile").unwrap();
+        assert!(AssetServer::new(&settings).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_layers_open_without_verification() {
+        let path = write_pack(true);
+        let stack = AssetServer::open_layers(&settings(&path, true)).unwrap();
+        assert!(!stack.read().layers()[0].verified);
+        assert!(matches!(
+            stack.read().verified_artifact(&AssetUuid([1; 16])),
+            Some(Err(PackfileError::ArtifactChecksumMismatch { .. }))
+        ));
+        drop(stack);
+        fs::remove_file(path).unwrap();
+    }
+}
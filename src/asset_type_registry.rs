@@ -1,29 +1,70 @@
-use crate::{AssetTypeId, Assets, AssetsRefCell};
+use crate::{
+    deserialize_reflect, ArtifactBytes, ArtifactStorage, AssetTypeId, Assets, AssetsRefCell,
+    FromArtifact,
+};
 use bevy_ecs::{Resource, Resources};
+use bevy_reflect::{Reflect, TypeRegistryArc};
 use serde::de::DeserializeOwned;
-use std::cell::RefCell;
 use std::collections::HashMap;
 use type_uuid::TypeUuid;
 
 pub(crate) struct AssetRegistration {
     pub ty: AssetTypeId,
-    pub get_assets_storage_fn:
-        fn(&Resources, &mut dyn FnMut(&dyn atelier_loader::storage::AssetStorage)),
+    pub get_assets_storage_fn: fn(&Resources, &mut dyn FnMut(&dyn ArtifactStorage)),
     // component_add_fn: fn(&mut World, resources: &Resources, Entity, &dyn Property),
     // component_apply_fn: fn(&mut World, Entity, &dyn Property),
     // component_properties_fn: fn(&Archetype, usize) -> &dyn Properties,
 }
 
+fn with_assets_storage<T: Resource>(
+    resources: &Resources,
+    cb: &mut dyn FnMut(&dyn ArtifactStorage),
+    deserialize_fn: &dyn Fn(ArtifactBytes) -> Result<T, anyhow::Error>,
+) {
+    let mut asset_storage = resources
+        .get_mut::<Assets<T>>()
+        .expect("Asset storage not found");
+    let asset_storage_cell = AssetsRefCell::new(&mut *asset_storage, deserialize_fn);
+    cb(&asset_storage_cell)
+}
+
+fn deserialize_bincode<T: DeserializeOwned>(bytes: ArtifactBytes) -> Result<T, anyhow::Error> {
+    Ok(bincode::deserialize::<T>(&bytes)?)
+}
+
 impl AssetRegistration {
+    /// Registers an asset type whose artifacts are bincode-serialized with serde
     pub fn of<T: TypeUuid + Resource + DeserializeOwned>() -> Self {
         Self {
             ty: AssetTypeId(<T as TypeUuid>::UUID),
             get_assets_storage_fn: |resources, cb| {
-                let mut asset_storage = resources
-                    .get_mut::<Assets<T>>()
-                    .expect("Asset storage not found");
-                let asset_storage_cell = AssetsRefCell(RefCell::new(&mut *asset_storage));
-                cb(&asset_storage_cell)
+                with_assets_storage::<T>(resources, cb, &deserialize_bincode::<T>)
+            },
+        }
+    }
+
+    /// Registers an asset type that is built from its raw artifact bytes
+    pub fn of_artifact<T: TypeUuid + Resource + FromArtifact>() -> Self {
+        Self {
+            ty: AssetTypeId(<T as TypeUuid>::UUID),
+            get_assets_storage_fn: |resources, cb| {
+                with_assets_storage::<T>(resources, cb, &T::from_artifact)
+            },
+        }
+    }
+
+    /// Registers an asset type whose artifacts are reflect-serialized RON, read through the app's type registry
+    pub fn of_reflect<T: TypeUuid + Resource + Reflect + Default>() -> Self {
+        Self {
+            ty: AssetTypeId(<T as TypeUuid>::UUID),
+            get_assets_storage_fn: |resources, cb| {
+                let registry: TypeRegistryArc = (*resources
+                    .get::<TypeRegistryArc>()
+                    .expect("TypeRegistryArc does not exist. Consider adding it as a resource."))
+                .clone();
+                with_assets_storage::<T>(resources, cb, &|bytes| {
+                    deserialize_reflect::<T>(&registry, bytes)
+                })
             },
         }
     }
@@ -41,4 +82,18 @@ impl AssetTypeRegistry {
             AssetRegistration::of::<T>(),
         );
     }
+
+    pub fn register_artifact<T: Resource + TypeUuid + FromArtifact>(&mut self) {
+        self.registrations.insert(
+            AssetTypeId(<T as TypeUuid>::UUID),
+            AssetRegistration::of_artifact::<T>(),
+        );
+    }
+
+    pub fn register_reflect<T: Resource + TypeUuid + Reflect + Default>(&mut self) {
+        self.registrations.insert(
+            AssetTypeId(<T as TypeUuid>::UUID),
+            AssetRegistration::of_reflect::<T>(),
+        );
+    }
 }
@@ -1,17 +1,21 @@
 use crate::{
-    update_asset_storage_system, AssetChannel, AssetLoader, AssetServer, AssetTypeRegistry,
-    ChannelAssetHandler, HANDLE_ALLOCATOR,
+    update_asset_storage_system, ArtifactBytes, AssetChannel, AssetLoader, AssetServer,
+    AssetServerError, AssetTypeRegistry, ChannelAssetHandler, FromArtifact, ImporterExtensions,
+    HANDLE_ALLOCATOR,
 };
 use atelier_importer::BoxedImporter;
 use atelier_loader::{
-    handle::{AssetHandle, GenericHandle, Handle, RefOp},
-    storage::{IndirectionTable, LoadHandle, HandleAllocator, IndirectIdentifier},
     crossbeam_channel::{unbounded, Receiver, Sender},
+    handle::{AssetHandle, GenericHandle, Handle, RefOp},
+    storage::{
+        AssetLoadOp, HandleAllocator, IndirectIdentifier, IndirectionTable, LoadHandle,
+        LoaderInfoProvider,
+    },
 };
 use bevy_app::{prelude::Events, AppBuilder};
 use bevy_ecs::{FromResources, IntoSystem, ResMut, Resource, Resources};
 use bevy_log::*;
-use bevy_reflect::prelude::RegisterTypeBuilder;
+use bevy_reflect::{prelude::RegisterTypeBuilder, GetTypeRegistration, Reflect};
 use serde::de::DeserializeOwned;
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -205,9 +209,36 @@ pub trait AddAsset {
     fn add_asset<T>(&mut self) -> &mut Self
     where
         T: Resource + TypeUuid + DeserializeOwned;
+    fn add_artifact_asset<T>(&mut self) -> &mut Self
+    where
+        T: Resource + TypeUuid + FromArtifact;
+    /// Adds an asset type that only derives `Reflect`. Its artifacts are stored in the reflect format
+    /// and applied to `T::default()` on load. Import it with a [ReflectImporter](crate::ReflectImporter),
+    /// which rejects files that don't set every field, so no field keeps its default.
+    fn add_reflect_asset<T>(&mut self) -> &mut Self
+    where
+        T: Resource + TypeUuid + Reflect + Default + GetTypeRegistration;
+    /// Registers an importer for files with the extension, e.g. `"item"` for `sword.item`.
+    ///
+    /// Panics if `ext` contains a `.`, since the asset daemon only matches the last extension of a file.
     fn add_importer<TImporter, EXT: AsRef<str>>(&mut self, ext: EXT) -> &mut Self
     where
-        TImporter: BoxedImporter + TypeUuid + FromResources;
+        TImporter: BoxedImporter + TypeUuid + FromResources + 'static;
+    /// Registers an importer for each of the extensions it declares, e.g. one made with
+    /// [derive(Importer)](derive@crate::Importer)
+    fn add_importer_with_extensions<TImporter>(&mut self) -> &mut Self
+    where
+        TImporter: BoxedImporter + TypeUuid + FromResources + ImporterExtensions + 'static;
+}
+
+fn init_asset_storage<T: Resource>(app: &mut AppBuilder) -> &mut AppBuilder {
+    app.init_resource::<Assets<T>>()
+        .register_type::<Handle<T>>()
+        .add_system_to_stage(
+            super::stage::ASSET_EVENTS,
+            Assets::<T>::asset_event_system.system(),
+        )
+        .add_event::<AssetEvent<T>>()
 }
 
 impl AddAsset for AppBuilder {
@@ -222,84 +253,137 @@ impl AddAsset for AppBuilder {
                 .expect("AssetTypeRegistry does not exist. Consider adding it as a resource.");
             asset_type_registry.register::<T>();
         }
-        self.init_resource::<Assets<T>>()
-            .register_type::<Handle<T>>()
-            .add_system_to_stage(
-                super::stage::ASSET_EVENTS,
-                Assets::<T>::asset_event_system.system(),
-            )
-            .add_event::<AssetEvent<T>>()
+        init_asset_storage::<T>(self)
+    }
+
+    fn add_artifact_asset<T>(&mut self) -> &mut Self
+    where
+        T: Resource + TypeUuid + FromArtifact,
+    {
+        {
+            let mut asset_type_registry = self
+                .resources()
+                .get_mut::<AssetTypeRegistry>()
+                .expect("AssetTypeRegistry does not exist. Consider adding it as a resource.");
+            asset_type_registry.register_artifact::<T>();
+        }
+        init_asset_storage::<T>(self)
+    }
+
+    fn add_reflect_asset<T>(&mut self) -> &mut Self
+    where
+        T: Resource + TypeUuid + Reflect + Default + GetTypeRegistration,
+    {
+        {
+            let mut asset_type_registry = self
+                .resources()
+                .get_mut::<AssetTypeRegistry>()
+                .expect("AssetTypeRegistry does not exist. Consider adding it as a resource.");
+            asset_type_registry.register_reflect::<T>();
+        }
+        self.register_type::<T>();
+        init_asset_storage::<T>(self)
     }
 
     fn add_importer<TImporter, EXT: AsRef<str>>(&mut self, ext: EXT) -> &mut Self
     where
-        TImporter: BoxedImporter + TypeUuid + FromResources,
+        TImporter: BoxedImporter + TypeUuid + FromResources + 'static,
     {
-        //TODO
-        /*
         {
+            let importer = <TImporter as FromResources>::from_resources(self.resources());
             let mut asset_server = self
                 .resources()
                 .get_mut::<AssetServer>()
                 .expect("AssetServer does not exist. Consider adding it as a resource.");
-            asset_server.add_importer(
-                <TImporter as FromResources>::from_resources(self.resources()),
-                ext,
-            );
+            if let Err(err) = asset_server.add_importer(importer, ext.as_ref()) {
+                panic!("{}", err);
+            }
+        }
+        self
+    }
+
+    fn add_importer_with_extensions<TImporter>(&mut self) -> &mut Self
+    where
+        TImporter: BoxedImporter + TypeUuid + FromResources + ImporterExtensions + 'static,
+    {
+        for ext in TImporter::EXTENSIONS {
+            self.add_importer::<TImporter, _>(ext);
         }
-        */
         self
     }
 }
 
-pub(crate) struct AssetsRefCell<'a, T: Resource>(pub RefCell<&'a mut Assets<T>>);
+/// Storage for the artifacts of a single asset type, fed by [AssetServer::process_system]
+pub(crate) trait ArtifactStorage {
+    fn update_asset(
+        &self,
+        loader_info: &dyn LoaderInfoProvider,
+        bytes: ArtifactBytes,
+        load_handle: LoadHandle,
+        load_op: AssetLoadOp,
+        version: u32,
+    ) -> Result<(), Box<dyn Error + Send + 'static>>;
+    fn commit_asset_version(&self, load_handle: LoadHandle, version: u32);
+    fn free(&self, load_handle: LoadHandle, version: u32);
+    /// Called when an indirect handle starts pointing at a different asset, e.g. after a locale change
+    fn repointed(&self, load_handle: LoadHandle);
+}
+
+pub(crate) struct AssetsRefCell<'a, T: Resource> {
+    assets: RefCell<&'a mut Assets<T>>,
+    deserialize_fn: &'a dyn Fn(ArtifactBytes) -> Result<T, anyhow::Error>,
+}
 
-impl<'a, T: Resource + DeserializeOwned> atelier_loader::storage::AssetStorage
-    for AssetsRefCell<'a, T>
-{
+impl<'a, T: Resource> AssetsRefCell<'a, T> {
+    pub fn new(
+        assets: &'a mut Assets<T>,
+        deserialize_fn: &'a dyn Fn(ArtifactBytes) -> Result<T, anyhow::Error>,
+    ) -> Self {
+        AssetsRefCell {
+            assets: RefCell::new(assets),
+            deserialize_fn,
+        }
+    }
+}
+
+impl<'a, T: Resource> ArtifactStorage for AssetsRefCell<'a, T> {
     fn update_asset(
         &self,
-        loader_info: &dyn atelier_loader::storage::LoaderInfoProvider,
-        asset_type_id: &atelier_core::AssetTypeId,
-        data: Vec<u8>,
-        load_handle: atelier_loader::storage::LoadHandle,
-        load_op: atelier_loader::storage::AssetLoadOp,
+        _loader_info: &dyn LoaderInfoProvider,
+        bytes: ArtifactBytes,
+        load_handle: LoadHandle,
+        load_op: AssetLoadOp,
         version: u32,
     ) -> Result<(), Box<dyn Error + Send + 'static>> {
-        let mut assets = self.0.borrow_mut();
-        assets.uncommitted.insert(
-            load_handle,
-            AssetVersion {
-                asset: bincode::deserialize::<T>(&data).expect("failed to deserialize asset"),
-                version,
-            },
-        );
-        info!("{} bytes loaded for {:?}", data.len(), load_handle);
+        let len = bytes.len();
+        let asset = match (self.deserialize_fn)(bytes) {
+            Ok(asset) => asset,
+            Err(err) => {
+                error!("Failed to deserialize asset {:?}: {:?}", load_handle, err);
+                load_op.error(AssetServerError::AssetDeserializeError(load_handle));
+                return Ok(());
+            }
+        };
+        let mut assets = self.assets.borrow_mut();
+        assets
+            .uncommitted
+            .insert(load_handle, AssetVersion { asset, version });
+        info!("{} bytes loaded for {:?}", len, load_handle);
         // The loading process could be async, in which case you can delay
         // calling `load_op.complete` as it should only be done when the asset is usable.
         load_op.complete();
         Ok(())
     }
-    fn commit_asset_version(
-        &self,
-        asset_type: &atelier_core::AssetTypeId,
-        load_handle: atelier_loader::LoadHandle,
-        version: u32,
-    ) {
-        let mut assets = self.0.borrow_mut();
+    fn commit_asset_version(&self, load_handle: LoadHandle, _version: u32) {
+        let mut assets = self.assets.borrow_mut();
         let uncommitted = assets
             .uncommitted
             .remove(&load_handle)
             .expect("asset not present when committing");
         assets.committed.insert(load_handle, uncommitted);
     }
-    fn free(
-        &self,
-        asset_type_id: &atelier_core::AssetTypeId,
-        load_handle: atelier_loader::LoadHandle,
-        version: u32,
-    ) {
-        let mut assets = self.0.borrow_mut();
+    fn free(&self, load_handle: LoadHandle, version: u32) {
+        let mut assets = self.assets.borrow_mut();
         if let Some(asset_version) = assets.uncommitted.get(&load_handle) {
             if asset_version.version == version {
                 assets.uncommitted.remove(&load_handle);
@@ -312,4 +396,11 @@ impl<'a, T: Resource + DeserializeOwned> atelier_loader::storage::AssetStorage
         }
         info!("Free {:?}", load_handle);
     }
+    fn repointed(&self, load_handle: LoadHandle) {
+        let mut assets = self.assets.borrow_mut();
+        // The event's handle is a new strong reference, released when the event is dropped
+        let _ = assets.ref_op_tx.send(RefOp::Increase(load_handle));
+        let handle = Handle::<T>::new(assets.ref_op_tx.clone(), load_handle).into();
+        assets.events.send(AssetEvent::Modified { handle });
+    }
 }
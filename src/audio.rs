@@ -0,0 +1,311 @@
+use crate::NamedState;
+use atelier_importer::{AsyncImporter, Error, ImportOp, ImportedAsset, ImporterValue, Result};
+use futures_core::future::BoxFuture;
+use futures_io::AsyncRead;
+use futures_util::AsyncReadExt;
+use serde::{Deserialize, Serialize};
+use std::{io::Cursor, time::Duration};
+use thiserror::Error;
+use type_uuid::*;
+
+/// Interleaved PCM samples
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum AudioSamples {
+    I16(Vec<i16>),
+    F32(Vec<f32>),
+}
+
+/// The sample type of an [AudioClip]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    I16,
+    F32,
+}
+
+impl AudioSamples {
+    pub fn len(&self) -> usize {
+        match self {
+            AudioSamples::I16(samples) => samples.len(),
+            AudioSamples::F32(samples) => samples.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn format(&self) -> SampleFormat {
+        match self {
+            AudioSamples::I16(_) => SampleFormat::I16,
+            AudioSamples::F32(_) => SampleFormat::F32,
+        }
+    }
+
+    /// The samples scaled to `-1.0..=1.0`
+    pub fn to_f32(&self) -> Vec<f32> {
+        match self {
+            AudioSamples::I16(samples) => samples
+                .iter()
+                .map(|sample| *sample as f32 / i16::MAX as f32)
+                .collect(),
+            AudioSamples::F32(samples) => samples.clone(),
+        }
+    }
+
+    pub(crate) fn from_f32(samples: Vec<f32>, format: SampleFormat) -> Self {
+        match format {
+            SampleFormat::I16 => AudioSamples::I16(
+                samples
+                    .into_iter()
+                    .map(|sample| (sample.max(-1.0).min(1.0) * i16::MAX as f32).round() as i16)
+                    .collect(),
+            ),
+            SampleFormat::F32 => AudioSamples::F32(samples),
+        }
+    }
+}
+
+/// A decoded sound
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[uuid = "6b212440-04fd-4bcf-a0c8-6b8606222e23"]
+pub struct AudioClip {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: AudioSamples,
+}
+
+impl AudioClip {
+    /// Number of samples per channel
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames() as f64 / self.sample_rate.max(1) as f64)
+    }
+
+    /// Mixes all channels down to one
+    pub fn downmix_to_mono(&mut self) {
+        if self.channels <= 1 {
+            return;
+        }
+        let channels = self.channels as usize;
+        let mixed = self
+            .samples
+            .to_f32()
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+        self.samples = AudioSamples::from_f32(mixed, self.samples.format());
+        self.channels = 1;
+    }
+
+    /// Resamples the clip to `sample_rate` with linear interpolation
+    pub fn resample(&mut self, sample_rate: u32) {
+        if sample_rate == self.sample_rate || sample_rate == 0 || self.frames() == 0 {
+            return;
+        }
+        let channels = self.channels.max(1) as usize;
+        let input = self.samples.to_f32();
+        let frames = self.frames();
+        let ratio = self.sample_rate as f64 / sample_rate as f64;
+        let output_frames = ((frames as f64) / ratio).round() as usize;
+        let mut output = Vec::with_capacity(output_frames * channels);
+        for frame in 0..output_frames {
+            let position = frame as f64 * ratio;
+            let index = (position as usize).min(frames - 1);
+            let next = (index + 1).min(frames - 1);
+            let t = (position - index as f64) as f32;
+            for channel in 0..channels {
+                let a = input[index * channels + channel];
+                let b = input[next * channels + channel];
+                output.push(a + (b - a) * t);
+            }
+        }
+        self.samples = AudioSamples::from_f32(output, self.samples.format());
+        self.sample_rate = sample_rate;
+    }
+
+    /// Converts the samples to another format
+    pub fn convert(&mut self, format: SampleFormat) {
+        if self.samples.format() != format {
+            self.samples = AudioSamples::from_f32(self.samples.to_f32(), format);
+        }
+    }
+}
+
+/// Import settings for audio clips, editable in their `.meta` files
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, Default)]
+#[uuid = "bca059ef-e2df-4729-92a3-5dfcfeb667a5"]
+#[serde(default)]
+pub struct AudioImporterOptions {
+    /// Mixes all channels down to one
+    pub mono: bool,
+    /// Resamples the clip to this rate. The source file's rate is kept if `None`.
+    pub sample_rate: Option<u32>,
+    /// Converts the samples to this format. The format decoded from the source file is kept if `None`.
+    pub sample_format: Option<SampleFormat>,
+}
+
+impl AudioImporterOptions {
+    fn apply(&self, mut clip: AudioClip) -> AudioClip {
+        if self.mono {
+            clip.downmix_to_mono();
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            clip.resample(sample_rate);
+        }
+        if let Some(format) = self.sample_format {
+            clip.convert(format);
+        }
+        clip
+    }
+}
+
+/// Errors that occur while decoding audio
+#[derive(Error, Debug)]
+pub enum AudioImportError {
+    #[error("Failed to decode WAV: {0}")]
+    Wav(#[from] hound::Error),
+    #[error("Failed to decode Ogg Vorbis: {0}")]
+    Vorbis(#[from] lewton::VorbisError),
+}
+
+fn decode_wav(bytes: &[u8]) -> std::result::Result<AudioClip, AudioImportError> {
+    let mut reader = hound::WavReader::new(Cursor::new(bytes))?;
+    let spec = reader.spec();
+    let samples = match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Float, _) => AudioSamples::F32(
+            reader
+                .samples::<f32>()
+                .collect::<std::result::Result<_, _>>()?,
+        ),
+        (hound::SampleFormat::Int, 16) => AudioSamples::I16(
+            reader
+                .samples::<i16>()
+                .collect::<std::result::Result<_, _>>()?,
+        ),
+        (hound::SampleFormat::Int, 8) => AudioSamples::I16(
+            reader
+                .samples::<i8>()
+                .map(|sample| sample.map(|sample| (sample as i16) << 8))
+                .collect::<std::result::Result<_, _>>()?,
+        ),
+        // Deeper integer samples would lose precision as i16
+        (hound::SampleFormat::Int, bits) => {
+            let scale = (1i64 << (bits - 1)) as f32;
+            AudioSamples::F32(
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f32 / scale))
+                    .collect::<std::result::Result<_, _>>()?,
+            )
+        }
+    };
+    Ok(AudioClip {
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+        samples,
+    })
+}
+
+fn decode_ogg(bytes: &[u8]) -> std::result::Result<AudioClip, AudioImportError> {
+    let mut reader = lewton::inside_ogg::OggStreamReader::new(Cursor::new(bytes))?;
+    let mut samples = Vec::new();
+    while let Some(packet) = reader.read_dec_packet_itl()? {
+        samples.extend(packet);
+    }
+    Ok(AudioClip {
+        sample_rate: reader.ident_hdr.audio_sample_rate,
+        channels: reader.ident_hdr.audio_channels as u16,
+        samples: AudioSamples::I16(samples),
+    })
+}
+
+/// Imports WAV files as [AudioClip]s
+#[derive(TypeUuid, Default)]
+#[uuid = "6aef875c-cefd-4cfa-9fe9-b8847319c231"]
+pub struct WavImporter;
+
+/// Imports Ogg Vorbis files as [AudioClip]s
+#[derive(TypeUuid, Default)]
+#[uuid = "68607fda-cf2d-4a5f-a98f-d299ccf8f98c"]
+pub struct OggImporter;
+
+fn import_clip<'a>(
+    source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+    options: AudioImporterOptions,
+    state: &'a mut NamedState,
+    decode: fn(&[u8]) -> std::result::Result<AudioClip, AudioImportError>,
+) -> BoxFuture<'a, Result<ImporterValue>> {
+    Box::pin(async move {
+        let id = state.id("asset");
+        let mut bytes = Vec::new();
+        source.read_to_end(&mut bytes).await?;
+        let clip = decode(&bytes).map_err(|e| Error::Boxed(Box::new(e)))?;
+        Ok(ImporterValue {
+            assets: vec![ImportedAsset {
+                id,
+                search_tags: vec![],
+                build_deps: vec![],
+                load_deps: vec![],
+                build_pipeline: None,
+                asset_data: Box::new(options.apply(clip)),
+            }],
+        })
+    })
+}
+
+impl AsyncImporter for WavImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        1
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = AudioImporterOptions;
+
+    type State = NamedState;
+
+    /// Decodes the WAV file into an [AudioClip].
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        import_clip(source, options.clone(), state, decode_wav)
+    }
+}
+
+impl AsyncImporter for OggImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        1
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = AudioImporterOptions;
+
+    type State = NamedState;
+
+    /// Decodes the Ogg Vorbis file into an [AudioClip].
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        import_clip(source, options.clone(), state, decode_ogg)
+    }
+}
@@ -0,0 +1,96 @@
+//! Builds a packfile from an asset directory, or verifies an existing one.
+//!
+//! ```text
+//! atelier_pack <asset-dir> [-o <output>] [--include <glob>]... [--exclude <glob>]...
+//!              [--tag <tag>]... [--exclude-tag <tag>]... [--compress <none|lz4|zstd[:level]>]
+//!              [--key-file <file>]
+//! atelier_pack --verify <packfile>
+//! ```
+use bevy_atelier::packfile::{
+    ArtifactCompression, Packfile, PackfileBuildError, PackfileBuilder, PackfileKey,
+};
+use std::{env, fs, fs::File, io::BufWriter, process};
+
+const USAGE: &str = "usage: atelier_pack <asset-dir> [-o <output>] [--include <glob>]... \
+                     [--exclude <glob>]... [--tag <tag>]... [--exclude-tag <tag>]... \
+                     [--compress <none|lz4|zstd[:level]>] [--key-file <file>]\n       \
+                     atelier_pack --verify <packfile>";
+
+fn usage_error(message: String) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn parse_compression(value: &str) -> ArtifactCompression {
+    let mut parts = value.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some("none"), None) => ArtifactCompression::None,
+        (Some("lz4"), None) => ArtifactCompression::Lz4,
+        (Some("zstd"), None) => ArtifactCompression::Zstd { level: 0 },
+        (Some("zstd"), Some(level)) => ArtifactCompression::Zstd {
+            level: level
+                .parse()
+                .unwrap_or_else(|_| usage_error(format!("invalid zstd level {}", level))),
+        },
+        _ => usage_error(format!("unknown compression {}", value)),
+    }
+}
+
+fn run() -> Result<(), PackfileBuildError> {
+    let mut args = env::args().skip(1);
+    let mut asset_dir = None;
+    let mut output = "assets.pack".to_string();
+    let mut builder = PackfileBuilder::default();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| usage_error(format!("missing value for {}", arg)))
+        };
+        match arg.as_str() {
+            "-o" | "--output" => output = value(),
+            "--include" => builder = builder.include(&value())?,
+            "--exclude" => builder = builder.exclude(&value())?,
+            "--tag" => builder = builder.include_tag(value()),
+            "--exclude-tag" => builder = builder.exclude_tag(value()),
+            "--compress" => builder = builder.with_compression(parse_compression(&value())),
+            "--key-file" => {
+                let path = value();
+                let key = PackfileKey::from_slice(&fs::read(&path)?).unwrap_or_else(|| {
+                    usage_error(format!("{} must contain exactly 32 bytes", path))
+                });
+                builder = builder.with_key(key);
+            }
+            "--verify" => {
+                let path = value();
+                let packfile = Packfile::open(&path)?;
+                packfile.verify_artifacts()?;
+                println!(
+                    "{} is valid: {} assets, build {}",
+                    path,
+                    packfile.entries().count(),
+                    packfile.build_id()
+                );
+                return Ok(());
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ if asset_dir.is_none() && !arg.starts_with('-') => asset_dir = Some(arg),
+            _ => usage_error(format!("unexpected argument {}", arg)),
+        }
+    }
+    let asset_dir = asset_dir.unwrap_or_else(|| usage_error("missing asset directory".to_string()));
+
+    let report = builder.build(&asset_dir, BufWriter::new(File::create(&output)?))?;
+    println!("wrote {}", output);
+    print!("{}", report);
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
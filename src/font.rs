@@ -0,0 +1,473 @@
+use crate::{
+    image::{ColorSpace, Image, PixelBuffer},
+    texture_atlas::{self, AtlasRect},
+    NamedState,
+};
+use ab_glyph::{Font as _, FontRef, InvalidFont, PxScale, ScaleFont};
+use atelier_core::{AssetRef, AssetUuid};
+use atelier_importer::{AsyncImporter, Error, ImportOp, ImportedAsset, ImporterValue, Result};
+use futures_core::future::BoxFuture;
+use futures_io::AsyncRead;
+use futures_util::AsyncReadExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+use type_uuid::*;
+
+/// Font-wide metrics in font units
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct FontMetrics {
+    pub units_per_em: f32,
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+    pub glyph_count: usize,
+}
+
+/// A TrueType or OpenType font
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[uuid = "651ad802-1c20-456b-8f4f-6f81015eb320"]
+pub struct Font {
+    /// The font file, for laying out and rasterizing text at runtime
+    pub data: Vec<u8>,
+    pub metrics: FontMetrics,
+    /// The pre-rasterized [GlyphAtlas], which is loaded along with the font
+    pub atlas: Option<AssetUuid>,
+}
+
+impl Font {
+    /// Parses the font data
+    pub fn font_ref(&self) -> std::result::Result<FontRef<'_>, InvalidFont> {
+        FontRef::try_from_slice(&self.data)
+    }
+}
+
+/// Placement of a rasterized glyph
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct GlyphInfo {
+    /// Where the glyph is in the atlas image. Empty for glyphs without an outline, such as spaces.
+    pub rect: AtlasRect,
+    /// Offset of the top left of the rect from the pen position on the baseline, in pixels
+    pub offset: [f32; 2],
+    pub advance: f32,
+}
+
+/// The glyphs rasterized at one pixel size
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GlyphSize {
+    pub size: f32,
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+    pub glyphs: HashMap<char, GlyphInfo>,
+}
+
+/// Glyphs of a [Font] rasterized into a single-channel coverage [Image]
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[uuid = "172ddad0-5d24-42d4-b90f-5fd7f3a2dbd0"]
+pub struct GlyphAtlas {
+    /// The atlas image, which is loaded along with the glyph atlas
+    pub image: AssetUuid,
+    pub width: u32,
+    pub height: u32,
+    pub sizes: Vec<GlyphSize>,
+}
+
+impl GlyphAtlas {
+    /// The glyphs rasterized at the size closest to `size`
+    pub fn nearest_size(&self, size: f32) -> Option<&GlyphSize> {
+        self.sizes.iter().min_by(|a, b| {
+            (a.size - size)
+                .abs()
+                .partial_cmp(&(b.size - size).abs())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+    }
+
+    pub fn glyph(&self, size: f32, c: char) -> Option<&GlyphInfo> {
+        self.sizes
+            .iter()
+            .find(|glyph_size| glyph_size.size == size)?
+            .glyphs
+            .get(&c)
+    }
+}
+
+/// Which glyphs to pre-rasterize, and at which sizes
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct GlyphAtlasOptions {
+    /// Inclusive ranges of characters
+    pub ranges: Vec<(char, char)>,
+    /// Pixel heights to rasterize at
+    pub sizes: Vec<f32>,
+    pub padding: u32,
+    pub max_size: u32,
+}
+
+impl Default for GlyphAtlasOptions {
+    fn default() -> Self {
+        GlyphAtlasOptions {
+            ranges: vec![(' ', '~')],
+            sizes: vec![16.0],
+            padding: 1,
+            max_size: 2048,
+        }
+    }
+}
+
+/// Import settings for fonts, editable in their `.meta` files
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, Default)]
+#[uuid = "af4acf21-31e9-4686-a356-c87dceb21306"]
+#[serde(default)]
+pub struct FontImporterOptions {
+    /// Rasterizes glyphs into a [GlyphAtlas] on import
+    pub atlas: Option<GlyphAtlasOptions>,
+}
+
+/// Errors that occur while importing a font
+#[derive(Error, Debug)]
+pub enum FontImportError {
+    #[error("Invalid font file.")]
+    InvalidFont(#[from] InvalidFont),
+    #[error("Glyphs do not fit into a {max_size}x{max_size} atlas.")]
+    AtlasTooLarge { max_size: u32 },
+}
+
+struct RasterizedGlyph {
+    size_index: usize,
+    c: char,
+    offset: [f32; 2],
+    advance: f32,
+    width: u32,
+    height: u32,
+    coverage: Vec<u8>,
+}
+
+fn rasterize(
+    font: &FontRef,
+    options: &GlyphAtlasOptions,
+) -> std::result::Result<(Image, u32, u32, Vec<GlyphSize>), FontImportError> {
+    let mut sizes = Vec::new();
+    let mut rasterized = Vec::new();
+    for (size_index, &size) in options.sizes.iter().enumerate() {
+        let scaled = font.as_scaled(PxScale::from(size));
+        sizes.push(GlyphSize {
+            size,
+            ascent: scaled.ascent(),
+            descent: scaled.descent(),
+            line_gap: scaled.line_gap(),
+            glyphs: HashMap::new(),
+        });
+        for &(first, last) in &options.ranges {
+            for c in first..=last {
+                let glyph = scaled.scaled_glyph(c);
+                let advance = scaled.h_advance(glyph.id);
+                let mut info = RasterizedGlyph {
+                    size_index,
+                    c,
+                    offset: [0.0, 0.0],
+                    advance,
+                    width: 0,
+                    height: 0,
+                    coverage: Vec::new(),
+                };
+                if let Some(outlined) = font.outline_glyph(glyph) {
+                    let bounds = outlined.px_bounds();
+                    info.offset = [bounds.min.x, bounds.min.y];
+                    info.width = bounds.width() as u32;
+                    info.height = bounds.height() as u32;
+                    let mut coverage = vec![0; (info.width * info.height) as usize];
+                    outlined.draw(|x, y, value| {
+                        if x < info.width && y < info.height {
+                            coverage[(y * info.width + x) as usize] =
+                                (value.max(0.0).min(1.0) * 255.0).round() as u8;
+                        }
+                    });
+                    info.coverage = coverage;
+                }
+                rasterized.push(info);
+            }
+        }
+    }
+
+    let glyph_sizes: Vec<_> = rasterized
+        .iter()
+        .map(|glyph| (glyph.width, glyph.height))
+        .collect();
+    let (width, height, positions) =
+        texture_atlas::pack(&glyph_sizes, options.padding, options.max_size).ok_or(
+            FontImportError::AtlasTooLarge {
+                max_size: options.max_size,
+            },
+        )?;
+    let mut pixels = vec![0; (width * height) as usize];
+    for (glyph, &(x, y)) in rasterized.into_iter().zip(&positions) {
+        for row in 0..glyph.height {
+            let src = (row * glyph.width) as usize;
+            let dst = ((y + row) * width + x) as usize;
+            pixels[dst..dst + glyph.width as usize]
+                .copy_from_slice(&glyph.coverage[src..src + glyph.width as usize]);
+        }
+        sizes[glyph.size_index].glyphs.insert(
+            glyph.c,
+            GlyphInfo {
+                rect: AtlasRect {
+                    x,
+                    y,
+                    width: glyph.width,
+                    height: glyph.height,
+                },
+                offset: glyph.offset,
+                advance: glyph.advance,
+            },
+        );
+    }
+    let mut image = Image::Luma8(PixelBuffer::new(width, height, pixels));
+    image.set_color_space(ColorSpace::Linear);
+    Ok((image, width, height, sizes))
+}
+
+/// Imports TrueType and OpenType fonts as [Font]s, optionally with a [GlyphAtlas]
+#[derive(TypeUuid, Default)]
+#[uuid = "a1434e14-6b38-4efa-ba8f-cbc78a7826f2"]
+pub struct FontImporter;
+
+impl AsyncImporter for FontImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        2
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = FontImporterOptions;
+
+    type State = NamedState;
+
+    /// Parses the font and rasterizes its glyph atlas.
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        let options = options.clone();
+        Box::pin(async move {
+            let mut data = Vec::new();
+            source.read_to_end(&mut data).await?;
+            let font = FontRef::try_from_slice(&data)
+                .map_err(|e| Error::Boxed(Box::new(FontImportError::from(e))))?;
+            let metrics = FontMetrics {
+                units_per_em: font.units_per_em().unwrap_or(1000.0),
+                ascent: font.ascent_unscaled(),
+                descent: font.descent_unscaled(),
+                line_gap: font.line_gap_unscaled(),
+                glyph_count: font.glyph_count(),
+            };
+
+            let mut assets = Vec::new();
+            let atlas = match &options.atlas {
+                Some(atlas_options) => {
+                    let (image, width, height, sizes) =
+                        rasterize(&font, atlas_options).map_err(|e| Error::Boxed(Box::new(e)))?;
+                    let image_id = state.id("atlas_image");
+                    let atlas_id = state.id("atlas");
+                    assets.push(ImportedAsset {
+                        id: image_id,
+                        search_tags: vec![],
+                        build_deps: vec![],
+                        load_deps: vec![],
+                        build_pipeline: None,
+                        asset_data: Box::new(image),
+                    });
+                    assets.push(ImportedAsset {
+                        id: atlas_id,
+                        search_tags: vec![],
+                        build_deps: vec![],
+                        load_deps: vec![AssetRef::Uuid(image_id)],
+                        build_pipeline: None,
+                        asset_data: Box::new(GlyphAtlas {
+                            image: image_id,
+                            width,
+                            height,
+                            sizes,
+                        }),
+                    });
+                    Some(atlas_id)
+                }
+                None => {
+                    state.retain_names(vec!["font"]);
+                    None
+                }
+            };
+            // A path resolves to the first asset imported from it, so the font goes first
+            assets.insert(
+                0,
+                ImportedAsset {
+                    id: state.id("font"),
+                    search_tags: vec![],
+                    build_deps: vec![],
+                    load_deps: atlas.into_iter().map(AssetRef::Uuid).collect(),
+                    build_pipeline: None,
+                    asset_data: Box::new(Font {
+                        data: data.clone(),
+                        metrics,
+                        atlas,
+                    }),
+                },
+            );
+            Ok(ImporterValue { assets })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imported_data;
+
+    fn words(values: &[i32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|&value| (value as u16).to_be_bytes().to_vec())
+            .collect()
+    }
+
+    /// A TrueType font with 1000 units per em and three glyphs: an empty `.notdef`, a 500x700 unit
+    /// square mapped to 'A', and an empty space
+    fn font_data() -> Vec<u8> {
+        let mut head = words(&[1, 0, 1, 0, 0, 0, 0x5F0F, 0x3CF5, 0, 1000]);
+        head.extend(vec![0; 16]);
+        head.extend(words(&[0, -200, 700, 800, 0, 8, 2, 0, 0]));
+        let mut hhea = words(&[1, 0, 800, -200, 0, 700, 0, 0, 600, 1, 0, 0]);
+        hhea.extend(words(&[0, 0, 0, 0, 0, 3]));
+        let maxp = words(&[0, 0x5000, 3]);
+        let hmtx = words(&[500, 0, 700, 100, 300, 0]);
+        let mut glyf = words(&[1, 100, 0, 600, 700, 3, 0]);
+        glyf.extend(&[1, 1, 1, 1]);
+        glyf.extend(words(&[100, 500, 0, -500, 0, 0, 700, 0]));
+        let loca = words(&[0, 0, 17, 17]);
+        let mut cmap = words(&[0, 1, 3, 1, 0, 12]);
+        cmap.extend(words(&[4, 40, 0, 6, 4, 1, 2]));
+        cmap.extend(words(&[
+            32, 65, 0xFFFF, 0, 32, 65, 0xFFFF, -30, -64, 1, 0, 0, 0,
+        ]));
+
+        let tables = [
+            (b"cmap", cmap),
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"loca", loca),
+            (b"maxp", maxp),
+        ];
+        let mut data = words(&[1, 0, tables.len() as i32, 64, 2, 48]);
+        let mut contents = Vec::new();
+        for (tag, table) in &tables {
+            let offset = 12 + 16 * tables.len() + contents.len();
+            data.extend_from_slice(*tag);
+            data.extend(words(&[0, 0]));
+            data.extend(words(&[(offset >> 16) as i32, offset as i32]));
+            data.extend(words(&[0, table.len() as i32]));
+            contents.extend(table);
+            while contents.len() % 4 != 0 {
+                contents.push(0);
+            }
+        }
+        data.extend(contents);
+        data
+    }
+
+    fn import(
+        options: FontImporterOptions,
+    ) -> std::result::Result<ImporterValue, atelier_importer::Error> {
+        let data = font_data();
+        let mut source = data.as_slice();
+        futures_executor::block_on(FontImporter.import(
+            &mut ImportOp::default(),
+            &mut source,
+            &options,
+            &mut NamedState::default(),
+        ))
+    }
+
+    #[test]
+    fn imports_font_metrics() {
+        let value = import(FontImporterOptions::default()).unwrap();
+        assert_eq!(value.assets.len(), 1);
+        let font: Font = imported_data(&value.assets[0]);
+        assert_eq!(
+            font.metrics,
+            FontMetrics {
+                units_per_em: 1000.0,
+                ascent: 800.0,
+                descent: -200.0,
+                line_gap: 0.0,
+                glyph_count: 3,
+            }
+        );
+        assert_eq!(font.atlas, None);
+        assert!(font.font_ref().is_ok());
+    }
+
+    #[test]
+    fn rasterizes_glyphs_at_each_size() {
+        let value = import(FontImporterOptions {
+            atlas: Some(GlyphAtlasOptions {
+                ranges: vec![(' ', ' '), ('A', 'A')],
+                sizes: vec![16.0, 32.0],
+                ..Default::default()
+            }),
+        })
+        .unwrap();
+        assert_eq!(value.assets.len(), 3);
+        let font: Font = imported_data(&value.assets[0]);
+        let image: Image = imported_data(&value.assets[1]);
+        let atlas: GlyphAtlas = imported_data(&value.assets[2]);
+        assert_eq!(font.atlas, Some(value.assets[2].id));
+        assert_eq!(atlas.image, value.assets[1].id);
+        assert_eq!(
+            value.assets[2].load_deps,
+            vec![AssetRef::Uuid(value.assets[1].id)]
+        );
+        assert_eq!((image.width(), image.height()), (atlas.width, atlas.height));
+        assert_eq!(image.format(), crate::image::PixelFormat::Luma8);
+
+        let space = atlas.glyph(16.0, ' ').unwrap();
+        assert_eq!((space.rect.width, space.rect.height), (0, 0));
+        assert!((space.advance - 4.8).abs() < 1e-3);
+
+        // The square spans 1.6..9.6 pixels horizontally and 11.2 pixels up from the baseline at 16px
+        let small = atlas.glyph(16.0, 'A').unwrap();
+        assert_eq!((small.rect.width, small.rect.height), (9, 12));
+        assert_eq!(small.offset, [1.0, -12.0]);
+        assert!((small.advance - 11.2).abs() < 1e-3);
+        let large = atlas.glyph(32.0, 'A').unwrap();
+        assert!(large.rect.width > small.rect.width);
+        assert_eq!(atlas.nearest_size(30.0).unwrap().size, 32.0);
+
+        let center = (small.rect.y + small.rect.height / 2) * atlas.width
+            + small.rect.x
+            + small.rect.width / 2;
+        assert_eq!(image.as_bytes()[center as usize], 255);
+    }
+
+    #[test]
+    fn rejects_atlases_past_the_max_size() {
+        let result = import(FontImporterOptions {
+            atlas: Some(GlyphAtlasOptions {
+                ranges: vec![('A', 'A')],
+                sizes: vec![64.0],
+                max_size: 16,
+                ..Default::default()
+            }),
+        });
+        assert!(result.is_err());
+    }
+}
@@ -1,38 +1,682 @@
-use atelier_core::AssetUuid;
+use crate::{
+    sprite_sheet::{NamedFrame, SpriteFrame, SpriteSheet, SpriteSheetLayout},
+    texture_atlas::AtlasRect,
+    NamedState,
+};
+use atelier_core::{AssetRef, AssetUuid};
 use atelier_importer::{AsyncImporter, Error, ImportOp, ImportedAsset, ImporterValue, Result};
 
 use futures_core::future::BoxFuture;
 use futures_io::AsyncRead;
 use futures_util::AsyncReadExt;
-use image2::{color, ImageBuf};
+use image::{imageops::FilterType, DynamicImage, ImageFormat};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
 use type_uuid::*;
 
-#[derive(TypeUuid, Serialize, Deserialize)]
+mod mipmap;
+
+pub use mipmap::MipFilter;
+
+/// The layout of a single pixel
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    Rgb8,
+    Rgba8,
+    Luma8,
+    LumaA8,
+    Luma16,
+    LumaA16,
+    Rgb16,
+    Rgba16,
+    Rgb32F,
+    Rgba32F,
+}
+
+impl PixelFormat {
+    pub fn channels(self) -> usize {
+        match self {
+            PixelFormat::Luma8 | PixelFormat::Luma16 => 1,
+            PixelFormat::LumaA8 | PixelFormat::LumaA16 => 2,
+            PixelFormat::Rgb8 | PixelFormat::Rgb16 | PixelFormat::Rgb32F => 3,
+            PixelFormat::Rgba8 | PixelFormat::Rgba16 | PixelFormat::Rgba32F => 4,
+        }
+    }
+
+    pub fn bytes_per_channel(self) -> usize {
+        match self {
+            PixelFormat::Rgb8 | PixelFormat::Rgba8 | PixelFormat::Luma8 | PixelFormat::LumaA8 => 1,
+            PixelFormat::Luma16
+            | PixelFormat::LumaA16
+            | PixelFormat::Rgb16
+            | PixelFormat::Rgba16 => 2,
+            PixelFormat::Rgb32F | PixelFormat::Rgba32F => 4,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> usize {
+        self.channels() * self.bytes_per_channel()
+    }
+}
+
+/// How the color channels of an image are encoded
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl Default for ColorSpace {
+    fn default() -> Self {
+        ColorSpace::Srgb
+    }
+}
+
+impl ColorSpace {
+    /// The color space images in a format are decoded as: linear for float formats such as HDR and
+    /// EXR images, sRGB for the rest
+    pub fn default_for(format: PixelFormat) -> Self {
+        match format {
+            PixelFormat::Rgb32F | PixelFormat::Rgba32F => ColorSpace::Linear,
+            _ => ColorSpace::Srgb,
+        }
+    }
+}
+
+/// Pixel data stored row by row, with [PixelFormat::channels] values per pixel
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PixelBuffer<T> {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<T>,
+    pub color_space: ColorSpace,
+    /// Mip levels below the base level, each half the size of the one before it down to 1x1
+    pub mips: Vec<Vec<T>>,
+}
+
+impl<T> PixelBuffer<T> {
+    pub fn new(width: u32, height: u32, data: Vec<T>) -> Self {
+        PixelBuffer {
+            width,
+            height,
+            data,
+            color_space: ColorSpace::default(),
+            mips: Vec::new(),
+        }
+    }
+
+    /// The pixel data of a mip level, where level 0 is the base image
+    pub fn level(&self, level: usize) -> Option<&[T]> {
+        match level {
+            0 => Some(&self.data),
+            level => self.mips.get(level - 1).map(|mip| mip.as_slice()),
+        }
+    }
+}
+
+/// Channel types that images can be processed in
+trait Channel: Copy {
+    fn premultiply(self, alpha: Self) -> Self;
+    /// The value normalized to `0.0..=1.0` for integer channels
+    fn to_f32(self) -> f32;
+    fn from_f32(value: f32) -> Self;
+}
+
+impl Channel for u8 {
+    fn premultiply(self, alpha: Self) -> Self {
+        ((self as u32 * alpha as u32 + 127) / 255) as u8
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32 / 255.0
+    }
+
+    fn from_f32(value: f32) -> Self {
+        (value.max(0.0).min(1.0) * 255.0).round() as u8
+    }
+}
+
+impl Channel for u16 {
+    fn premultiply(self, alpha: Self) -> Self {
+        ((self as u64 * alpha as u64 + 32767) / 65535) as u16
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32 / 65535.0
+    }
+
+    fn from_f32(value: f32) -> Self {
+        (value.max(0.0).min(1.0) * 65535.0).round() as u16
+    }
+}
+
+impl Channel for f32 {
+    fn premultiply(self, alpha: Self) -> Self {
+        self * alpha
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl<T> PixelBuffer<T> {
+    /// Makes mip level `level` the base level, dropping the larger ones
+    fn drop_levels(&mut self, level: usize) {
+        if level == 0 || level > self.mips.len() {
+            return;
+        }
+        let (width, height) = mipmap::level_size(self.width, self.height, level);
+        let mut mips = std::mem::take(&mut self.mips).into_iter().skip(level - 1);
+        self.data = mips.next().unwrap();
+        self.mips = mips.collect();
+        self.width = width;
+        self.height = height;
+    }
+}
+
+impl<T: Channel> PixelBuffer<T> {
+    /// Multiplies the color channels by the alpha channel, which must be the last of `channels`
+    fn premultiply_alpha(&mut self, channels: usize) {
+        for pixel in self.data.chunks_exact_mut(channels) {
+            let (alpha, color) = pixel.split_last_mut().unwrap();
+            for value in color {
+                *value = value.premultiply(*alpha);
+            }
+        }
+    }
+
+    /// Replaces the mip chain with one generated from the base level.
+    /// sRGB color channels are filtered in linear space; alpha is always filtered as is.
+    fn generate_mips(&mut self, channels: usize, has_alpha: bool, filter: MipFilter) {
+        let srgb = self.color_space == ColorSpace::Srgb;
+        let is_color = |index: usize| !(has_alpha && index % channels == channels - 1);
+        let base: Vec<f32> = self
+            .data
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                if srgb && is_color(i) {
+                    srgb_to_linear(value.to_f32())
+                } else {
+                    value.to_f32()
+                }
+            })
+            .collect();
+        self.mips = mipmap::generate(&base, self.width, self.height, channels, filter)
+            .into_iter()
+            .map(|level| {
+                level
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, value)| {
+                        if srgb && is_color(i) {
+                            T::from_f32(linear_to_srgb(value))
+                        } else {
+                            T::from_f32(value)
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+    }
+}
+
+impl<P: image::Pixel> From<image::ImageBuffer<P, Vec<P::Subpixel>>> for PixelBuffer<P::Subpixel> {
+    fn from(image: image::ImageBuffer<P, Vec<P::Subpixel>>) -> Self {
+        PixelBuffer::new(image.width(), image.height(), image.into_raw())
+    }
+}
+
+/// An image asset, stored in the pixel format of its source file
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[uuid = "d4079e74-3ec9-4ebc-9b77-a87cafdfdada"]
 pub enum Image {
-    Rgb8(ImageBuf<u8, color::Rgb>),
-    // ...
+    Rgb8(PixelBuffer<u8>),
+    Rgba8(PixelBuffer<u8>),
+    Luma8(PixelBuffer<u8>),
+    LumaA8(PixelBuffer<u8>),
+    Luma16(PixelBuffer<u16>),
+    LumaA16(PixelBuffer<u16>),
+    Rgb16(PixelBuffer<u16>),
+    Rgba16(PixelBuffer<u16>),
+    Rgb32F(PixelBuffer<f32>),
+    Rgba32F(PixelBuffer<f32>),
+}
+
+/// Evaluates `$body` with `$buffer` bound to the image's [PixelBuffer], whatever its channel type
+macro_rules! with_buffer {
+    ($image:expr, $buffer:ident => $body:expr) => {
+        match $image {
+            Image::Rgb8($buffer)
+            | Image::Rgba8($buffer)
+            | Image::Luma8($buffer)
+            | Image::LumaA8($buffer) => $body,
+            Image::Luma16($buffer)
+            | Image::LumaA16($buffer)
+            | Image::Rgb16($buffer)
+            | Image::Rgba16($buffer) => $body,
+            Image::Rgb32F($buffer) | Image::Rgba32F($buffer) => $body,
+        }
+    };
+}
+
+impl Image {
+    pub fn width(&self) -> u32 {
+        with_buffer!(self, buffer => buffer.width)
+    }
+
+    pub fn height(&self) -> u32 {
+        with_buffer!(self, buffer => buffer.height)
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        match self {
+            Image::Rgb8(_) => PixelFormat::Rgb8,
+            Image::Rgba8(_) => PixelFormat::Rgba8,
+            Image::Luma8(_) => PixelFormat::Luma8,
+            Image::LumaA8(_) => PixelFormat::LumaA8,
+            Image::Luma16(_) => PixelFormat::Luma16,
+            Image::LumaA16(_) => PixelFormat::LumaA16,
+            Image::Rgb16(_) => PixelFormat::Rgb16,
+            Image::Rgba16(_) => PixelFormat::Rgba16,
+            Image::Rgb32F(_) => PixelFormat::Rgb32F,
+            Image::Rgba32F(_) => PixelFormat::Rgba32F,
+        }
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        with_buffer!(self, buffer => buffer.color_space)
+    }
+
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        with_buffer!(self, buffer => buffer.color_space = color_space)
+    }
+
+    /// The raw pixel data of the base level in native endianness, row by row
+    pub fn as_bytes(&self) -> &[u8] {
+        with_buffer!(self, buffer => bytemuck::cast_slice(&buffer.data))
+    }
+
+    /// Number of mip levels stored, including the base level
+    pub fn mip_level_count(&self) -> usize {
+        with_buffer!(self, buffer => buffer.mips.len() + 1)
+    }
+
+    /// The width and height of a mip level
+    pub fn mip_level_size(&self, level: usize) -> (u32, u32) {
+        mipmap::level_size(self.width(), self.height(), level)
+    }
+
+    /// The raw pixel data of a mip level in native endianness, row by row
+    pub fn mip_level_bytes(&self, level: usize) -> Option<&[u8]> {
+        with_buffer!(self, buffer => buffer.level(level).map(bytemuck::cast_slice))
+    }
+
+    /// Generates a full mip chain down to 1x1 from the base level
+    pub fn generate_mips(&mut self, filter: MipFilter) {
+        let format = self.format();
+        let channels = format.channels();
+        let has_alpha = channels == 2 || channels == 4;
+        with_buffer!(self, buffer => buffer.generate_mips(channels, has_alpha, filter))
+    }
+
+    /// Halves the image until neither side exceeds `max_dimension`, filtering as mips are.
+    /// The mips below the new base level are kept if the image had any.
+    pub fn downscale(&mut self, max_dimension: u32, filter: MipFilter) {
+        let mut level = 0;
+        loop {
+            let (width, height) = self.mip_level_size(level);
+            if (width <= max_dimension && height <= max_dimension) || (width == 1 && height == 1) {
+                break;
+            }
+            level += 1;
+        }
+        if level == 0 {
+            return;
+        }
+        let had_mips = self.mip_level_count() > 1;
+        if !had_mips {
+            self.generate_mips(filter);
+        }
+        with_buffer!(self, buffer => buffer.drop_levels(level));
+        if !had_mips {
+            with_buffer!(self, buffer => buffer.mips.clear());
+        }
+    }
+
+    /// Multiplies the color channels by alpha. Does nothing for formats without alpha.
+    pub fn premultiply_alpha(&mut self) {
+        let channels = self.format().channels();
+        match self {
+            Image::LumaA8(buffer) | Image::Rgba8(buffer) => buffer.premultiply_alpha(channels),
+            Image::LumaA16(buffer) | Image::Rgba16(buffer) => buffer.premultiply_alpha(channels),
+            Image::Rgba32F(buffer) => buffer.premultiply_alpha(channels),
+            _ => {}
+        }
+    }
+}
+
+fn convert(image: DynamicImage, format: PixelFormat) -> DynamicImage {
+    match format {
+        PixelFormat::Rgb8 => DynamicImage::ImageRgb8(image.into_rgb8()),
+        PixelFormat::Rgba8 => DynamicImage::ImageRgba8(image.into_rgba8()),
+        PixelFormat::Luma8 => DynamicImage::ImageLuma8(image.into_luma8()),
+        PixelFormat::LumaA8 => DynamicImage::ImageLumaA8(image.into_luma_alpha8()),
+        PixelFormat::Luma16 => DynamicImage::ImageLuma16(image.into_luma16()),
+        PixelFormat::LumaA16 => DynamicImage::ImageLumaA16(image.into_luma_alpha16()),
+        PixelFormat::Rgb16 => DynamicImage::ImageRgb16(image.into_rgb16()),
+        PixelFormat::Rgba16 => DynamicImage::ImageRgba16(image.into_rgba16()),
+        PixelFormat::Rgb32F => DynamicImage::ImageRgb32F(image.into_rgb32f()),
+        PixelFormat::Rgba32F => DynamicImage::ImageRgba32F(image.into_rgba32f()),
+    }
+}
+
+impl From<DynamicImage> for Image {
+    fn from(image: DynamicImage) -> Self {
+        let mut image = match image {
+            DynamicImage::ImageRgb8(image) => Image::Rgb8(image.into()),
+            DynamicImage::ImageRgba8(image) => Image::Rgba8(image.into()),
+            DynamicImage::ImageLuma8(image) => Image::Luma8(image.into()),
+            DynamicImage::ImageLumaA8(image) => Image::LumaA8(image.into()),
+            DynamicImage::ImageLuma16(image) => Image::Luma16(image.into()),
+            DynamicImage::ImageLumaA16(image) => Image::LumaA16(image.into()),
+            DynamicImage::ImageRgb16(image) => Image::Rgb16(image.into()),
+            DynamicImage::ImageRgba16(image) => Image::Rgba16(image.into()),
+            DynamicImage::ImageRgb32F(image) => Image::Rgb32F(image.into()),
+            DynamicImage::ImageRgba32F(image) => Image::Rgba32F(image.into()),
+            image => Image::Rgba8(image.into_rgba8().into()),
+        };
+        image.set_color_space(ColorSpace::default_for(image.format()));
+        image
+    }
+}
+
+/// The filter used when an image is downscaled on import
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<ResizeFilter> for FilterType {
+    fn from(filter: ResizeFilter) -> Self {
+        match filter {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Gaussian => FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Import settings for an image, editable in its `.meta` file
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug)]
+#[uuid = "d8238bfd-9d1f-4070-ac2d-77137b6ec56f"]
+#[serde(default)]
+pub struct ImageImporterOptions {
+    /// Converts the image to this format. The source file's format is kept if `None`.
+    pub format: Option<PixelFormat>,
+    /// The color space of the image's color channels, or [ColorSpace::default_for] its format if `None`
+    pub color_space: Option<ColorSpace>,
+    pub flip_vertical: bool,
+    /// Downscales the image so that neither side exceeds this many pixels, preserving its aspect ratio.
+    pub max_dimension: Option<u32>,
+    pub resize_filter: ResizeFilter,
+    pub premultiply_alpha: bool,
+    /// Generates a full mip chain on import so the renderer doesn't have to
+    pub generate_mips: bool,
+    pub mip_filter: MipFilter,
+    /// Divides the image into frames, each imported as its own image alongside a [SpriteSheet].
+    /// Frames are cut from the flipped and downscaled sheet, and their rects are in its coordinates.
+    pub sheet: Option<SpriteSheetLayout>,
+}
+
+impl Default for ImageImporterOptions {
+    fn default() -> Self {
+        ImageImporterOptions {
+            format: None,
+            color_space: None,
+            flip_vertical: false,
+            max_dimension: None,
+            resize_filter: ResizeFilter::Triangle,
+            premultiply_alpha: false,
+            generate_mips: false,
+            mip_filter: MipFilter::Kaiser,
+            sheet: None,
+        }
+    }
+}
+
+/// File extensions the image importer is registered for, with the format each one is decoded as
+pub const IMAGE_EXTENSIONS: &[(&str, ImageFormat)] = &[
+    ("png", ImageFormat::Png),
+    ("jpg", ImageFormat::Jpeg),
+    ("jpeg", ImageFormat::Jpeg),
+    ("bmp", ImageFormat::Bmp),
+    ("tga", ImageFormat::Tga),
+    ("hdr", ImageFormat::Hdr),
+    ("exr", ImageFormat::OpenExr),
+    ("gif", ImageFormat::Gif),
+    ("ico", ImageFormat::Ico),
+    ("tif", ImageFormat::Tiff),
+    ("tiff", ImageFormat::Tiff),
+    ("webp", ImageFormat::WebP),
+    ("dds", ImageFormat::Dds),
+    ("pbm", ImageFormat::Pnm),
+    ("pgm", ImageFormat::Pnm),
+    ("ppm", ImageFormat::Pnm),
+    ("pam", ImageFormat::Pnm),
+    ("ff", ImageFormat::Farbfeld),
+];
+
+/// Errors that occur while importing an image
+#[derive(Error, Debug)]
+pub enum ImageImportError {
+    #[error("Unsupported image format: {0}")]
+    UnsupportedFormat(String),
+    #[error("Failed to decode image: {0}")]
+    Decode(#[from] image::ImageError),
+    #[error("Sprite sheet frame {name:?} lies outside of the {width}x{height} image.")]
+    FrameOutOfBounds {
+        name: String,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl ImageImporterOptions {
+    /// Decodes an image and applies these options to it.
+    ///
+    /// The format is guessed from the file's contents if `format` is `None`.
+    pub fn process(
+        &self,
+        bytes: &[u8],
+        format: Option<ImageFormat>,
+    ) -> Result<Image, ImageImportError> {
+        Ok(self.apply(decode(bytes, format)?))
+    }
+
+    /// Applies these options to a decoded sprite sheet, and cuts the frames described by `sheet` out of
+    /// the result. Frame rects are given in the coordinates of the source file, and returned in those of
+    /// the processed sheet, which may be flipped or downscaled.
+    pub fn process_sheet(
+        &self,
+        image: DynamicImage,
+        sheet: &SpriteSheetLayout,
+    ) -> Result<(Image, Vec<(NamedFrame, Image)>), ImageImportError> {
+        let (width, height) = (image.width(), image.height());
+        let frames = sheet.frames(width, height);
+        for frame in &frames {
+            let rect = frame.rect;
+            let fits = rect.x.checked_add(rect.width).map_or(false, |x| x <= width)
+                && rect
+                    .y
+                    .checked_add(rect.height)
+                    .map_or(false, |y| y <= height);
+            if !fits {
+                return Err(ImageImportError::FrameOutOfBounds {
+                    name: frame.name.clone(),
+                    width,
+                    height,
+                });
+            }
+        }
+        let transformed = self.transform(image);
+        let frames = frames
+            .into_iter()
+            .map(|mut frame| {
+                frame.rect = self.transform_rect(frame.rect, (width, height), &transformed);
+                let rect = frame.rect;
+                let cropped = transformed.crop_imm(rect.x, rect.y, rect.width, rect.height);
+                (frame, self.finish(cropped))
+            })
+            .collect();
+        Ok((self.finish(transformed), frames))
+    }
+
+    /// Maps a rect of a `width` by `height` image to the image [transform](Self::transform) made of it
+    fn transform_rect(
+        &self,
+        rect: AtlasRect,
+        (width, height): (u32, u32),
+        transformed: &DynamicImage,
+    ) -> AtlasRect {
+        let (new_width, new_height) = (transformed.width(), transformed.height());
+        let scale = |value: u32, from: u32, to: u32| {
+            ((value as u64 * to as u64 + from as u64 / 2) / from as u64) as u32
+        };
+        let x = scale(rect.x, width, new_width);
+        let right = scale(rect.x + rect.width, width, new_width);
+        let top = scale(rect.y, height, new_height);
+        let bottom = scale(rect.y + rect.height, height, new_height);
+        AtlasRect {
+            x,
+            y: if self.flip_vertical {
+                new_height - bottom
+            } else {
+                top
+            },
+            width: right - x,
+            height: bottom - top,
+        }
+    }
+
+    pub(crate) fn apply(&self, image: DynamicImage) -> Image {
+        self.finish(self.transform(image))
+    }
+
+    /// Flips and downscales an image
+    pub(crate) fn transform(&self, mut image: DynamicImage) -> DynamicImage {
+        if self.flip_vertical {
+            image = image.flipv();
+        }
+        if let Some(max_dimension) = self.max_dimension {
+            if image.width() > max_dimension || image.height() > max_dimension {
+                image = image.resize(max_dimension, max_dimension, self.resize_filter.into());
+            }
+        }
+        image
+    }
+
+    /// Converts, premultiplies and generates mips for an image that is already transformed
+    pub(crate) fn finish(&self, mut image: DynamicImage) -> Image {
+        if let Some(format) = self.format {
+            image = convert(image, format);
+        }
+        let mut image = Image::from(image);
+        if self.premultiply_alpha {
+            image.premultiply_alpha();
+        }
+        if let Some(color_space) = self.color_space {
+            image.set_color_space(color_space);
+        }
+        if self.generate_mips {
+            image.generate_mips(self.mip_filter);
+        }
+        image
+    }
 }
 
+/// Decodes an image, guessing its format from the contents if `format` is `None`
+pub(crate) fn decode(
+    bytes: &[u8],
+    format: Option<ImageFormat>,
+) -> Result<DynamicImage, ImageImportError> {
+    let format = match format {
+        Some(format) => format,
+        None => image::guess_format(bytes).map_err(|_| {
+            ImageImportError::UnsupportedFormat(
+                "file contents do not match any known image format".to_string(),
+            )
+        })?,
+    };
+    image::load_from_memory_with_format(bytes, format).map_err(|err| match err {
+        image::ImageError::Unsupported(err) => ImageImportError::UnsupportedFormat(err.to_string()),
+        err => ImageImportError::Decode(err),
+    })
+}
+
+/// The UUID of the imported image, plus those of the sprite sheet and its frames if the image is divided into frames
 #[derive(TypeUuid, Serialize, Deserialize, Default)]
 #[uuid = "3c8367c8-45fb-40bb-a229-00e5e9c3fc70"]
-pub struct SimpleState(Option<AssetUuid>);
-#[derive(TypeUuid)]
+pub struct SimpleState(Option<AssetUuid>, #[serde(default)] NamedState);
+/// Imports image files, decoding them as the format of the extension they are registered for
+#[derive(TypeUuid, Default)]
 #[uuid = "720d636b-b79c-42d4-8f46-a2d8e1ada46e"]
-pub struct ImageImporter;
+pub struct ImageImporter {
+    format: Option<ImageFormat>,
+}
+
+impl ImageImporter {
+    /// An importer that guesses the format from each file's contents
+    pub fn new() -> Self {
+        ImageImporter::default()
+    }
+
+    pub fn with_format(format: ImageFormat) -> Self {
+        ImageImporter {
+            format: Some(format),
+        }
+    }
+}
 impl AsyncImporter for ImageImporter {
     fn version_static() -> u32
     where
         Self: Sized,
     {
-        1
+        7
     }
     fn version(&self) -> u32 {
         Self::version_static()
     }
 
-    type Options = ();
+    type Options = ImageImporterOptions;
 
     type State = SimpleState;
 
@@ -41,28 +685,247 @@ impl AsyncImporter for ImageImporter {
         &'a self,
         _op: &'a mut ImportOp,
         source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
-        _options: &Self::Options,
+        options: &Self::Options,
         state: &'a mut Self::State,
     ) -> BoxFuture<'a, Result<ImporterValue>> {
+        let options = options.clone();
+        let format = self.format;
         Box::pin(async move {
             let id = state
                 .0
                 .unwrap_or_else(|| AssetUuid(*uuid::Uuid::new_v4().as_bytes()));
-            *state = SimpleState(Some(id));
+            state.0 = Some(id);
             let mut bytes = Vec::new();
             source.read_to_end(&mut bytes).await?;
-            let asset =
-                Image::Rgb8(image2::io::decode(&bytes).map_err(|e| Error::Boxed(Box::new(e)))?);
-            Ok(ImporterValue {
-                assets: vec![ImportedAsset {
-                    id,
-                    search_tags: vec![],
+            let decoded = decode(&bytes, format).map_err(|e| Error::Boxed(Box::new(e)))?;
+            let (image, frames) = match &options.sheet {
+                Some(sheet) => options
+                    .process_sheet(decoded, sheet)
+                    .map_err(|e| Error::Boxed(Box::new(e)))?,
+                None => (options.apply(decoded), Vec::new()),
+            };
+            let mut assets = vec![ImportedAsset {
+                id,
+                search_tags: vec![],
+                build_deps: vec![],
+                load_deps: vec![],
+                build_pipeline: None,
+                asset_data: Box::new(image),
+            }];
+            if options.sheet.is_none() {
+                state.1 = NamedState::default();
+                return Ok(ImporterValue { assets });
+            }
+
+            // Frame names are prefixed so that they can't collide with the sheet's own name
+            let names: Vec<String> = frames
+                .iter()
+                .map(|(frame, _)| format!("frame/{}", frame.name))
+                .collect();
+            state.1.retain_names(
+                names
+                    .iter()
+                    .map(String::as_str)
+                    .chain(std::iter::once("sheet")),
+            );
+            let mut sheet = SpriteSheet {
+                image: id,
+                frames: Vec::new(),
+                names: HashMap::new(),
+            };
+            for ((frame, image), name) in frames.into_iter().zip(&names) {
+                let frame_id = state.1.id(name);
+                sheet.names.insert(frame.name.clone(), sheet.frames.len());
+                sheet.frames.push(SpriteFrame {
+                    rect: frame.rect,
+                    image: frame_id,
+                });
+                assets.push(ImportedAsset {
+                    id: frame_id,
+                    search_tags: vec![("frame".to_string(), Some(frame.name))],
                     build_deps: vec![],
                     load_deps: vec![],
                     build_pipeline: None,
-                    asset_data: Box::new(asset),
-                }],
-            })
+                    asset_data: Box::new(image),
+                });
+            }
+            let frame_ids = sheet.frames.iter().map(|frame| AssetRef::Uuid(frame.image));
+            let load_deps = std::iter::once(AssetRef::Uuid(id))
+                .chain(frame_ids)
+                .collect();
+            assets.push(ImportedAsset {
+                id: state.1.id("sheet"),
+                search_tags: vec![],
+                build_deps: vec![],
+                load_deps,
+                build_pipeline: None,
+                asset_data: Box::new(sheet),
+            });
+            Ok(ImporterValue { assets })
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::new(width, height));
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image
+            .write_to(&mut bytes, ImageFormat::Png)
+            .expect("PNG encoding is infallible for RGBA8");
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn extensions_are_unique_and_plain() {
+        for (index, (ext, _)) in IMAGE_EXTENSIONS.iter().enumerate() {
+            assert!(!ext.contains('.'), "{}", ext);
+            assert_eq!(*ext, ext.to_lowercase());
+            assert!(
+                IMAGE_EXTENSIONS[index + 1..]
+                    .iter()
+                    .all(|(other, _)| other != ext),
+                "{} is registered twice",
+                ext
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_as_the_registered_format() {
+        let bytes = png(3, 2);
+        let options = ImageImporterOptions::default();
+        let image = options.process(&bytes, Some(ImageFormat::Png)).unwrap();
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert_eq!(image.format(), PixelFormat::Rgba8);
+        assert_eq!(image.color_space(), ColorSpace::Srgb);
+
+        let guessed = options.process(&bytes, None).unwrap();
+        assert_eq!(guessed, image);
+
+        assert!(options.process(&bytes, Some(ImageFormat::Jpeg)).is_err());
+        assert!(matches!(
+            options.process(b"not an image", None),
+            Err(ImageImportError::UnsupportedFormat(_))
+        ));
+    }
+
+    /// A 4x4 image with a different color in every pixel
+    fn gradient() -> DynamicImage {
+        DynamicImage::ImageRgba8(image::RgbaImage::from_fn(4, 4, |x, y| {
+            image::Rgba([x as u8 * 60, y as u8 * 60, 0, 255])
+        }))
+    }
+
+    /// The RGBA8 pixels of an image within a rect
+    fn region(image: &Image, rect: AtlasRect) -> Vec<u8> {
+        let row_len = image.width() as usize * 4;
+        (rect.y..rect.y + rect.height)
+            .flat_map(|y| {
+                let start = y as usize * row_len + rect.x as usize * 4;
+                image.as_bytes()[start..start + rect.width as usize * 4].to_vec()
+            })
+            .collect()
+    }
+
+    fn grid(cell: u32) -> SpriteSheetLayout {
+        SpriteSheetLayout::Grid {
+            cell_width: cell,
+            cell_height: cell,
+            columns: None,
+            rows: None,
+            padding: 0,
+            offset: 0,
+            names: vec![],
+        }
+    }
+
+    #[test]
+    fn frames_match_the_flipped_sheet() {
+        let options = ImageImporterOptions {
+            flip_vertical: true,
+            ..Default::default()
+        };
+        let (sheet, frames) = options.process_sheet(gradient(), &grid(2)).unwrap();
+        assert_eq!((sheet.width(), sheet.height()), (4, 4));
+        assert_eq!(frames.len(), 4);
+        // The top left frame of the source ends up at the bottom left of the sheet
+        assert_eq!(
+            frames[0].0.rect,
+            AtlasRect {
+                x: 0,
+                y: 2,
+                width: 2,
+                height: 2
+            }
+        );
+        for (frame, image) in &frames {
+            assert_eq!(
+                region(&sheet, frame.rect),
+                image.as_bytes(),
+                "{}",
+                frame.name
+            );
+        }
+    }
+
+    #[test]
+    fn frames_match_the_downscaled_sheet() {
+        let options = ImageImporterOptions {
+            flip_vertical: true,
+            max_dimension: Some(2),
+            ..Default::default()
+        };
+        let (sheet, frames) = options.process_sheet(gradient(), &grid(2)).unwrap();
+        assert_eq!((sheet.width(), sheet.height()), (2, 2));
+        let rects: Vec<AtlasRect> = frames.iter().map(|(frame, _)| frame.rect).collect();
+        let rect = |x, y| AtlasRect {
+            x,
+            y,
+            width: 1,
+            height: 1,
+        };
+        assert_eq!(rects, vec![rect(0, 1), rect(1, 1), rect(0, 0), rect(1, 0)]);
+        for (frame, image) in &frames {
+            assert_eq!(
+                region(&sheet, frame.rect),
+                image.as_bytes(),
+                "{}",
+                frame.name
+            );
+        }
+    }
+
+    #[test]
+    fn frames_outside_the_sheet_are_rejected() {
+        let layout = SpriteSheetLayout::Frames(vec![NamedFrame {
+            name: "wide".to_string(),
+            rect: AtlasRect {
+                x: 2,
+                y: 0,
+                width: 3,
+                height: 1,
+            },
+        }]);
+        assert!(matches!(
+            ImageImporterOptions::default().process_sheet(gradient(), &layout),
+            Err(ImageImportError::FrameOutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn state_without_sheet_uuids_still_deserializes() {
+        // The state of images imported before sprite sheets were supported
+        #[derive(Serialize)]
+        struct OldSimpleState(Option<AssetUuid>);
+
+        let id = AssetUuid([3; 16]);
+        let old = ron::ser::to_string(&OldSimpleState(Some(id))).unwrap();
+        let state: SimpleState = ron::de::from_str(&old).unwrap();
+        assert_eq!(state.0, Some(id));
+        assert!(state.1 .0.is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// The filter used to downsample each level of a mip chain
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MipFilter {
+    /// Averages each 2x2 block. Fast, but blurs and aliases more than the other filters.
+    Box,
+    /// A Kaiser-windowed sinc, which keeps detail without the ringing of Lanczos.
+    Kaiser,
+    /// A three-lobed Lanczos filter, the sharpest of the three.
+    Lanczos,
+}
+
+impl Default for MipFilter {
+    fn default() -> Self {
+        MipFilter::Kaiser
+    }
+}
+
+impl MipFilter {
+    /// Half-width of the filter kernel, measured in pixels of the level being produced
+    fn support(self) -> f32 {
+        match self {
+            MipFilter::Box => 0.5,
+            MipFilter::Kaiser | MipFilter::Lanczos => 3.0,
+        }
+    }
+
+    fn weight(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            MipFilter::Box => {
+                if x <= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            MipFilter::Kaiser => {
+                const ALPHA: f32 = 4.0;
+                let t = x / self.support();
+                if t >= 1.0 {
+                    0.0
+                } else {
+                    sinc(x) * bessel_i0(ALPHA * (1.0 - t * t).sqrt()) / bessel_i0(ALPHA)
+                }
+            }
+            MipFilter::Lanczos => {
+                if x >= self.support() {
+                    0.0
+                } else {
+                    sinc(x) * sinc(x / self.support())
+                }
+            }
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Zeroth order modified Bessel function of the first kind, used by the Kaiser window
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+    for k in 1..32 {
+        term *= half_x / k as f32;
+        sum += term * term;
+        if term * term < sum * 1e-8 {
+            break;
+        }
+    }
+    sum
+}
+
+/// The size of `level` for an image whose base level is `width` by `height`
+pub(crate) fn level_size(width: u32, height: u32, level: usize) -> (u32, u32) {
+    (
+        (width >> level.min(31)).max(1),
+        (height >> level.min(31)).max(1),
+    )
+}
+
+/// For every destination index, the source indices it samples and their normalized weights.
+/// Samples past the edges are clamped to the border pixel.
+fn axis_weights(src_len: u32, dst_len: u32, filter: MipFilter) -> Vec<Vec<(usize, f32)>> {
+    let scale = src_len as f32 / dst_len as f32;
+    let radius = filter.support() * scale;
+    (0..dst_len)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * scale;
+            let first = (center - radius).floor() as i64;
+            let last = (center + radius).ceil() as i64;
+            let mut taps: Vec<(usize, f32)> = (first..=last)
+                .map(|j| {
+                    let weight = filter.weight((j as f32 + 0.5 - center) / scale);
+                    (j.max(0).min(src_len as i64 - 1) as usize, weight)
+                })
+                .filter(|(_, weight)| *weight != 0.0)
+                .collect();
+            let total: f32 = taps.iter().map(|(_, weight)| weight).sum();
+            for (_, weight) in &mut taps {
+                *weight /= total;
+            }
+            taps
+        })
+        .collect()
+}
+
+/// Downsamples one level to the next, filtering rows and then columns
+fn downsample(
+    src: &[f32],
+    width: u32,
+    height: u32,
+    channels: usize,
+    filter: MipFilter,
+) -> (Vec<f32>, u32, u32) {
+    let (new_width, new_height) = level_size(width, height, 1);
+    let row_taps = axis_weights(width, new_width, filter);
+    let column_taps = axis_weights(height, new_height, filter);
+
+    let mut rows = vec![0.0; new_width as usize * height as usize * channels];
+    for y in 0..height as usize {
+        for (x, taps) in row_taps.iter().enumerate() {
+            let out = (y * new_width as usize + x) * channels;
+            for &(sx, weight) in taps {
+                let input = (y * width as usize + sx) * channels;
+                for c in 0..channels {
+                    rows[out + c] += src[input + c] * weight;
+                }
+            }
+        }
+    }
+
+    let mut level = vec![0.0; new_width as usize * new_height as usize * channels];
+    for (y, taps) in column_taps.iter().enumerate() {
+        for x in 0..new_width as usize {
+            let out = (y * new_width as usize + x) * channels;
+            for &(sy, weight) in taps {
+                let input = (sy * new_width as usize + x) * channels;
+                for c in 0..channels {
+                    level[out + c] += rows[input + c] * weight;
+                }
+            }
+        }
+    }
+    (level, new_width, new_height)
+}
+
+/// Generates every level below `base` down to 1x1
+pub(crate) fn generate(
+    base: &[f32],
+    width: u32,
+    height: u32,
+    channels: usize,
+    filter: MipFilter,
+) -> Vec<Vec<f32>> {
+    let mut levels: Vec<Vec<f32>> = Vec::new();
+    let (mut width, mut height) = (width, height);
+    while width > 1 || height > 1 {
+        let src = levels.last().map_or(base, |level| level.as_slice());
+        let (level, new_width, new_height) = downsample(src, width, height, channels, filter);
+        levels.push(level);
+        width = new_width;
+        height = new_height;
+    }
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{Image, PixelBuffer};
+
+    const FILTERS: [MipFilter; 3] = [MipFilter::Box, MipFilter::Kaiser, MipFilter::Lanczos];
+
+    #[test]
+    fn level_sizes_halve_down_to_one() {
+        assert_eq!(level_size(8, 2, 0), (8, 2));
+        assert_eq!(level_size(8, 2, 1), (4, 1));
+        assert_eq!(level_size(8, 2, 2), (2, 1));
+        assert_eq!(level_size(8, 2, 3), (1, 1));
+        assert_eq!(level_size(5, 3, 1), (2, 1));
+        assert_eq!(level_size(1, 1, 40), (1, 1));
+    }
+
+    #[test]
+    fn chain_ends_at_one_by_one() {
+        for &(width, height, levels) in &[(1, 1, 0), (2, 2, 1), (8, 2, 3), (5, 3, 2), (7, 16, 4)] {
+            for &filter in &FILTERS {
+                let channels = 2;
+                let base = vec![0.5; (width * height) as usize * channels];
+                let chain = generate(&base, width, height, channels, filter);
+                assert_eq!(chain.len(), levels, "{}x{} {:?}", width, height, filter);
+                for (level, data) in chain.iter().enumerate() {
+                    let (w, h) = level_size(width, height, level + 1);
+                    assert_eq!(data.len(), (w * h) as usize * channels);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn flat_images_stay_flat() {
+        for &filter in &FILTERS {
+            let base = vec![0.25; 6 * 5];
+            for level in generate(&base, 6, 5, 1, filter) {
+                for value in level {
+                    assert!((value - 0.25).abs() < 1e-5, "{:?}: {}", filter, value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn box_filter_averages_blocks() {
+        let base = [0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0];
+        let chain = generate(&base, 4, 2, 1, MipFilter::Box);
+        assert_eq!(chain[0], vec![0.5, 0.5]);
+        assert_eq!(chain[1], vec![0.5]);
+    }
+
+    #[test]
+    fn image_mip_levels() {
+        let mut image = Image::Rgba8(PixelBuffer::new(6, 4, vec![200; 6 * 4 * 4]));
+        image.generate_mips(MipFilter::Kaiser);
+        assert_eq!(image.mip_level_count(), 3);
+        for level in 0..3 {
+            let (width, height) = image.mip_level_size(level);
+            assert_eq!((width, height), level_size(6, 4, level));
+            let bytes = image.mip_level_bytes(level).unwrap();
+            assert_eq!(bytes.len(), (width * height * 4) as usize);
+            assert!(bytes.iter().all(|&value| (199..=201).contains(&value)));
+        }
+        assert!(image.mip_level_bytes(3).is_none());
+    }
+}
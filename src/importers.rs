@@ -0,0 +1,86 @@
+use crate::{
+    animation::{AsepriteFormat, AsepriteImporter},
+    image::ImageImporter,
+    shader::ShaderImporter,
+    tiled::{TiledFormat, TiledImporter},
+};
+use atelier_core::AssetUuid;
+use atelier_importer::BoxedImporter;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path};
+use type_uuid::TypeUuid;
+
+/// The importers registered by default, keyed by file extension.
+///
+/// Used both by the asset daemon and by [PackfileBuilder](crate::packfile::PackfileBuilder) so that
+/// packfiles contain the same assets a directory would. Importers that read other files, such as
+/// the sprites of a texture atlas, resolve their paths relative to `asset_root`.
+pub fn default_importers(asset_root: &Path) -> Vec<(&'static str, Box<dyn BoxedImporter>)> {
+    let mut importers: Vec<(&'static str, Box<dyn BoxedImporter>)> = Vec::new();
+    for (ext, format) in crate::image::IMAGE_EXTENSIONS {
+        importers.push((ext, Box::new(ImageImporter::with_format(*format))));
+    }
+    importers.push(("wav", Box::new(crate::audio::WavImporter)));
+    importers.push(("ogg", Box::new(crate::audio::OggImporter)));
+    importers.push(("gltf", Box::new(crate::mesh::GltfImporter)));
+    importers.push(("glb", Box::new(crate::mesh::GltfImporter)));
+    importers.push(("obj", Box::new(crate::mesh::ObjImporter)));
+    for (ext, language, stage) in crate::shader::SHADER_EXTENSIONS {
+        importers.push((
+            ext,
+            Box::new(ShaderImporter::new(asset_root, *language, *stage)),
+        ));
+    }
+    importers.push(("ttf", Box::new(crate::font::FontImporter)));
+    importers.push(("otf", Box::new(crate::font::FontImporter)));
+    importers.push((
+        "atlas",
+        Box::new(crate::texture_atlas::TextureAtlasImporter::new(asset_root)),
+    ));
+    importers.push((
+        "tmx",
+        Box::new(TiledImporter::new(asset_root, TiledFormat::Tmx)),
+    ));
+    importers.push((
+        "tmj",
+        Box::new(TiledImporter::new(asset_root, TiledFormat::Json)),
+    ));
+    importers.push(("loc", Box::new(crate::localization::StringTableImporter)));
+    for ext in &["aseprite", "ase"] {
+        importers.push((
+            ext,
+            Box::new(AsepriteImporter::new(asset_root, AsepriteFormat::Binary)),
+        ));
+    }
+    importers
+}
+
+/// Importers that know which file extensions they handle, such as those made with
+/// [derive(Importer)](derive@crate::Importer)
+pub trait ImporterExtensions {
+    const EXTENSIONS: &'static [&'static str];
+}
+
+/// Importer state for importers that produce several assets from one source file.
+///
+/// Each asset is identified by a name that is stable across imports, so it keeps its UUID
+/// when the source file changes.
+#[derive(TypeUuid, Serialize, Deserialize, Default, Clone, Debug)]
+#[uuid = "a0a69708-85d6-4333-a34c-d9024090e637"]
+pub struct NamedState(pub HashMap<String, AssetUuid>);
+
+impl NamedState {
+    /// The UUID of the named asset, generating one on first use
+    pub fn id(&mut self, name: &str) -> AssetUuid {
+        *self
+            .0
+            .entry(name.to_string())
+            .or_insert_with(|| AssetUuid(*uuid::Uuid::new_v4().as_bytes()))
+    }
+
+    /// Forgets the UUIDs of assets that were not produced by the latest import
+    pub fn retain_names<'a, I: IntoIterator<Item = &'a str>>(&mut self, names: I) {
+        let names: std::collections::HashSet<&str> = names.into_iter().collect();
+        self.0.retain(|name, _| names.contains(name.as_str()));
+    }
+}
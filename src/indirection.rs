@@ -0,0 +1,488 @@
+use crate::AssetTypeId;
+use atelier_core::{AssetMetadata, AssetUuid};
+use atelier_loader::storage::{
+    DefaultIndirectionResolver, IndirectIdentifier, IndirectionResolver, IndirectionTable,
+    LoadHandle,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+/// Chooses variants of a path to load in its place, e.g. `tree.low.png` for `tree.png` on low quality.
+///
+/// Set with [AssetServer::set_resolver](crate::AssetServer::set_resolver).
+pub trait VariantResolver: Send + Sync + 'static {
+    /// Paths to load in place of `path`, most preferred first. `path` itself is loaded if none of them exist.
+    fn variants(&self, path: &str) -> Vec<String>;
+
+    /// Whether `path` can have variants at all. Paths no resolver applies to are loaded without probing
+    /// for variants.
+    fn applies_to(&self, _path: &str) -> bool {
+        true
+    }
+}
+
+/// Whether the last extension of `path` is one of `extensions`, ignoring case
+fn has_extension(path: &str, extensions: &[String]) -> bool {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    match file_name.rfind('.') {
+        Some(dot) if dot > 0 => extensions
+            .iter()
+            .any(|ext| ext.eq_ignore_ascii_case(&file_name[dot + 1..])),
+        _ => false,
+    }
+}
+
+/// Inserts `.{suffix}` before the extension of a path: `ui/strings.loc` becomes `ui/strings.fr.loc`
+pub fn with_suffix(path: &str, suffix: &str) -> String {
+    let file_start = path.rfind('/').map_or(0, |slash| slash + 1);
+    match path[file_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = file_start + dot;
+            format!("{}.{}{}", &path[..dot], suffix, &path[dot..])
+        }
+        _ => format!("{}.{}", path, suffix),
+    }
+}
+
+/// Prefers variants with one of the suffixes, in order, e.g. `["low"]` for a low quality setting or
+/// `["switch", "console"]` for a platform
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VariantSuffixResolver {
+    pub suffixes: Vec<String>,
+    /// Only paths with one of these extensions have variants, e.g. `["png"]`. All paths do if empty.
+    pub extensions: Vec<String>,
+}
+
+impl VariantSuffixResolver {
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(suffixes: I) -> Self {
+        VariantSuffixResolver {
+            suffixes: suffixes.into_iter().map(Into::into).collect(),
+            extensions: Vec::new(),
+        }
+    }
+
+    pub fn with_extensions<I: IntoIterator<Item = S>, S: Into<String>>(
+        mut self,
+        extensions: I,
+    ) -> Self {
+        self.extensions = extensions.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+impl VariantResolver for VariantSuffixResolver {
+    fn variants(&self, path: &str) -> Vec<String> {
+        self.suffixes
+            .iter()
+            .map(|suffix| with_suffix(path, suffix))
+            .collect()
+    }
+
+    fn applies_to(&self, path: &str) -> bool {
+        !self.suffixes.is_empty()
+            && (self.extensions.is_empty() || has_extension(path, &self.extensions))
+    }
+}
+
+/// Prefers the variant for a locale, then for its language, then for the default locale:
+/// `strings.fr-CA.loc`, `strings.fr.loc`, `strings.en.loc`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocaleResolver {
+    pub locale: String,
+    pub default_locale: String,
+    /// Only paths with one of these extensions are localized. Defaults to the string table extension, `loc`.
+    pub extensions: Vec<String>,
+}
+
+impl Default for LocaleResolver {
+    fn default() -> Self {
+        LocaleResolver {
+            locale: "en".to_string(),
+            default_locale: "en".to_string(),
+            extensions: vec!["loc".to_string()],
+        }
+    }
+}
+
+impl VariantResolver for LocaleResolver {
+    fn variants(&self, path: &str) -> Vec<String> {
+        let mut locales = vec![self.locale.as_str()];
+        if let Some(language) = self.locale.split(|c| c == '-' || c == '_').next() {
+            locales.push(language);
+        }
+        locales.push(&self.default_locale);
+        let mut variants: Vec<String> = Vec::new();
+        for locale in locales {
+            let variant = with_suffix(path, locale);
+            if !locale.is_empty() && !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+        variants
+    }
+
+    fn applies_to(&self, path: &str) -> bool {
+        has_extension(path, &self.extensions)
+    }
+}
+
+/// The name the locale resolver is registered under
+pub(crate) const LOCALE_RESOLVER: &str = "locale";
+
+/// Tracks indirect handles so that they can point at a variant of their path instead of the path itself.
+///
+/// The loader only offers an [IndirectionResolver] the assets at the exact path being resolved, so the
+/// variants of every loaded path are loaded alongside it. Once a variant resolves, or the resolvers change,
+/// the path is invalidated and resolved again.
+pub(crate) struct IndirectionState {
+    pub locale: LocaleResolver,
+    /// Named resolvers, in order of priority
+    resolvers: Vec<(String, Box<dyn VariantResolver>)>,
+    /// Paths loaded through the asset server, and their indirect handles
+    requested: HashMap<String, HashSet<LoadHandle>>,
+    /// The path of each requested handle, and how many references to it are alive
+    handles: HashMap<LoadHandle, (String, usize)>,
+    /// Variant paths loaded on behalf of requested paths
+    probes: HashMap<String, LoadHandle>,
+    /// The UUID each path resolved to on its own
+    resolved: HashMap<String, AssetUuid>,
+    /// The UUID each requested path was last resolved to
+    chosen: HashMap<String, Option<AssetUuid>>,
+    /// Where each requested handle pointed after the last update
+    targets: HashMap<LoadHandle, Option<LoadHandle>>,
+    /// Handles that now point at a different asset, waiting for it to be committed
+    repointed: HashSet<LoadHandle>,
+    /// The type of each committed asset
+    asset_types: HashMap<LoadHandle, AssetTypeId>,
+}
+
+impl Default for IndirectionState {
+    fn default() -> Self {
+        IndirectionState {
+            locale: LocaleResolver::default(),
+            resolvers: vec![(
+                LOCALE_RESOLVER.to_string(),
+                Box::new(LocaleResolver::default()),
+            )],
+            requested: HashMap::new(),
+            handles: HashMap::new(),
+            probes: HashMap::new(),
+            resolved: HashMap::new(),
+            chosen: HashMap::new(),
+            targets: HashMap::new(),
+            repointed: HashSet::new(),
+            asset_types: HashMap::new(),
+        }
+    }
+}
+
+impl IndirectionState {
+    /// Paths to try in place of `path`, most preferred first. `path` itself is the last resort.
+    ///
+    /// Each resolver refines the variants of the ones before it, so with a quality resolver followed by
+    /// the locale resolver `tree.low.fr.png` is preferred over `tree.low.png`, which is preferred over `tree.fr.png`.
+    pub fn variants(&self, path: &str) -> Vec<String> {
+        let mut paths = vec![path.to_string()];
+        for (_, resolver) in &self.resolvers {
+            if !resolver.applies_to(path) {
+                continue;
+            }
+            let mut refined: Vec<String> = Vec::new();
+            for path in paths {
+                for variant in resolver.variants(&path).into_iter().chain(Some(path)) {
+                    if !refined.contains(&variant) {
+                        refined.push(variant);
+                    }
+                }
+            }
+            paths = refined;
+        }
+        paths.retain(|variant| variant != path);
+        paths
+    }
+
+    /// Adds a resolver, or replaces the one with the same name in place
+    pub fn set_resolver(&mut self, name: &str, resolver: Box<dyn VariantResolver>) {
+        match self
+            .resolvers
+            .iter_mut()
+            .find(|(existing, _)| existing == name)
+        {
+            Some((_, existing)) => *existing = resolver,
+            None => self.resolvers.push((name.to_string(), resolver)),
+        }
+    }
+
+    pub fn remove_resolver(&mut self, name: &str) -> bool {
+        let len = self.resolvers.len();
+        self.resolvers.retain(|(existing, _)| existing != name);
+        self.resolvers.len() != len
+    }
+
+    /// The UUID `path` should resolve to given what has been resolved so far
+    fn preferred(&self, path: &str) -> Option<AssetUuid> {
+        self.variants(path)
+            .iter()
+            .find_map(|variant| self.resolved.get(variant))
+            .or_else(|| self.resolved.get(path))
+            .copied()
+    }
+
+    /// Records a handle returned by [AssetServer::load](crate::AssetServer::load). Returns the variant
+    /// paths that aren't being loaded yet.
+    pub fn request(&mut self, path: &str, handle: LoadHandle) -> Vec<String> {
+        self.requested
+            .entry(path.to_string())
+            .or_default()
+            .insert(handle);
+        self.handles
+            .entry(handle)
+            .or_insert_with(|| (path.to_string(), 0))
+            .1 += 1;
+        self.unprobed(path)
+    }
+
+    /// Counts a clone of a requested handle
+    pub fn add_ref(&mut self, handle: LoadHandle) {
+        if let Some((_, refs)) = self.handles.get_mut(&handle) {
+            *refs += 1;
+        }
+    }
+
+    /// Counts a dropped reference to a requested handle. Once none are left the handle is forgotten,
+    /// and the probes no longer needed are returned so their references can be released.
+    pub fn remove_ref(&mut self, handle: LoadHandle) -> Vec<LoadHandle> {
+        let path = match self.handles.get_mut(&handle) {
+            Some((_, refs)) if *refs > 1 => {
+                *refs -= 1;
+                return Vec::new();
+            }
+            Some(_) => self.handles.remove(&handle).unwrap().0,
+            None => return Vec::new(),
+        };
+        self.targets.remove(&handle);
+        self.repointed.remove(&handle);
+        if let Some(handles) = self.requested.get_mut(&path) {
+            handles.remove(&handle);
+            if handles.is_empty() {
+                self.requested.remove(&path);
+                self.chosen.remove(&path);
+            }
+        }
+        self.take_unused_probes()
+    }
+
+    /// Forgets probes that aren't a variant of any requested path, e.g. after their paths were
+    /// released or the resolvers changed. Returns their handles.
+    pub fn take_unused_probes(&mut self) -> Vec<LoadHandle> {
+        let used: HashSet<String> = self
+            .requested
+            .keys()
+            .flat_map(|path| self.variants(path))
+            .collect();
+        let unused: Vec<String> = self
+            .probes
+            .keys()
+            .filter(|path| !used.contains(*path))
+            .cloned()
+            .collect();
+        let requested = &self.requested;
+        self.resolved
+            .retain(|path, _| requested.contains_key(path) || used.contains(path));
+        unused
+            .into_iter()
+            .filter_map(|path| self.probes.remove(&path))
+            .collect()
+    }
+
+    fn unprobed(&self, path: &str) -> Vec<String> {
+        self.variants(path)
+            .into_iter()
+            .filter(|variant| !self.probes.contains_key(variant))
+            .collect()
+    }
+
+    /// Variant paths of every requested path that aren't being loaded yet, e.g. after a resolver changes
+    pub fn all_unprobed(&self) -> Vec<String> {
+        let mut unprobed: Vec<String> = Vec::new();
+        for path in self.requested.keys() {
+            for variant in self.unprobed(path) {
+                if !unprobed.contains(&variant) {
+                    unprobed.push(variant);
+                }
+            }
+        }
+        unprobed
+    }
+
+    pub fn add_probe(&mut self, path: String, handle: LoadHandle) {
+        self.probes.insert(path, handle);
+    }
+
+    /// Requested paths whose preferred asset is no longer the one they resolved to. They are
+    /// forgotten until resolved again, so that each is only invalidated once.
+    pub fn take_stale_paths(&mut self) -> Vec<PathBuf> {
+        let stale: Vec<String> = self
+            .chosen
+            .iter()
+            .filter(|(path, chosen)| self.preferred(path) != **chosen)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in &stale {
+            self.chosen.remove(path);
+        }
+        stale.into_iter().map(PathBuf::from).collect()
+    }
+
+    /// Finds handles that point at a different asset than before. Returns those whose new asset is
+    /// committed, with its type, so a Modified event can be sent for them.
+    pub fn take_repointed(&mut self, table: &IndirectionTable) -> Vec<(LoadHandle, AssetTypeId)> {
+        for handle in self.requested.values().flatten() {
+            let target = table.resolve(*handle);
+            if let Some(previous) = self.targets.insert(*handle, target) {
+                if previous.is_some() && previous != target {
+                    self.repointed.insert(*handle);
+                }
+            }
+        }
+        let mut ready = Vec::new();
+        let asset_types = &self.asset_types;
+        self.repointed.retain(|handle| {
+            match table
+                .resolve(*handle)
+                .and_then(|target| asset_types.get(&target))
+            {
+                Some(asset_type) => {
+                    ready.push((*handle, *asset_type));
+                    false
+                }
+                None => true,
+            }
+        });
+        ready
+    }
+
+    pub fn committed(&mut self, handle: LoadHandle, asset_type: AssetTypeId) {
+        self.asset_types.insert(handle, asset_type);
+    }
+
+    pub fn freed(&mut self, handle: LoadHandle) {
+        self.asset_types.remove(&handle);
+    }
+}
+
+/// The loader doesn't resolve a path to a file that several assets were imported from. It resolves to the
+/// first of them, of the requested type if there is one. Importers produce the file's main asset first,
+/// e.g. the [Model](crate::mesh::Model) of a glTF file rather than one of its meshes.
+fn first_asset(
+    id: &IndirectIdentifier,
+    candidates: &[(PathBuf, Vec<AssetMetadata>)],
+) -> Option<AssetUuid> {
+    if candidates.len() != 1 {
+        return None;
+    }
+    let asset_type = id.type_id();
+    candidates[0]
+        .1
+        .iter()
+        .find(|asset| match (asset_type, &asset.artifact) {
+            (None, _) => true,
+            (Some(asset_type), Some(artifact)) => artifact.type_id == *asset_type,
+            (Some(_), None) => false,
+        })
+        .map(|asset| asset.id)
+}
+
+/// Resolves requested paths to their preferred variant, and every other path as the loader would
+pub(crate) struct VariantIndirectionResolver<'a>(pub &'a Mutex<IndirectionState>);
+
+impl<'a> IndirectionResolver for VariantIndirectionResolver<'a> {
+    fn resolve(
+        &self,
+        id: &IndirectIdentifier,
+        candidates: Vec<(PathBuf, Vec<AssetMetadata>)>,
+    ) -> Option<AssetUuid> {
+        let own = DefaultIndirectionResolver
+            .resolve(id, candidates.clone())
+            .or_else(|| first_asset(id, &candidates));
+        let mut state = self.0.lock();
+        let path = id.path().to_string();
+        match own {
+            Some(uuid) => state.resolved.insert(path.clone(), uuid),
+            None => state.resolved.remove(&path),
+        };
+        if !state.requested.contains_key(&path) {
+            return own;
+        }
+        let chosen = state.preferred(&path);
+        state.chosen.insert(path, chosen);
+        chosen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn french_canadian() -> LocaleResolver {
+        LocaleResolver {
+            locale: "fr-CA".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn locale_variants_fall_back_to_language_then_default() {
+        let resolver = french_canadian();
+        assert_eq!(
+            resolver.variants("ui/strings.loc"),
+            vec![
+                "ui/strings.fr-CA.loc",
+                "ui/strings.fr.loc",
+                "ui/strings.en.loc"
+            ]
+        );
+        assert_eq!(
+            LocaleResolver::default().variants("strings.loc"),
+            vec!["strings.en.loc"]
+        );
+        assert!(resolver.applies_to("ui/strings.loc"));
+        assert!(!resolver.applies_to("ui/strings.ron"));
+        assert!(!resolver.applies_to("textures/tree.png"));
+    }
+
+    #[test]
+    fn preferred_variant() {
+        let mut state = IndirectionState::default();
+        state.set_resolver(LOCALE_RESOLVER, Box::new(french_canadian()));
+        state
+            .resolved
+            .insert("strings.loc".to_string(), AssetUuid([0; 16]));
+        assert_eq!(state.preferred("strings.loc"), Some(AssetUuid([0; 16])));
+        state
+            .resolved
+            .insert("strings.en.loc".to_string(), AssetUuid([1; 16]));
+        assert_eq!(state.preferred("strings.loc"), Some(AssetUuid([1; 16])));
+        state
+            .resolved
+            .insert("strings.fr.loc".to_string(), AssetUuid([2; 16]));
+        assert_eq!(state.preferred("strings.loc"), Some(AssetUuid([2; 16])));
+    }
+
+    #[test]
+    fn only_localized_paths_are_probed_and_probes_are_released() {
+        let mut state = IndirectionState::default();
+        assert!(state.request("tree.png", LoadHandle(1)).is_empty());
+        let probes = state.request("strings.loc", LoadHandle(2));
+        assert_eq!(probes, vec!["strings.en.loc"]);
+        state.add_probe(probes[0].clone(), LoadHandle(3));
+        assert!(state.request("strings.loc", LoadHandle(2)).is_empty());
+
+        assert!(state.remove_ref(LoadHandle(2)).is_empty());
+        assert_eq!(state.remove_ref(LoadHandle(2)), vec![LoadHandle(3)]);
+        assert!(state.requested.get("strings.loc").is_none());
+    }
+}
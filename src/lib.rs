@@ -1,16 +1,40 @@
+pub mod animation;
+mod artifact;
 mod asset_server;
 mod asset_type_registry;
 mod assets;
+pub mod audio;
+pub mod font;
 pub mod image;
+mod importers;
+mod indirection;
 mod load_request;
 mod loader;
+pub mod localization;
+pub mod mesh;
+pub mod packfile;
+pub mod pipeline;
+mod reflect_importer;
+pub mod scene;
+mod serde_importer;
+pub mod shader;
+pub mod sprite_sheet;
+mod table;
+pub mod texture_atlas;
+pub mod tiled;
 
+pub use artifact::*;
 pub use asset_server::*;
 use asset_type_registry::*;
 pub use assets::*;
+pub use importers::*;
+pub use indirection::{with_suffix, LocaleResolver, VariantResolver, VariantSuffixResolver};
 pub use load_request::*;
 pub use loader::*;
+pub use reflect_importer::*;
+pub use serde_importer::*;
 use std::path::PathBuf;
+pub use table::*;
 
 /// The names of asset stages in an App Schedule
 pub mod stage {
@@ -22,6 +46,93 @@ pub mod prelude {
     pub use crate::{AddAsset, AssetEvent, AssetServer, Assets};
 }
 pub use atelier_core::AssetTypeId;
+pub use bevy_atelier_derive::Importer;
+
+/// Attribute errors of `#[derive(Importer)]`. The first example compiles, each of the others
+/// changes one attribute so that it doesn't.
+///
+/// ```
+/// # use bevy_atelier::Importer;
+/// # use serde::{Deserialize, Serialize};
+/// # use type_uuid::TypeUuid;
+/// #[derive(TypeUuid, Serialize, Deserialize, Importer)]
+/// #[uuid = "7bd55ed5-42d3-498e-905c-3c6d5ba5ab6e"]
+/// #[importer(extension = "item", format = "json", version = 2, name = "ItemLoader")]
+/// pub struct Item;
+/// ```
+///
+/// Extensions can't contain a `.`, as importers are chosen by the last extension of a file:
+///
+/// ```compile_fail
+/// # use bevy_atelier::Importer;
+/// # use serde::{Deserialize, Serialize};
+/// # use type_uuid::TypeUuid;
+/// #[derive(TypeUuid, Serialize, Deserialize, Importer)]
+/// #[uuid = "7bd55ed5-42d3-498e-905c-3c6d5ba5ab6e"]
+/// #[importer(extension = "item.ron")]
+/// pub struct Item;
+/// ```
+///
+/// ```compile_fail
+/// # use bevy_atelier::Importer;
+/// # use serde::{Deserialize, Serialize};
+/// # use type_uuid::TypeUuid;
+/// #[derive(TypeUuid, Serialize, Deserialize, Importer)]
+/// #[uuid = "7bd55ed5-42d3-498e-905c-3c6d5ba5ab6e"]
+/// #[importer(format = "yaml")]
+/// pub struct Item;
+/// ```
+///
+/// ```compile_fail
+/// # use bevy_atelier::Importer;
+/// # use serde::{Deserialize, Serialize};
+/// # use type_uuid::TypeUuid;
+/// #[derive(TypeUuid, Serialize, Deserialize, Importer)]
+/// #[uuid = "7bd55ed5-42d3-498e-905c-3c6d5ba5ab6e"]
+/// #[importer(extension = 5)]
+/// pub struct Item;
+/// ```
+///
+/// ```compile_fail
+/// # use bevy_atelier::Importer;
+/// # use serde::{Deserialize, Serialize};
+/// # use type_uuid::TypeUuid;
+/// #[derive(TypeUuid, Serialize, Deserialize, Importer)]
+/// #[uuid = "7bd55ed5-42d3-498e-905c-3c6d5ba5ab6e"]
+/// #[importer(uuid = "not a uuid")]
+/// pub struct Item;
+/// ```
+///
+/// ```compile_fail
+/// # use bevy_atelier::Importer;
+/// # use serde::{Deserialize, Serialize};
+/// # use type_uuid::TypeUuid;
+/// #[derive(TypeUuid, Serialize, Deserialize, Importer)]
+/// #[uuid = "7bd55ed5-42d3-498e-905c-3c6d5ba5ab6e"]
+/// #[importer(compression = "zstd")]
+/// pub struct Item;
+/// ```
+///
+/// ```compile_fail
+/// # use bevy_atelier::Importer;
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Serialize, Deserialize, Importer)]
+/// #[importer(extension = "item")]
+/// pub struct Item<T>(T);
+/// ```
+#[cfg(doctest)]
+pub struct DeriveImporterErrors;
+
+/// Paths used by code generated by `#[derive(Importer)]`
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::xor_uuid;
+    pub use atelier_importer;
+    pub use futures_core;
+    pub use futures_io;
+    pub use futures_util;
+    pub use type_uuid;
+}
 use atelier_loader::storage::{AtomicHandleAllocator, LoadHandle};
 
 use bevy_app::{prelude::Plugin, AppBuilder};
@@ -43,7 +154,15 @@ impl Plugin for AssetPlugin {
             .get::<AssetServerSettings>()
             .map(|s| (*s).clone())
             .unwrap_or_default();
-        let asset_server = AssetServer::new(&settings).unwrap();
+        let asset_server = AssetServer::new(&settings).unwrap_or_else(|err| {
+            bevy_log::error!(
+                "failed to start the asset server, no assets will load: {:?}",
+                err
+            );
+            // Without layers there is nothing to open, so this can't fail. Layers can still be pushed later.
+            AssetServer::new(&AssetServerSettings::Layered(PackfileSettings::default()))
+                .expect("an asset server without packfiles can't fail to start")
+        });
         app.register_type::<LoadHandle>()
             .init_resource::<AssetTypeRegistry>()
             .add_resource(asset_server)
@@ -57,6 +176,27 @@ impl Plugin for AssetPlugin {
                 stage::ASSET_EVENTS,
                 SystemStage::parallel(),
             )
-            .add_system_to_stage(stage::LOAD_ASSETS, AssetServer::process_system.system());
+            .add_system_to_stage(stage::LOAD_ASSETS, AssetServer::process_system.system())
+            // Scenes are validated against the app's type registry, so their importer can't be a default one
+            .add_importer_with_extensions::<scene::SceneImporter>();
     }
 }
+
+/// Deserializes the data of an imported asset from the artifact the loader would receive
+#[cfg(test)]
+pub(crate) fn imported_data<T: serde::de::DeserializeOwned>(
+    asset: &atelier_importer::ImportedAsset,
+) -> T {
+    let mut scratch = Vec::new();
+    let artifact = atelier_importer::SerializedAsset::create(
+        asset.id,
+        Vec::new(),
+        Vec::new(),
+        &*asset.asset_data,
+        atelier_core::CompressionType::None,
+        &mut scratch,
+    )
+    .unwrap()
+    .data;
+    bincode::deserialize(&artifact).unwrap()
+}
@@ -0,0 +1,562 @@
+use crate::NamedState;
+use atelier_importer::{AsyncImporter, Error, ImportOp, ImportedAsset, ImporterValue, Result};
+use futures_core::future::BoxFuture;
+use futures_io::AsyncRead;
+use futures_util::AsyncReadExt;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+use thiserror::Error;
+use type_uuid::*;
+
+/// A piece of a parsed message
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+enum Segment {
+    Text(String),
+    /// `{name}`, replaced by the argument
+    Arg(String),
+    /// `#` inside a plural case, replaced by the plural argument
+    Count,
+    /// `{name, plural, =0 {...} one {...} other {...}}`
+    Plural {
+        arg: String,
+        cases: Vec<(String, Vec<Segment>)>,
+    },
+    /// `{name, select, male {...} other {...}}`
+    Select {
+        arg: String,
+        cases: Vec<(String, Vec<Segment>)>,
+    },
+}
+
+/// An ICU-style message, parsed on import
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Message {
+    pub source: String,
+    segments: Vec<Segment>,
+}
+
+/// A value substituted into a [Message]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MessageArg<'a> {
+    Str(&'a str),
+    Number(f64),
+}
+
+impl<'a> From<&'a str> for MessageArg<'a> {
+    fn from(value: &'a str) -> Self {
+        MessageArg::Str(value)
+    }
+}
+
+impl<'a> From<&'a String> for MessageArg<'a> {
+    fn from(value: &'a String) -> Self {
+        MessageArg::Str(value)
+    }
+}
+
+macro_rules! impl_number_arg {
+    ($($ty:ty),*) => {
+        $(impl From<$ty> for MessageArg<'_> {
+            fn from(value: $ty) -> Self {
+                MessageArg::Number(value as f64)
+            }
+        })*
+    };
+}
+
+impl_number_arg!(i32, i64, u32, u64, usize, f32, f64);
+
+impl fmt::Display for MessageArg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageArg::Str(value) => f.write_str(value),
+            MessageArg::Number(value) if value.fract() == 0.0 => write!(f, "{}", *value as i64),
+            MessageArg::Number(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// The CLDR plural category of `n` in a locale, for the languages whose rules differ from English
+fn plural_category(locale: &str, n: f64) -> &'static str {
+    let language = locale.split(|c| c == '-' || c == '_').next().unwrap_or("");
+    let integer = n.fract() == 0.0;
+    let i = n.abs() as u64;
+    match language {
+        "ja" | "ko" | "zh" | "th" | "vi" | "id" => "other",
+        "fr" | "pt" if n.abs() < 2.0 => "one",
+        "ru" | "uk" | "pl" if integer => {
+            if language != "pl" && i % 10 == 1 && i % 100 != 11 {
+                "one"
+            } else if language == "pl" && i == 1 {
+                "one"
+            } else if (2..=4).contains(&(i % 10)) && !(12..=14).contains(&(i % 100)) {
+                "few"
+            } else {
+                "many"
+            }
+        }
+        "ru" | "uk" | "pl" => "other",
+        _ if integer && i == 1 => "one",
+        _ => "other",
+    }
+}
+
+impl Message {
+    /// Formats the message, leaving `{name}` in place of missing arguments
+    pub fn format(&self, locale: &str, args: &[(&str, MessageArg)]) -> String {
+        let mut output = String::new();
+        format_segments(&self.segments, locale, args, None, &mut output);
+        output
+    }
+}
+
+fn format_segments(
+    segments: &[Segment],
+    locale: &str,
+    args: &[(&str, MessageArg)],
+    count: Option<MessageArg>,
+    output: &mut String,
+) {
+    use std::fmt::Write;
+
+    let arg = |name: &str| {
+        args.iter()
+            .find(|(arg, _)| *arg == name)
+            .map(|(_, value)| *value)
+    };
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => output.push_str(text),
+            Segment::Arg(name) => match arg(name) {
+                Some(value) => {
+                    let _ = write!(output, "{}", value);
+                }
+                None => {
+                    let _ = write!(output, "{{{}}}", name);
+                }
+            },
+            Segment::Count => match count {
+                Some(value) => {
+                    let _ = write!(output, "{}", value);
+                }
+                None => output.push('#'),
+            },
+            Segment::Plural { arg: name, cases } => {
+                let value = arg(name);
+                let n = match value {
+                    Some(MessageArg::Number(n)) => n,
+                    Some(MessageArg::Str(s)) => s.parse().unwrap_or(0.0),
+                    None => 0.0,
+                };
+                let exact = format!("={}", MessageArg::Number(n));
+                let category = plural_category(locale, n);
+                let case = cases
+                    .iter()
+                    .find(|(key, _)| *key == exact)
+                    .or_else(|| cases.iter().find(|(key, _)| key == category))
+                    .or_else(|| cases.iter().find(|(key, _)| key == "other"));
+                if let Some((_, segments)) = case {
+                    format_segments(segments, locale, args, value, output);
+                }
+            }
+            Segment::Select { arg: name, cases } => {
+                let value = arg(name).map(|value| value.to_string());
+                let case = cases
+                    .iter()
+                    .find(|(key, _)| Some(key) == value.as_ref())
+                    .or_else(|| cases.iter().find(|(key, _)| key == "other"));
+                if let Some((_, segments)) = case {
+                    format_segments(segments, locale, args, count, output);
+                }
+            }
+        }
+    }
+}
+
+/// A syntax error in a message
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Message {key:?}, at character {offset}: {message}")]
+pub struct MessageSyntaxError {
+    pub key: String,
+    pub offset: usize,
+    pub message: String,
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    position: usize,
+    key: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, message: &str) -> std::result::Result<T, MessageSyntaxError> {
+        Err(MessageSyntaxError {
+            key: self.key.to_string(),
+            offset: self.position,
+            message: message.to_string(),
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let start = self.position;
+        while self.peek().map_or(false, |c| {
+            !c.is_whitespace() && c != ',' && c != '{' && c != '}'
+        }) {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    /// Parses text and arguments up to an unmatched `}` or the end of the message
+    fn segments(
+        &mut self,
+        in_plural: bool,
+    ) -> std::result::Result<Vec<Segment>, MessageSyntaxError> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            match c {
+                '\'' => {
+                    self.position += 1;
+                    match self.peek() {
+                        // '' is a literal apostrophe
+                        Some('\'') => {
+                            text.push('\'');
+                            self.position += 1;
+                        }
+                        // '{...}' and '#' are quoted literally
+                        Some('{') | Some('}') | Some('#') => {
+                            while let Some(c) = self.peek() {
+                                self.position += 1;
+                                if c == '\'' {
+                                    break;
+                                }
+                                text.push(c);
+                            }
+                        }
+                        _ => text.push('\''),
+                    }
+                }
+                '{' => {
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    self.position += 1;
+                    segments.push(self.argument(in_plural)?);
+                }
+                '}' => break,
+                '#' if in_plural => {
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    self.position += 1;
+                    segments.push(Segment::Count);
+                }
+                c => {
+                    text.push(c);
+                    self.position += 1;
+                }
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(segments)
+    }
+
+    /// Parses an argument after its opening `{`, including the closing `}`. `#` in the cases of a
+    /// select nested in a plural is still the plural's count.
+    fn argument(&mut self, in_plural: bool) -> std::result::Result<Segment, MessageSyntaxError> {
+        self.skip_whitespace();
+        let name = self.word();
+        if name.is_empty() {
+            return self.error("expected an argument name");
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            Some('}') => {
+                self.position += 1;
+                return Ok(Segment::Arg(name));
+            }
+            Some(',') => self.position += 1,
+            _ => return self.error("expected ',' or '}' after the argument name"),
+        }
+        self.skip_whitespace();
+        let kind = self.word();
+        self.skip_whitespace();
+        match kind.as_str() {
+            "number" | "date" | "time" => {
+                // Styles are accepted but not applied
+                while let Some(c) = self.peek() {
+                    self.position += 1;
+                    if c == '}' {
+                        return Ok(Segment::Arg(name));
+                    }
+                }
+                self.error("unterminated argument")
+            }
+            "plural" | "select" => {
+                if self.peek() != Some(',') {
+                    return self.error("expected ',' before the cases");
+                }
+                self.position += 1;
+                let mut cases = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some('}') => {
+                            self.position += 1;
+                            break;
+                        }
+                        None => return self.error("unterminated argument"),
+                        _ => {}
+                    }
+                    let key = self.word();
+                    if key.is_empty() {
+                        return self.error("expected a case");
+                    }
+                    self.skip_whitespace();
+                    if self.peek() != Some('{') {
+                        return self.error("expected '{' after the case");
+                    }
+                    self.position += 1;
+                    let segments = self.segments(in_plural || kind == "plural")?;
+                    if self.peek() != Some('}') {
+                        return self.error("unterminated case");
+                    }
+                    self.position += 1;
+                    cases.push((key, segments));
+                }
+                if !cases.iter().any(|(key, _)| key == "other") {
+                    return self.error("missing an 'other' case");
+                }
+                Ok(if kind == "plural" {
+                    Segment::Plural { arg: name, cases }
+                } else {
+                    Segment::Select { arg: name, cases }
+                })
+            }
+            _ => self.error("unknown argument type"),
+        }
+    }
+}
+
+impl Message {
+    pub fn parse(key: &str, source: &str) -> std::result::Result<Self, MessageSyntaxError> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            position: 0,
+            key,
+        };
+        let segments = parser.segments(false)?;
+        if parser.peek().is_some() {
+            return parser.error("unmatched '}'");
+        }
+        Ok(Message {
+            source: source.to_string(),
+            segments,
+        })
+    }
+}
+
+/// Messages of one locale, keyed by message id.
+///
+/// Load tables through the locale-neutral path, e.g. `strings.loc` for `strings.en.loc`
+/// and `strings.fr.loc`, to get the one for the [current locale](crate::AssetServer::set_locale).
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[uuid = "dae450b6-0ede-48dc-8bd5-2e6001b57fda"]
+pub struct StringTable {
+    pub messages: HashMap<String, Message>,
+}
+
+impl StringTable {
+    pub fn get(&self, key: &str) -> Option<&Message> {
+        self.messages.get(key)
+    }
+
+    /// Formats a message with plural rules for `locale`
+    pub fn format(&self, locale: &str, key: &str, args: &[(&str, MessageArg)]) -> Option<String> {
+        self.get(key).map(|message| message.format(locale, args))
+    }
+}
+
+/// Errors that occur while importing a string table
+#[derive(Error, Debug)]
+pub enum StringTableError {
+    #[error("Invalid string table: {0}")]
+    Ron(#[from] ron::Error),
+    #[error("{}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n"))]
+    Syntax(Vec<MessageSyntaxError>),
+}
+
+/// Imports a RON map of message ids to ICU-style messages as a [StringTable].
+///
+/// Registered for `.loc` files, which are written in RON.
+#[derive(TypeUuid, Default)]
+#[uuid = "b2b7531c-1f3b-4527-9d8d-b9faa1861c4b"]
+pub struct StringTableImporter;
+
+impl AsyncImporter for StringTableImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        1
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = ();
+
+    type State = NamedState;
+
+    /// Parses every message, reporting all syntax errors at once.
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        _options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        Box::pin(async move {
+            let id = state.id("asset");
+            let mut text = String::new();
+            source.read_to_string(&mut text).await?;
+            let sources: HashMap<String, String> = ron::de::from_str(&text)
+                .map_err(|e| Error::Boxed(Box::new(StringTableError::from(e))))?;
+            let mut table = StringTable::default();
+            let mut errors = Vec::new();
+            for (key, source) in sources {
+                match Message::parse(&key, &source) {
+                    Ok(message) => {
+                        table.messages.insert(key, message);
+                    }
+                    Err(err) => errors.push(err),
+                }
+            }
+            if !errors.is_empty() {
+                errors.sort_by(|a, b| a.key.cmp(&b.key));
+                return Err(Error::Boxed(Box::new(StringTableError::Syntax(errors))));
+            }
+            Ok(ImporterValue {
+                assets: vec![ImportedAsset {
+                    id,
+                    search_tags: vec![],
+                    build_deps: vec![],
+                    load_deps: vec![],
+                    build_pipeline: None,
+                    asset_data: Box::new(table),
+                }],
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str, locale: &str, args: &[(&str, MessageArg)]) -> String {
+        Message::parse("key", source).unwrap().format(locale, args)
+    }
+
+    fn parse_error(source: &str) -> MessageSyntaxError {
+        Message::parse("key", source).unwrap_err()
+    }
+
+    #[test]
+    fn arguments() {
+        assert_eq!(
+            format("Hello {name}!", "en", &[("name", "Ann".into())]),
+            "Hello Ann!"
+        );
+        assert_eq!(format("Hello {name}!", "en", &[]), "Hello {name}!");
+        assert_eq!(
+            format("{n, number} left", "en", &[("n", 2.5.into())]),
+            "2.5 left"
+        );
+        assert_eq!(format("Issue #5", "en", &[]), "Issue #5");
+    }
+
+    #[test]
+    fn plurals() {
+        let source = "{n, plural, =0 {no items} one {# item} other {# items}}";
+        assert_eq!(format(source, "en", &[("n", 0.into())]), "no items");
+        assert_eq!(format(source, "en", &[("n", 1.into())]), "1 item");
+        assert_eq!(format(source, "en", &[("n", 5.into())]), "5 items");
+        assert_eq!(format(source, "fr", &[("n", 1.5.into())]), "1.5 item");
+        assert_eq!(format(source, "ja", &[("n", 1.into())]), "1 items");
+    }
+
+    #[test]
+    fn plural_categories() {
+        assert_eq!(plural_category("en", 1.0), "one");
+        assert_eq!(plural_category("en-US", 2.0), "other");
+        assert_eq!(plural_category("fr", 0.0), "one");
+        assert_eq!(plural_category("ru", 21.0), "one");
+        assert_eq!(plural_category("ru", 11.0), "many");
+        assert_eq!(plural_category("ru_RU", 3.0), "few");
+        assert_eq!(plural_category("ru", 1.5), "other");
+        assert_eq!(plural_category("pl", 21.0), "many");
+        assert_eq!(plural_category("pl", 22.0), "few");
+    }
+
+    #[test]
+    fn selects() {
+        let source = "{g, select, female {She} male {He} other {They}} left";
+        assert_eq!(format(source, "en", &[("g", "female".into())]), "She left");
+        assert_eq!(format(source, "en", &[("g", "robot".into())]), "They left");
+        assert_eq!(format(source, "en", &[]), "They left");
+    }
+
+    #[test]
+    fn count_inside_nested_select() {
+        let source = "{n, plural, other {{g, select, other {# things}}}}";
+        assert_eq!(
+            format(source, "en", &[("n", 3.into()), ("g", "x".into())]),
+            "3 things"
+        );
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(
+            format("It''s '{literal}' text", "en", &[]),
+            "It's {literal} text"
+        );
+        assert_eq!(
+            format("{n, plural, other {'#' #}}", "en", &[("n", 3.into())]),
+            "# 3"
+        );
+        assert_eq!(format("don't", "en", &[]), "don't");
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let err = parse_error("oops }");
+        assert_eq!(err.offset, 5);
+        assert_eq!(err.message, "unmatched '}'");
+        assert_eq!(err.key, "key");
+        assert_eq!(
+            parse_error("{n, plural, one {x}}").message,
+            "missing an 'other' case"
+        );
+        assert_eq!(parse_error("{n, percent}").message, "unknown argument type");
+        assert_eq!(parse_error("{}").message, "expected an argument name");
+        assert_eq!(
+            parse_error("{n, plural, other {x}").message,
+            "unterminated argument"
+        );
+    }
+}
@@ -0,0 +1,610 @@
+use crate::{
+    image::{ColorSpace, Image, PixelBuffer},
+    NamedState,
+};
+use atelier_core::{AssetRef, AssetUuid};
+use atelier_importer::{
+    AsyncImporter, Error, ImportOp, ImportedAsset, ImporterValue, Result, SerdeObj,
+};
+use futures_core::future::BoxFuture;
+use futures_io::AsyncRead;
+use futures_util::AsyncReadExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use thiserror::Error;
+use type_uuid::*;
+
+/// A single draw's worth of vertex data with one material
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct MeshPrimitive {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    /// Triangle list indices, or `None` if every three vertices form a triangle
+    pub indices: Option<Vec<u32>>,
+    pub material: Option<AssetUuid>,
+}
+
+/// A mesh made of one or more primitives. Its materials are loaded along with it.
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[uuid = "18aecfe6-892f-400b-8f99-2cacb5482c24"]
+pub struct Mesh {
+    pub name: Option<String>,
+    pub primitives: Vec<MeshPrimitive>,
+}
+
+/// How a material's alpha is interpreted
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    /// Fully transparent below the cutoff, opaque above it
+    Mask(f32),
+    Blend,
+}
+
+impl Default for AlphaMode {
+    fn default() -> Self {
+        AlphaMode::Opaque
+    }
+}
+
+/// A metallic-roughness PBR material. Its textures are loaded along with it.
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[uuid = "ede4c49e-17f1-49fe-acc6-c379201b2932"]
+pub struct Material {
+    pub name: Option<String>,
+    pub base_color: [f32; 4],
+    pub base_color_texture: Option<AssetUuid>,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub metallic_roughness_texture: Option<AssetUuid>,
+    pub normal_texture: Option<AssetUuid>,
+    pub occlusion_texture: Option<AssetUuid>,
+    pub emissive: [f32; 3],
+    pub emissive_texture: Option<AssetUuid>,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            name: None,
+            base_color: [1.0; 4],
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive: [0.0; 3],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+impl Material {
+    pub fn textures(&self) -> impl Iterator<Item = AssetUuid> {
+        vec![
+            self.base_color_texture,
+            self.metallic_roughness_texture,
+            self.normal_texture,
+            self.occlusion_texture,
+            self.emissive_texture,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+/// Every mesh in a model file. Loading a model loads its meshes, and through them its materials and textures.
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[uuid = "0cba6452-0d5a-418a-9d9d-11daad3fe85e"]
+pub struct Model {
+    pub meshes: Vec<AssetUuid>,
+}
+
+/// Errors that occur while importing a model
+#[derive(Error, Debug)]
+pub enum ModelImportError {
+    #[error("Failed to read glTF: {0}")]
+    Gltf(#[from] gltf::Error),
+    #[error("Failed to read OBJ: {0}")]
+    Obj(#[from] tobj::LoadError),
+    #[error("Mesh primitive {mesh}/{primitive} has no positions.")]
+    MissingPositions { mesh: usize, primitive: usize },
+}
+
+fn asset(id: AssetUuid, load_deps: Vec<AssetUuid>, asset_data: Box<dyn SerdeObj>) -> ImportedAsset {
+    ImportedAsset {
+        id,
+        search_tags: vec![],
+        build_deps: vec![],
+        load_deps: load_deps.into_iter().map(AssetRef::Uuid).collect(),
+        build_pipeline: None,
+        asset_data,
+    }
+}
+
+/// Collects the imported assets, keeping the UUIDs of names seen in earlier imports
+struct ModelBuilder<'a> {
+    state: &'a mut NamedState,
+    names: Vec<String>,
+    assets: Vec<ImportedAsset>,
+}
+
+impl<'a> ModelBuilder<'a> {
+    fn new(state: &'a mut NamedState) -> Self {
+        ModelBuilder {
+            state,
+            names: Vec::new(),
+            assets: Vec::new(),
+        }
+    }
+
+    fn id(&mut self, name: String) -> AssetUuid {
+        let id = self.state.id(&name);
+        self.names.push(name);
+        id
+    }
+
+    fn finish(mut self, meshes: Vec<AssetUuid>) -> ImporterValue {
+        let id = self.id("model".to_string());
+        let model = Model { meshes };
+        // A path resolves to the first asset imported from it, so the model goes first
+        self.assets
+            .insert(0, asset(id, model.meshes.clone(), Box::new(model)));
+        self.state
+            .retain_names(self.names.iter().map(String::as_str));
+        ImporterValue {
+            assets: self.assets,
+        }
+    }
+}
+
+fn gltf_image(data: gltf::image::Data, color_space: ColorSpace) -> Image {
+    use gltf::image::Format;
+    let (width, height) = (data.width, data.height);
+    let wide = |pixels: &[u8]| -> Vec<u16> {
+        pixels
+            .chunks_exact(2)
+            .map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]))
+            .collect()
+    };
+    let swizzle = |pixels: Vec<u8>, channels: usize| -> Vec<u8> {
+        let mut pixels = pixels;
+        for pixel in pixels.chunks_exact_mut(channels) {
+            pixel.swap(0, 2);
+        }
+        pixels
+    };
+    let mut image = match data.format {
+        Format::R8 => Image::Luma8(PixelBuffer::new(width, height, data.pixels)),
+        Format::R8G8 => Image::LumaA8(PixelBuffer::new(width, height, data.pixels)),
+        Format::R8G8B8 => Image::Rgb8(PixelBuffer::new(width, height, data.pixels)),
+        Format::R8G8B8A8 => Image::Rgba8(PixelBuffer::new(width, height, data.pixels)),
+        Format::B8G8R8 => Image::Rgb8(PixelBuffer::new(width, height, swizzle(data.pixels, 3))),
+        Format::B8G8R8A8 => Image::Rgba8(PixelBuffer::new(width, height, swizzle(data.pixels, 4))),
+        Format::R16 => Image::Luma16(PixelBuffer::new(width, height, wide(&data.pixels))),
+        Format::R16G16 => Image::LumaA16(PixelBuffer::new(width, height, wide(&data.pixels))),
+        Format::R16G16B16 => Image::Rgb16(PixelBuffer::new(width, height, wide(&data.pixels))),
+        Format::R16G16B16A16 => Image::Rgba16(PixelBuffer::new(width, height, wide(&data.pixels))),
+    };
+    image.set_color_space(color_space);
+    image
+}
+
+fn import_gltf(
+    bytes: &[u8],
+    state: &mut NamedState,
+) -> std::result::Result<ImporterValue, ModelImportError> {
+    let (document, buffers, images) = gltf::import_slice(bytes)?;
+    let mut builder = ModelBuilder::new(state);
+
+    // Color textures are stored as sRGB, everything else is linear data
+    let mut srgb_images = HashSet::new();
+    for material in document.materials() {
+        let pbr = material.pbr_metallic_roughness();
+        let color_textures = pbr
+            .base_color_texture()
+            .map(|info| info.texture())
+            .into_iter()
+            .chain(material.emissive_texture().map(|info| info.texture()));
+        for texture in color_textures {
+            srgb_images.insert(texture.source().index());
+        }
+    }
+    let mut image_ids = Vec::new();
+    for (index, data) in images.into_iter().enumerate() {
+        let id = builder.id(format!("texture/{}", index));
+        let color_space = if srgb_images.contains(&index) {
+            ColorSpace::Srgb
+        } else {
+            ColorSpace::Linear
+        };
+        builder
+            .assets
+            .push(asset(id, vec![], Box::new(gltf_image(data, color_space))));
+        image_ids.push(id);
+    }
+
+    let mut material_ids = Vec::new();
+    for (index, material) in document.materials().enumerate() {
+        let texture_id = |texture: gltf::Texture| image_ids[texture.source().index()];
+        let pbr = material.pbr_metallic_roughness();
+        let emissive = material.emissive_factor();
+        let asset_data = Material {
+            name: material.name().map(str::to_string),
+            base_color: pbr.base_color_factor(),
+            base_color_texture: pbr.base_color_texture().map(|t| texture_id(t.texture())),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            metallic_roughness_texture: pbr
+                .metallic_roughness_texture()
+                .map(|t| texture_id(t.texture())),
+            normal_texture: material.normal_texture().map(|t| texture_id(t.texture())),
+            occlusion_texture: material
+                .occlusion_texture()
+                .map(|t| texture_id(t.texture())),
+            emissive: [emissive[0], emissive[1], emissive[2]],
+            emissive_texture: material.emissive_texture().map(|t| texture_id(t.texture())),
+            alpha_mode: match material.alpha_mode() {
+                gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                gltf::material::AlphaMode::Mask => {
+                    AlphaMode::Mask(material.alpha_cutoff().unwrap_or(0.5))
+                }
+                gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+            },
+            double_sided: material.double_sided(),
+        };
+        let id = builder.id(format!("material/{}", index));
+        builder.assets.push(asset(
+            id,
+            asset_data.textures().collect(),
+            Box::new(asset_data),
+        ));
+        material_ids.push(id);
+    }
+
+    let mut mesh_ids = Vec::new();
+    for mesh in document.meshes() {
+        let mut primitives = Vec::new();
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<_> = reader
+                .read_positions()
+                .ok_or(ModelImportError::MissingPositions {
+                    mesh: mesh.index(),
+                    primitive: primitive.index(),
+                })?
+                .collect();
+            primitives.push(MeshPrimitive {
+                positions,
+                normals: reader
+                    .read_normals()
+                    .map(|normals| normals.collect())
+                    .unwrap_or_default(),
+                uvs: reader
+                    .read_tex_coords(0)
+                    .map(|uvs| uvs.into_f32().collect())
+                    .unwrap_or_default(),
+                indices: reader
+                    .read_indices()
+                    .map(|indices| indices.into_u32().collect()),
+                material: primitive
+                    .material()
+                    .index()
+                    .map(|index| material_ids[index]),
+            });
+        }
+        let asset_data = Mesh {
+            name: mesh.name().map(str::to_string),
+            primitives,
+        };
+        let materials: HashSet<_> = asset_data
+            .primitives
+            .iter()
+            .filter_map(|primitive| primitive.material)
+            .collect();
+        let id = builder.id(format!("mesh/{}", mesh.index()));
+        builder.assets.push(asset(
+            id,
+            materials.into_iter().collect(),
+            Box::new(asset_data),
+        ));
+        mesh_ids.push(id);
+    }
+    Ok(builder.finish(mesh_ids))
+}
+
+/// Imports OBJ geometry. Materials are not imported, as `.mtl` files are referenced by relative path.
+fn import_obj(
+    bytes: &[u8],
+    state: &mut NamedState,
+) -> std::result::Result<ImporterValue, ModelImportError> {
+    let options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ..Default::default()
+    };
+    let (models, _) = tobj::load_obj_buf(&mut std::io::Cursor::new(bytes), &options, |_| {
+        Err(tobj::LoadError::OpenFileFailed)
+    })?;
+    let mut builder = ModelBuilder::new(state);
+    let mut mesh_ids = Vec::new();
+    for (index, model) in models.into_iter().enumerate() {
+        let mesh = model.mesh;
+        let asset_data = Mesh {
+            name: Some(model.name),
+            primitives: vec![MeshPrimitive {
+                positions: mesh
+                    .positions
+                    .chunks_exact(3)
+                    .map(|p| [p[0], p[1], p[2]])
+                    .collect(),
+                normals: mesh
+                    .normals
+                    .chunks_exact(3)
+                    .map(|n| [n[0], n[1], n[2]])
+                    .collect(),
+                uvs: mesh
+                    .texcoords
+                    .chunks_exact(2)
+                    .map(|uv| [uv[0], uv[1]])
+                    .collect(),
+                indices: Some(mesh.indices),
+                material: None,
+            }],
+        };
+        let id = builder.id(format!("mesh/{}", index));
+        builder.assets.push(asset(id, vec![], Box::new(asset_data)));
+        mesh_ids.push(id);
+    }
+    Ok(builder.finish(mesh_ids))
+}
+
+/// Imports glTF 2.0 files (`.gltf` with embedded buffers, or `.glb`) as a [Model] with a [Mesh]
+/// per mesh, a [Material] per material and an [Image] per texture image
+#[derive(TypeUuid, Default)]
+#[uuid = "68a3bda9-b273-4752-896b-184b509d4cee"]
+pub struct GltfImporter;
+
+/// Imports Wavefront OBJ files as a [Model] with a [Mesh] per object
+#[derive(TypeUuid, Default)]
+#[uuid = "c3bcd3fc-6372-4897-98a1-7e21511daab1"]
+pub struct ObjImporter;
+
+fn import_model<'a>(
+    source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+    state: &'a mut NamedState,
+    import: fn(&[u8], &mut NamedState) -> std::result::Result<ImporterValue, ModelImportError>,
+) -> BoxFuture<'a, Result<ImporterValue>> {
+    Box::pin(async move {
+        let mut bytes = Vec::new();
+        source.read_to_end(&mut bytes).await?;
+        import(&bytes, state).map_err(|e| Error::Boxed(Box::new(e)))
+    })
+}
+
+impl AsyncImporter for GltfImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        2
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = ();
+
+    type State = NamedState;
+
+    /// Reads the glTF document and produces its meshes, materials and textures.
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        _options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        import_model(source, state, import_gltf)
+    }
+}
+
+impl AsyncImporter for ObjImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        2
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = ();
+
+    type State = NamedState;
+
+    /// Reads the OBJ file and produces its meshes.
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        _options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        import_model(source, state, import_obj)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imported_data;
+
+    const TRIANGLES: &str = "o first
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f 1 2 3
+o second
+v 0 0 1
+v 1 0 1
+v 0 1 1
+f 4 5 6
+";
+
+    #[test]
+    fn model_is_the_first_asset() {
+        let mut state = NamedState::default();
+        let value = import_obj(TRIANGLES.as_bytes(), &mut state).unwrap();
+        assert_eq!(value.assets.len(), 3);
+        assert_eq!(value.assets[0].asset_data.uuid(), Model::UUID);
+        let meshes: Vec<AssetRef> = value.assets[1..]
+            .iter()
+            .map(|asset| AssetRef::Uuid(asset.id))
+            .collect();
+        assert_eq!(value.assets[0].load_deps, meshes);
+    }
+
+    fn png_uri(width: u32, height: u32) -> String {
+        let image = ::image::DynamicImage::ImageRgba8(::image::RgbaImage::new(width, height));
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image
+            .write_to(&mut bytes, ::image::ImageFormat::Png)
+            .unwrap();
+        format!(
+            "data:image/png;base64,{}",
+            base64::encode(bytes.into_inner())
+        )
+    }
+
+    /// A triangle with a masked, double-sided material that has a color and a normal texture
+    fn triangle_gltf() -> String {
+        let mut buffer = Vec::new();
+        for position in &[[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+            for component in position {
+                buffer.extend_from_slice(&component.to_le_bytes());
+            }
+        }
+        for index in &[0u16, 1, 2, 0] {
+            buffer.extend_from_slice(&index.to_le_bytes());
+        }
+        format!(
+            r#"{{
+  "asset": {{ "version": "2.0" }},
+  "buffers": [{{ "byteLength": 44, "uri": "data:application/octet-stream;base64,{buffer}" }}],
+  "bufferViews": [
+    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+    {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+  ],
+  "accessors": [
+    {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+       "min": [0, 0, 0], "max": [1, 1, 0] }},
+    {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+  ],
+  "images": [{{ "uri": "{color}" }}, {{ "uri": "{normal}" }}],
+  "textures": [{{ "source": 0 }}, {{ "source": 1 }}],
+  "materials": [{{
+    "name": "leaf",
+    "pbrMetallicRoughness": {{ "baseColorFactor": [1, 0.5, 0.25, 1], "baseColorTexture": {{ "index": 0 }} }},
+    "normalTexture": {{ "index": 1 }},
+    "alphaMode": "MASK",
+    "alphaCutoff": 0.25,
+    "doubleSided": true
+  }}],
+  "meshes": [{{ "name": "triangle", "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1, "material": 0 }}] }}]
+}}"#,
+            buffer = base64::encode(buffer),
+            color = png_uri(2, 1),
+            normal = png_uri(1, 2),
+        )
+    }
+
+    #[test]
+    fn imports_gltf_sub_assets() {
+        let mut state = NamedState::default();
+        let value = import_gltf(triangle_gltf().as_bytes(), &mut state).unwrap();
+        let uuids: Vec<_> = value
+            .assets
+            .iter()
+            .map(|asset| asset.asset_data.uuid())
+            .collect();
+        assert_eq!(
+            uuids,
+            vec![
+                Model::UUID,
+                Image::UUID,
+                Image::UUID,
+                Material::UUID,
+                Mesh::UUID
+            ]
+        );
+        let ids: Vec<_> = value.assets.iter().map(|asset| asset.id).collect();
+        assert_eq!(value.assets[0].load_deps, vec![AssetRef::Uuid(ids[4])]);
+        assert_eq!(value.assets[4].load_deps, vec![AssetRef::Uuid(ids[3])]);
+        assert_eq!(
+            value.assets[3].load_deps,
+            vec![AssetRef::Uuid(ids[1]), AssetRef::Uuid(ids[2])]
+        );
+
+        let color: Image = imported_data(&value.assets[1]);
+        let normal: Image = imported_data(&value.assets[2]);
+        assert_eq!(color.color_space(), ColorSpace::Srgb);
+        assert_eq!(normal.color_space(), ColorSpace::Linear);
+
+        let material: Material = imported_data(&value.assets[3]);
+        assert_eq!(material.name.as_deref(), Some("leaf"));
+        assert_eq!(material.base_color, [1.0, 0.5, 0.25, 1.0]);
+        assert_eq!(material.base_color_texture, Some(ids[1]));
+        assert_eq!(material.normal_texture, Some(ids[2]));
+        assert_eq!(material.alpha_mode, AlphaMode::Mask(0.25));
+        assert!(material.double_sided);
+
+        let mesh: Mesh = imported_data(&value.assets[4]);
+        assert_eq!(mesh.name.as_deref(), Some("triangle"));
+        let primitive = &mesh.primitives[0];
+        assert_eq!(
+            primitive.positions,
+            vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+        );
+        assert_eq!(primitive.indices, Some(vec![0, 1, 2]));
+        assert_eq!(primitive.material, Some(ids[3]));
+    }
+
+    #[test]
+    fn reimporting_keeps_ids() {
+        let mut state = NamedState::default();
+        let first = import_gltf(triangle_gltf().as_bytes(), &mut state).unwrap();
+        let second = import_gltf(triangle_gltf().as_bytes(), &mut state).unwrap();
+        let ids = |value: &ImporterValue| {
+            value
+                .assets
+                .iter()
+                .map(|asset| asset.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&first), ids(&second));
+
+        let mut state = NamedState::default();
+        let first = import_obj(TRIANGLES.as_bytes(), &mut state).unwrap();
+        let second = import_obj(TRIANGLES.as_bytes(), &mut state).unwrap();
+        assert_eq!(ids(&first), ids(&second));
+    }
+
+    #[test]
+    fn imports_obj_objects_as_meshes() {
+        let mut state = NamedState::default();
+        let value = import_obj(TRIANGLES.as_bytes(), &mut state).unwrap();
+        let meshes: Vec<Mesh> = value.assets[1..].iter().map(imported_data).collect();
+        let names: Vec<_> = meshes.iter().map(|mesh| mesh.name.as_deref()).collect();
+        assert_eq!(names, vec![Some("first"), Some("second")]);
+        assert_eq!(meshes[1].primitives[0].positions[0], [0.0, 0.0, 1.0]);
+        assert_eq!(meshes[1].primitives[0].indices, Some(vec![0, 1, 2]));
+    }
+}
@@ -0,0 +1,441 @@
+use super::{ArtifactCompression, PackfileError, PackfileKey, PackfileWriter};
+use crate::pipeline::{BuildPipelines, PipelineRunner};
+use atelier_core::{AssetMetadata, AssetRef, AssetTypeId, CompressionType};
+use atelier_importer::{BoxedImporter, ImportOp, SerdeObj, SerializedAsset};
+use bevy_log::*;
+use futures_util::io::AllowStdIo;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use type_uuid::TypeUuid;
+
+/// Errors that occur while building a packfile
+#[derive(Error, Debug)]
+pub enum PackfileBuildError {
+    #[error("Encountered an io error while building the packfile.")]
+    Io(#[from] io::Error),
+    #[error("Invalid path glob.")]
+    Pattern(#[from] glob::PatternError),
+    #[error("Failed to read import settings from {path:?}.")]
+    Meta { path: PathBuf, error: ron::Error },
+    #[error("Failed to import {path:?}: {message}")]
+    Import { path: PathBuf, message: String },
+    #[error("Failed to write the packfile.")]
+    Packfile(#[from] PackfileError),
+    #[error("Importer extension {0:?} contains a '.'; importers are chosen by the last extension of a file only.")]
+    InvalidExtension(String),
+}
+
+/// Packfile entries are keyed by their path relative to the asset directory, while importers
+/// reference other files by their path joined onto it. Makes such a path relative to the asset
+/// directory, with `.` and `..` resolved, so that it matches the entry.
+fn relative_ref(asset_dir: &Path, asset_ref: AssetRef) -> AssetRef {
+    match asset_ref {
+        AssetRef::Path(path) => {
+            let mut relative = PathBuf::new();
+            for component in path.strip_prefix(asset_dir).unwrap_or(&path).components() {
+                match component {
+                    Component::CurDir => {}
+                    Component::ParentDir => {
+                        relative.pop();
+                    }
+                    component => relative.push(component),
+                }
+            }
+            AssetRef::Path(relative)
+        }
+        asset_ref => asset_ref,
+    }
+}
+
+/// The importer settings the asset daemon stores next to each source file.
+/// Reusing the importer state keeps asset UUIDs identical to the ones the daemon assigned.
+#[derive(Deserialize)]
+struct SourceMeta {
+    importer_options: ron::Value,
+    importer_state: ron::Value,
+}
+
+/// Size statistics for one asset type in a [BuildReport]
+#[derive(Default, Debug, Clone)]
+pub struct TypeReport {
+    pub name: Option<String>,
+    pub count: usize,
+    pub bytes: u64,
+}
+
+/// A summary of what went into a packfile
+#[derive(Default, Debug)]
+pub struct BuildReport {
+    pub asset_count: usize,
+    pub total_bytes: u64,
+    pub types: HashMap<AssetTypeId, TypeReport>,
+    /// Source files that were skipped because no importer handles their extension
+    pub skipped: Vec<PathBuf>,
+}
+
+impl fmt::Display for BuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} assets, {} bytes", self.asset_count, self.total_bytes)?;
+        let mut types: Vec<_> = self.types.iter().collect();
+        types.sort_by(|(_, a), (_, b)| b.bytes.cmp(&a.bytes));
+        for (type_id, report) in types {
+            let name = match &report.name {
+                Some(name) => name.clone(),
+                None => uuid::Uuid::from_bytes(type_id.0).to_string(),
+            };
+            writeln!(
+                f,
+                "  {:<48} {:>8} assets {:>12} bytes",
+                name, report.count, report.bytes
+            )?;
+        }
+        if !self.skipped.is_empty() {
+            writeln!(f, "{} files skipped (no importer)", self.skipped.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Imports every asset in a directory and writes them into a packfile, without running the asset daemon.
+///
+/// The [default importers](crate::default_importers) are used unless overridden with [PackfileBuilder::with_importer].
+/// Importer options and state are read from the daemon's `.meta` files where they exist, so packed
+/// assets keep the UUIDs they have during development.
+pub struct PackfileBuilder {
+    importers: HashMap<String, Box<dyn BoxedImporter>>,
+    type_names: HashMap<AssetTypeId, String>,
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+    include_tags: Vec<String>,
+    exclude_tags: Vec<String>,
+    compression: ArtifactCompression,
+    key: Option<PackfileKey>,
+    profile: Option<(Arc<BuildPipelines>, String)>,
+    pipeline_cache: Option<PathBuf>,
+}
+
+impl Default for PackfileBuilder {
+    fn default() -> Self {
+        PackfileBuilder {
+            importers: Default::default(),
+            type_names: Default::default(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            include_tags: Vec::new(),
+            exclude_tags: Vec::new(),
+            compression: ArtifactCompression::None,
+            key: None,
+            profile: None,
+            pipeline_cache: None,
+        }
+        .with_type_name::<crate::image::Image>()
+        .with_type_name::<crate::texture_atlas::TextureAtlasLayout>()
+        .with_type_name::<crate::sprite_sheet::SpriteSheet>()
+        .with_type_name::<crate::audio::AudioClip>()
+        .with_type_name::<crate::mesh::Mesh>()
+        .with_type_name::<crate::mesh::Material>()
+        .with_type_name::<crate::mesh::Model>()
+        .with_type_name::<crate::font::Font>()
+        .with_type_name::<crate::font::GlyphAtlas>()
+        .with_type_name::<crate::shader::Shader>()
+        .with_type_name::<crate::tiled::Tilemap>()
+        .with_type_name::<crate::animation::AnimationClip>()
+        .with_type_name::<crate::scene::ReflectScene>()
+        .with_type_name::<crate::localization::StringTable>()
+    }
+}
+
+impl PackfileBuilder {
+    /// Imports files with the extension using `importer`. Like the asset daemon, the builder chooses
+    /// importers by the last extension of a file, so `ext` can't contain a `.`.
+    pub fn with_importer<I: BoxedImporter + 'static>(
+        mut self,
+        ext: &str,
+        importer: I,
+    ) -> Result<Self, PackfileBuildError> {
+        let ext = ext.trim_start_matches('.').to_lowercase();
+        if ext.contains('.') {
+            return Err(PackfileBuildError::InvalidExtension(ext));
+        }
+        self.importers.insert(ext, Box::new(importer));
+        Ok(self)
+    }
+
+    /// Names an asset type in the [BuildReport]
+    pub fn with_type_name<T: TypeUuid>(mut self) -> Self {
+        self.type_names
+            .insert(AssetTypeId(T::UUID), std::any::type_name::<T>().to_string());
+        self
+    }
+
+    /// Only packs source files whose path relative to the asset directory matches one of the included globs
+    pub fn include(mut self, pattern: &str) -> Result<Self, PackfileBuildError> {
+        self.include.push(glob::Pattern::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Skips source files whose path relative to the asset directory matches the glob
+    pub fn exclude(mut self, pattern: &str) -> Result<Self, PackfileBuildError> {
+        self.exclude.push(glob::Pattern::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Only packs assets that have one of the included search tags
+    pub fn include_tag<S: Into<String>>(mut self, tag: S) -> Self {
+        self.include_tags.push(tag.into());
+        self
+    }
+
+    /// Skips assets that have the search tag
+    pub fn exclude_tag<S: Into<String>>(mut self, tag: S) -> Self {
+        self.exclude_tags.push(tag.into());
+        self
+    }
+
+    /// Compresses every artifact in the pack
+    pub fn with_compression(mut self, compression: ArtifactCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Encrypts every artifact in the pack with the key
+    pub fn with_key(mut self, key: PackfileKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Runs the build pipelines of a profile on every imported asset before it is packed
+    pub fn with_build_profile(mut self, pipelines: BuildPipelines, profile: &str) -> Self {
+        self.profile = Some((Arc::new(pipelines), profile.to_string()));
+        self
+    }
+
+    /// Caches processed artifacts in a directory, keyed by a hash of their input, so later builds
+    /// skip the stages of assets that haven't changed
+    pub fn with_pipeline_cache<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.pipeline_cache = Some(dir.into());
+        self
+    }
+
+    fn includes_path(&self, path: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches_path(path)))
+            && !self.exclude.iter().any(|p| p.matches_path(path))
+    }
+
+    fn includes_tags(&self, tags: &[(String, Option<String>)]) -> bool {
+        let has_tag = |tag: &String| tags.iter().any(|(name, _)| name == tag);
+        (self.include_tags.is_empty() || self.include_tags.iter().any(has_tag))
+            && !self.exclude_tags.iter().any(has_tag)
+    }
+
+    /// Imports the assets in `asset_dir` and writes them to `writer` as a packfile
+    pub fn build<P: AsRef<Path>, W: Write>(
+        &self,
+        asset_dir: P,
+        writer: W,
+    ) -> Result<BuildReport, PackfileBuildError> {
+        let asset_dir = asset_dir.as_ref();
+        let mut sources = Vec::new();
+        collect_files(asset_dir, &mut sources)?;
+        sources.sort();
+
+        let defaults = crate::default_importers(asset_dir);
+        let mut importers: HashMap<&str, &dyn BoxedImporter> = defaults
+            .iter()
+            .map(|(ext, importer)| (*ext, importer.as_ref()))
+            .collect();
+        for (ext, importer) in &self.importers {
+            importers.insert(ext, importer.as_ref());
+        }
+
+        let runner = self.profile.as_ref().map(|(pipelines, profile)| {
+            PipelineRunner::new(
+                pipelines.clone(),
+                profile.clone(),
+                self.pipeline_cache.clone(),
+            )
+        });
+
+        let mut report = BuildReport::default();
+        let mut pack = PackfileWriter::new(writer)?;
+        pack.set_compression(self.compression);
+        pack.set_key(self.key.clone());
+        for source in sources {
+            let relative = source
+                .strip_prefix(asset_dir)
+                .unwrap_or(&source)
+                .to_path_buf();
+            if source.extension().map_or(false, |ext| ext == "meta")
+                || !self.includes_path(&relative)
+            {
+                continue;
+            }
+            let importer = match source
+                .extension()
+                .and_then(|ext| importers.get(&*ext.to_string_lossy().to_lowercase()))
+            {
+                Some(importer) => *importer,
+                None => {
+                    report.skipped.push(relative);
+                    continue;
+                }
+            };
+            for (metadata, artifact) in
+                self.import(importer, runner.as_ref(), asset_dir, &source)?
+            {
+                if !self.includes_tags(&metadata.search_tags) {
+                    continue;
+                }
+                if let Some(artifact_metadata) = &metadata.artifact {
+                    let type_report = report
+                        .types
+                        .entry(artifact_metadata.type_id)
+                        .or_insert_with(|| TypeReport {
+                            name: self.type_names.get(&artifact_metadata.type_id).cloned(),
+                            ..Default::default()
+                        });
+                    type_report.count += 1;
+                    type_report.bytes += artifact.len() as u64;
+                }
+                report.asset_count += 1;
+                report.total_bytes += artifact.len() as u64;
+                pack.add_asset(relative.clone(), metadata, &artifact)?;
+            }
+        }
+        pack.finish()?;
+        Ok(report)
+    }
+
+    fn import(
+        &self,
+        importer: &dyn BoxedImporter,
+        runner: Option<&PipelineRunner>,
+        asset_dir: &Path,
+        source: &Path,
+    ) -> Result<Vec<(AssetMetadata, Vec<u8>)>, PackfileBuildError> {
+        let import_error = |message: String| PackfileBuildError::Import {
+            path: source.to_path_buf(),
+            message,
+        };
+        let (options, state) = self.read_meta(importer, source)?;
+        let mut op = ImportOp::default();
+        let mut file = AllowStdIo::new(fs::File::open(source)?);
+        let mut imported =
+            futures_executor::block_on(importer.import_boxed(&mut op, &mut file, options, state))
+                .map_err(|err| import_error(format!("{:?}", err)))?;
+        if let Some(runner) = runner {
+            runner
+                .process(&mut imported.value.assets)
+                .map_err(|err| import_error(err.to_string()))?;
+        }
+
+        let mut assets = Vec::new();
+        for asset in imported.value.assets {
+            let relative = |deps: Vec<AssetRef>| -> Vec<AssetRef> {
+                deps.into_iter()
+                    .map(|dep| relative_ref(asset_dir, dep))
+                    .collect()
+            };
+            let mut scratch = Vec::new();
+            let serialized = SerializedAsset::create(
+                asset.id,
+                relative(asset.build_deps),
+                relative(asset.load_deps),
+                &*asset.asset_data,
+                CompressionType::None,
+                &mut scratch,
+            )
+            .map_err(|err| import_error(format!("{:?}", err)))?;
+            let metadata = AssetMetadata {
+                id: asset.id,
+                search_tags: asset.search_tags,
+                build_pipeline: asset.build_pipeline,
+                artifact: Some(serialized.metadata),
+            };
+            assets.push((metadata, serialized.data));
+        }
+        Ok(assets)
+    }
+
+    fn read_meta(
+        &self,
+        importer: &dyn BoxedImporter,
+        source: &Path,
+    ) -> Result<(Box<dyn SerdeObj>, Box<dyn SerdeObj>), PackfileBuildError> {
+        let mut meta_path = source.as_os_str().to_owned();
+        meta_path.push(".meta");
+        let meta_path = PathBuf::from(meta_path);
+        if !meta_path.exists() {
+            warn!(
+                "{:?} has no .meta file, its asset UUIDs will change between builds",
+                source
+            );
+            return Ok((importer.default_options(), importer.default_state()));
+        }
+        let meta_error = |error| PackfileBuildError::Meta {
+            path: meta_path.clone(),
+            error,
+        };
+        let meta: SourceMeta =
+            ron::de::from_str(&fs::read_to_string(&meta_path)?).map_err(meta_error)?;
+        let import_error = |err: atelier_importer::Error| PackfileBuildError::Import {
+            path: source.to_path_buf(),
+            message: format!("{:?}", err),
+        };
+        let options = importer
+            .deserialize_options(&mut erased_serde::Deserializer::erase(
+                meta.importer_options,
+            ))
+            .map_err(import_error)?;
+        let state = importer
+            .deserialize_state(&mut erased_serde::Deserializer::erase(meta.importer_state))
+            .map_err(import_error)?;
+        Ok((options, state))
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atelier_core::AssetUuid;
+
+    fn relative(asset_dir: &str, path: &str) -> AssetRef {
+        relative_ref(Path::new(asset_dir), AssetRef::Path(PathBuf::from(path)))
+    }
+
+    #[test]
+    fn path_refs_are_relative_to_the_asset_dir() {
+        let expected = AssetRef::Path(["tiles", "grass.png"].iter().collect());
+        assert_eq!(relative("assets", "assets/tiles/grass.png"), expected);
+        assert_eq!(
+            relative("assets", "assets/maps/../tiles/./grass.png"),
+            expected
+        );
+        assert_eq!(
+            relative("/game/assets", "/game/assets/tiles/grass.png"),
+            expected
+        );
+        assert_eq!(relative("assets", "tiles/grass.png"), expected);
+        let uuid = AssetRef::Uuid(AssetUuid([1; 16]));
+        assert_eq!(relative_ref(Path::new("assets"), uuid.clone()), uuid);
+    }
+}
@@ -0,0 +1,98 @@
+use super::PackfileError;
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, io, path::Path};
+
+/// How an artifact is compressed inside a packfile
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArtifactCompression {
+    None,
+    Lz4,
+    Zstd { level: i32 },
+}
+
+impl Default for ArtifactCompression {
+    fn default() -> Self {
+        ArtifactCompression::None
+    }
+}
+
+/// A symmetric key used to encrypt packfile artifacts with ChaCha20-Poly1305.
+///
+/// Encryption is authenticated, so a modified artifact fails to load instead of producing garbage.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PackfileKey(pub [u8; 32]);
+
+impl PackfileKey {
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() == 32 {
+            let mut key = [0; 32];
+            key.copy_from_slice(bytes);
+            Some(PackfileKey(key))
+        } else {
+            None
+        }
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&self.0))
+    }
+}
+
+impl fmt::Debug for PackfileKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PackfileKey(..)")
+    }
+}
+
+pub(crate) fn compress(bytes: &[u8], compression: ArtifactCompression) -> io::Result<Vec<u8>> {
+    match compression {
+        ArtifactCompression::None => Ok(bytes.to_vec()),
+        ArtifactCompression::Lz4 => Ok(lz4_flex::compress_prepend_size(bytes)),
+        ArtifactCompression::Zstd { level } => zstd::encode_all(bytes, level),
+    }
+}
+
+pub(crate) fn decompress(
+    bytes: &[u8],
+    compression: ArtifactCompression,
+    path: &Path,
+) -> Result<Vec<u8>, PackfileError> {
+    let decompress_error = |message: String| PackfileError::Decompress {
+        path: path.to_path_buf(),
+        message,
+    };
+    match compression {
+        ArtifactCompression::None => Ok(bytes.to_vec()),
+        ArtifactCompression::Lz4 => lz4_flex::decompress_size_prepended(bytes)
+            .map_err(|err| decompress_error(err.to_string())),
+        ArtifactCompression::Zstd { .. } => {
+            zstd::decode_all(bytes).map_err(|err| decompress_error(err.to_string()))
+        }
+    }
+}
+
+pub(crate) fn encrypt(bytes: &[u8], key: &PackfileKey) -> ([u8; 12], Vec<u8>) {
+    let nonce: [u8; 12] = rand::random();
+    let encrypted = key
+        .cipher()
+        .encrypt(Nonce::from_slice(&nonce), bytes)
+        .expect("artifact too large to encrypt");
+    (nonce, encrypted)
+}
+
+pub(crate) fn decrypt(
+    bytes: &[u8],
+    nonce: &[u8; 12],
+    key: &PackfileKey,
+    path: &Path,
+) -> Result<Vec<u8>, PackfileError> {
+    key.cipher()
+        .decrypt(Nonce::from_slice(nonce), bytes)
+        .map_err(|_| PackfileError::Decrypt {
+            path: path.to_path_buf(),
+        })
+}
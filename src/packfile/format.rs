@@ -0,0 +1,69 @@
+use super::ArtifactCompression;
+use atelier_core::{AssetMetadata, AssetUuid};
+use serde::{Deserialize, Serialize};
+use std::{io, path::PathBuf};
+use thiserror::Error;
+
+/// Identifies a file as a bevy_atelier packfile.
+pub(crate) const MAGIC: &[u8; 8] = b"BATLPACK";
+/// The packfile format version written by [PackfileWriter](super::PackfileWriter).
+pub const FORMAT_VERSION: u32 = 2;
+/// Size of the header: magic, format version (u32) and build id (16 bytes).
+pub(crate) const HEADER_LEN: usize = MAGIC.len() + 4 + 16;
+/// Size of the trailer at the end of a packfile: index offset and length (u64s) and index checksum (u32).
+pub(crate) const TRAILER_LEN: usize = 8 + 8 + 4;
+
+/// Errors that occur while reading or writing a packfile
+#[derive(Error, Debug)]
+pub enum PackfileError {
+    #[error("Encountered an io error while accessing the packfile.")]
+    Io(#[from] io::Error),
+    #[error("File is not a packfile (bad magic number).")]
+    InvalidMagic,
+    #[error("Packfile format version {found} is not supported, expected version {expected}.")]
+    UnsupportedVersion { found: u32, expected: u32 },
+    #[error("Packfile is truncated: {len} bytes is too short for its header, trailer or index.")]
+    Truncated { len: usize },
+    #[error("Packfile index is corrupt: checksum {found:08x} does not match {expected:08x}.")]
+    IndexChecksumMismatch { found: u32, expected: u32 },
+    #[error("Failed to (de)serialize the packfile index.")]
+    Index(#[from] bincode::Error),
+    #[error("Artifact for {path:?} lies outside of the packfile.")]
+    ArtifactOutOfBounds { path: PathBuf },
+    #[error("Artifact {id:?} for {path:?} is corrupt: checksum {found:08x} does not match {expected:08x}.")]
+    ArtifactChecksumMismatch {
+        id: AssetUuid,
+        path: PathBuf,
+        found: u32,
+        expected: u32,
+    },
+    #[error("Artifact for {path:?} is encrypted but no packfile key was supplied.")]
+    MissingKey { path: PathBuf },
+    #[error("Failed to decrypt artifact for {path:?}: wrong key or tampered data.")]
+    Decrypt { path: PathBuf },
+    #[error("Failed to decompress artifact for {path:?}: {message}")]
+    Decompress { path: PathBuf, message: String },
+}
+
+/// An asset stored in a packfile, along with the location of its artifact.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PackEntry {
+    /// The source path the asset was imported from, used to resolve path-based loads.
+    pub path: PathBuf,
+    pub metadata: AssetMetadata,
+    /// Offset of the artifact from the start of the file.
+    pub offset: u64,
+    /// Length of the stored artifact in bytes.
+    pub len: u64,
+    /// CRC32 of the stored artifact bytes.
+    pub checksum: u32,
+    pub compression: ArtifactCompression,
+    /// The nonce the artifact was encrypted with, if it is encrypted.
+    pub nonce: Option<[u8; 12]>,
+}
+
+/// The table of contents written at the end of a packfile.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct PackIndex {
+    pub entries: Vec<PackEntry>,
+}
@@ -0,0 +1,111 @@
+use super::PackfileStack;
+use atelier_core::{ArtifactMetadata, AssetRef, AssetUuid};
+use atelier_loader::{
+    io::{DataRequest, LoaderIO, MetadataRequest, ResolveRequest},
+    loader::LoaderState,
+};
+use parking_lot::RwLock;
+use std::{collections::HashSet, path::PathBuf, sync::Arc};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+enum PackfileIOError {
+    #[error("Asset {0:?} is not in the packfile.")]
+    MissingAsset(AssetUuid),
+}
+
+/// Serves a [Loader](atelier_loader::Loader) from a stack of memory-mapped [Packfile](super::Packfile)s.
+///
+/// Data requests are completed with an empty buffer. The actual bytes are borrowed from the
+/// mapping by the asset storage when the asset is updated, so no artifact is ever copied.
+pub struct PackfileIO {
+    packfiles: Arc<RwLock<PackfileStack>>,
+}
+
+impl PackfileIO {
+    pub fn new(packfiles: Arc<RwLock<PackfileStack>>) -> Self {
+        PackfileIO { packfiles }
+    }
+
+    fn collect_metadata(
+        packfiles: &PackfileStack,
+        id: &AssetUuid,
+        visited: &mut HashSet<AssetUuid>,
+        metadata: &mut Vec<ArtifactMetadata>,
+    ) -> Result<(), PackfileIOError> {
+        if !visited.insert(*id) {
+            return Ok(());
+        }
+        let entry = packfiles
+            .entry(id)
+            .ok_or(PackfileIOError::MissingAsset(*id))?;
+        if let Some(artifact) = &entry.metadata.artifact {
+            for dep in &artifact.load_deps {
+                match dep {
+                    AssetRef::Uuid(dep) => {
+                        Self::collect_metadata(packfiles, dep, visited, metadata)?
+                    }
+                    AssetRef::Path(path) => {
+                        for dep in packfiles.entries_at_path(path) {
+                            Self::collect_metadata(packfiles, &dep.metadata.id, visited, metadata)?;
+                        }
+                    }
+                }
+            }
+            metadata.push(artifact.clone());
+        }
+        Ok(())
+    }
+}
+
+impl LoaderIO for PackfileIO {
+    fn get_asset_metadata_with_dependencies(&mut self, request: MetadataRequest) {
+        let packfiles = self.packfiles.read();
+        let mut visited = HashSet::new();
+        let mut metadata = Vec::new();
+        let ids: Vec<_> = request.requested_assets().copied().collect();
+        for id in &ids {
+            if let Err(err) = Self::collect_metadata(&packfiles, id, &mut visited, &mut metadata) {
+                request.error(err);
+                return;
+            }
+        }
+        request.complete(metadata);
+    }
+
+    fn get_asset_candidates(&mut self, requests: Vec<ResolveRequest>) {
+        let packfiles = self.packfiles.read();
+        for request in requests {
+            let path = PathBuf::from(request.identifier().path());
+            let candidates: Vec<_> = packfiles
+                .entries_at_path(&path)
+                .into_iter()
+                .map(|entry| entry.metadata.clone())
+                .collect();
+            request.complete(vec![(path, candidates)]);
+        }
+    }
+
+    fn get_artifacts(&mut self, requests: Vec<DataRequest>) {
+        let packfiles = self.packfiles.read();
+        for request in requests {
+            let id = request.asset_id();
+            if packfiles.contains(&id) {
+                request.complete(Vec::new());
+            } else {
+                request.error(PackfileIOError::MissingAsset(id));
+            }
+        }
+    }
+
+    fn tick(&mut self, loader: &mut LoaderState) {
+        // Layers that were enabled or disabled since the last tick need their assets reloaded
+        let (assets, paths) = self.packfiles.write().take_invalidated();
+        if !assets.is_empty() {
+            loader.invalidate_assets(&assets);
+        }
+        if !paths.is_empty() {
+            loader.invalidate_paths(&paths);
+        }
+    }
+}
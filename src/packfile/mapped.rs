@@ -0,0 +1,323 @@
+use super::{
+    codec, ArtifactCompression, PackEntry, PackIndex, PackfileError, PackfileKey, FORMAT_VERSION,
+    HEADER_LEN, MAGIC, TRAILER_LEN,
+};
+use crate::ArtifactBytes;
+use atelier_core::AssetUuid;
+use memmap2::{Mmap, MmapOptions};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// A packfile mapped into memory. Artifacts are handed out as [ArtifactBytes] that borrow from the mapping.
+///
+/// Opening a packfile checks its format version, the bounds of the index and every artifact, and the
+/// index checksum. Artifact checksums are checked by [Packfile::verified_artifact] when an artifact is
+/// served, or all at once by [Packfile::verify_artifacts].
+///
+/// Compressed or encrypted artifacts cannot be borrowed from the mapping; they are decoded into an
+/// owned buffer when served.
+pub struct Packfile {
+    map: Arc<Mmap>,
+    build_id: uuid::Uuid,
+    key: Option<PackfileKey>,
+    entries: HashMap<AssetUuid, PackEntry>,
+    paths: HashMap<PathBuf, Vec<AssetUuid>>,
+}
+
+impl Packfile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, PackfileError> {
+        Self::from_file(&File::open(path)?)
+    }
+
+    pub fn from_file(file: &File) -> Result<Self, PackfileError> {
+        // SAFETY: packfiles are treated as read-only for as long as they are mapped
+        let map = unsafe { MmapOptions::new().map(file)? };
+        let truncated = PackfileError::Truncated { len: map.len() };
+        if map.len() < MAGIC.len() || &map[..MAGIC.len()] != MAGIC {
+            return Err(PackfileError::InvalidMagic);
+        }
+        if map.len() < HEADER_LEN + TRAILER_LEN {
+            return Err(truncated);
+        }
+        let version = u32::from_le_bytes(map[MAGIC.len()..MAGIC.len() + 4].try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(PackfileError::UnsupportedVersion {
+                found: version,
+                expected: FORMAT_VERSION,
+            });
+        }
+        let build_id = uuid::Uuid::from_slice(&map[MAGIC.len() + 4..HEADER_LEN]).unwrap();
+
+        let trailer = &map[map.len() - TRAILER_LEN..];
+        let index_offset = u64::from_le_bytes(trailer[..8].try_into().unwrap()) as usize;
+        let index_len = u64::from_le_bytes(trailer[8..16].try_into().unwrap()) as usize;
+        let index_checksum = u32::from_le_bytes(trailer[16..].try_into().unwrap());
+        let index_end = match index_offset.checked_add(index_len) {
+            Some(end) if index_offset >= HEADER_LEN && end <= map.len() - TRAILER_LEN => end,
+            _ => return Err(truncated),
+        };
+        let index_bytes = &map[index_offset..index_end];
+        let checksum = crc32fast::hash(index_bytes);
+        if checksum != index_checksum {
+            return Err(PackfileError::IndexChecksumMismatch {
+                found: checksum,
+                expected: index_checksum,
+            });
+        }
+        let index: PackIndex = bincode::deserialize(index_bytes)?;
+
+        let mut entries = HashMap::with_capacity(index.entries.len());
+        let mut paths: HashMap<PathBuf, Vec<AssetUuid>> = HashMap::new();
+        for entry in index.entries {
+            let in_bounds = entry.offset >= HEADER_LEN as u64
+                && entry
+                    .offset
+                    .checked_add(entry.len)
+                    .map_or(false, |end| end <= index_offset as u64);
+            if !in_bounds {
+                return Err(PackfileError::ArtifactOutOfBounds { path: entry.path });
+            }
+            paths
+                .entry(entry.path.clone())
+                .or_default()
+                .push(entry.metadata.id);
+            entries.insert(entry.metadata.id, entry);
+        }
+
+        Ok(Packfile {
+            map: Arc::new(map),
+            build_id,
+            key: None,
+            entries,
+            paths,
+        })
+    }
+
+    /// Sets the key used to decrypt encrypted artifacts
+    pub fn with_key(mut self, key: Option<PackfileKey>) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// The id generated when the packfile was written
+    pub fn build_id(&self) -> uuid::Uuid {
+        self.build_id
+    }
+
+    pub fn entry(&self, id: &AssetUuid) -> Option<&PackEntry> {
+        self.entries.get(id)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &PackEntry> {
+        self.entries.values()
+    }
+
+    /// Returns the entries of all assets imported from the given source path.
+    pub fn entries_at_path<P: AsRef<Path>>(&self, path: P) -> impl Iterator<Item = &PackEntry> {
+        self.paths
+            .get(path.as_ref())
+            .into_iter()
+            .flatten()
+            .filter_map(move |id| self.entries.get(id))
+    }
+
+    pub fn contains(&self, id: &AssetUuid) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    /// Returns the artifact of an asset, decrypted and decompressed. Plain artifacts are not copied
+    /// out of the mapping.
+    pub fn artifact(&self, id: &AssetUuid) -> Option<Result<ArtifactBytes, PackfileError>> {
+        self.entries
+            .get(id)
+            .map(|entry| self.decode_entry(entry, self.entry_bytes(entry)))
+    }
+
+    /// Returns the artifact of an asset after checking its stored bytes against their checksum.
+    pub fn verified_artifact(
+        &self,
+        id: &AssetUuid,
+    ) -> Option<Result<ArtifactBytes, PackfileError>> {
+        self.entries.get(id).map(|entry| {
+            let bytes = self.entry_bytes(entry);
+            Self::verify_entry(entry, &bytes)?;
+            self.decode_entry(entry, bytes)
+        })
+    }
+
+    /// Checks every artifact against its checksum. This reads the whole file.
+    pub fn verify_artifacts(&self) -> Result<(), PackfileError> {
+        for entry in self.entries.values() {
+            Self::verify_entry(entry, &self.entry_bytes(entry))?;
+        }
+        Ok(())
+    }
+
+    fn entry_bytes(&self, entry: &PackEntry) -> ArtifactBytes {
+        let start = entry.offset as usize;
+        ArtifactBytes::mapped(self.map.clone(), start..start + entry.len as usize)
+    }
+
+    fn decode_entry(
+        &self,
+        entry: &PackEntry,
+        bytes: ArtifactBytes,
+    ) -> Result<ArtifactBytes, PackfileError> {
+        let decrypted = match &entry.nonce {
+            Some(nonce) => {
+                let key = self.key.as_ref().ok_or_else(|| PackfileError::MissingKey {
+                    path: entry.path.clone(),
+                })?;
+                ArtifactBytes::Owned(codec::decrypt(&bytes, nonce, key, &entry.path)?)
+            }
+            None => bytes,
+        };
+        match entry.compression {
+            ArtifactCompression::None => Ok(decrypted),
+            compression => Ok(ArtifactBytes::Owned(codec::decompress(
+                &decrypted,
+                compression,
+                &entry.path,
+            )?)),
+        }
+    }
+
+    fn verify_entry(entry: &PackEntry, bytes: &[u8]) -> Result<(), PackfileError> {
+        let checksum = crc32fast::hash(bytes);
+        if checksum == entry.checksum {
+            Ok(())
+        } else {
+            Err(PackfileError::ArtifactChecksumMismatch {
+                id: entry.metadata.id,
+                path: entry.path.clone(),
+                found: checksum,
+                expected: entry.checksum,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packfile::PackfileWriter;
+    use atelier_core::AssetMetadata;
+
+    fn metadata(id: u8) -> AssetMetadata {
+        AssetMetadata {
+            id: AssetUuid([id; 16]),
+            search_tags: vec![],
+            build_pipeline: None,
+            artifact: None,
+        }
+    }
+
+    fn write_pack(
+        compression: ArtifactCompression,
+        key: Option<PackfileKey>,
+        artifacts: &[(u8, &[u8])],
+    ) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("bevy_atelier-{}.pack", uuid::Uuid::new_v4()));
+        let mut writer = PackfileWriter::new(File::create(&path).unwrap()).unwrap();
+        writer.set_compression(compression);
+        writer.set_key(key);
+        for (id, artifact) in artifacts {
+            writer
+                .add_asset(format!("{}.bin", id), metadata(*id), artifact)
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        path
+    }
+
+    fn read_back(packfile: &Packfile, id: u8) -> Result<Vec<u8>, PackfileError> {
+        packfile
+            .verified_artifact(&AssetUuid([id; 16]))
+            .expect("asset is in the packfile")
+            .map(|bytes| bytes.to_vec())
+    }
+
+    const ARTIFACTS: &[(u8, &[u8])] = &[
+        (1, &b"first artifact"[..]),
+        (2, &[0xab; 4096][..]),
+        (3, &[][..]),
+    ];
+
+    fn round_trip(compression: ArtifactCompression, key: Option<PackfileKey>) {
+        let path = write_pack(compression, key.clone(), ARTIFACTS);
+        let packfile = Packfile::open(&path).unwrap().with_key(key);
+        packfile.verify_artifacts().unwrap();
+        for (id, artifact) in ARTIFACTS {
+            assert_eq!(read_back(&packfile, *id).unwrap(), *artifact);
+            assert_eq!(packfile.entries_at_path(format!("{}.bin", id)).count(), 1);
+        }
+        assert!(!packfile.contains(&AssetUuid([4; 16])));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn plain_round_trip() {
+        round_trip(ArtifactCompression::None, None);
+    }
+
+    #[test]
+    fn plain_artifacts_borrow_the_mapping() {
+        let path = write_pack(ArtifactCompression::None, None, ARTIFACTS);
+        let packfile = Packfile::open(&path).unwrap();
+        let bytes = packfile.artifact(&AssetUuid([1; 16])).unwrap().unwrap();
+        assert!(matches!(bytes, ArtifactBytes::Mapped { .. }));
+        drop(bytes);
+        drop(packfile);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn lz4_round_trip() {
+        round_trip(ArtifactCompression::Lz4, None);
+    }
+
+    #[test]
+    fn zstd_round_trip() {
+        round_trip(ArtifactCompression::Zstd { level: 3 }, None);
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        round_trip(ArtifactCompression::None, Some(PackfileKey([7; 32])));
+        round_trip(ArtifactCompression::Lz4, Some(PackfileKey([7; 32])));
+    }
+
+    #[test]
+    fn encrypted_without_the_key() {
+        let key = PackfileKey([7; 32]);
+        let path = write_pack(ArtifactCompression::None, Some(key), ARTIFACTS);
+        let packfile = Packfile::open(&path).unwrap();
+        assert!(matches!(
+            read_back(&packfile, 1),
+            Err(PackfileError::MissingKey { .. })
+        ));
+        let packfile = packfile.with_key(Some(PackfileKey([8; 32])));
+        assert!(matches!(
+            read_back(&packfile, 1),
+            Err(PackfileError::Decrypt { .. })
+        ));
+        drop(packfile);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_non_packfiles() {
+        let path = std::env::temp_dir().join(format!("bevy_atelier-{}.pack", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"not a packfile at all").unwrap();
+        assert!(matches!(
+            Packfile::open(&path),
+            Err(PackfileError::InvalidMagic)
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,15 @@
+mod builder;
+mod codec;
+mod format;
+mod io;
+mod mapped;
+mod stack;
+mod writer;
+
+pub use builder::*;
+pub use codec::{ArtifactCompression, PackfileKey};
+pub use format::*;
+pub use io::*;
+pub use mapped::*;
+pub use stack::*;
+pub use writer::*;
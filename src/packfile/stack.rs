@@ -0,0 +1,153 @@
+use super::{PackEntry, Packfile, PackfileError, PackfileKey};
+use crate::ArtifactBytes;
+use atelier_core::AssetUuid;
+use std::path::{Path, PathBuf};
+
+/// A named packfile within a [PackfileStack]
+pub struct PackfileLayer {
+    pub name: String,
+    pub packfile: Packfile,
+    pub enabled: bool,
+    /// Whether every artifact was checked against its checksum when the layer was pushed
+    pub verified: bool,
+}
+
+/// An ordered stack of packfiles, e.g. a base pack followed by patches and mods.
+///
+/// Enabled layers shadow the layers below them: an asset is served from the topmost layer that
+/// contains its UUID, and a path resolves to the assets of the topmost layer that has that path.
+#[derive(Default)]
+pub struct PackfileStack {
+    layers: Vec<PackfileLayer>,
+    key: Option<PackfileKey>,
+    invalidated_assets: Vec<AssetUuid>,
+    invalidated_paths: Vec<PathBuf>,
+}
+
+impl PackfileStack {
+    /// Creates an empty stack whose layers decrypt their artifacts with `key`
+    pub fn with_key(key: Option<PackfileKey>) -> Self {
+        PackfileStack {
+            key,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a layer on top of the stack. Its artifacts are checked against their checksums as they
+    /// are served.
+    pub fn push<N: Into<String>>(&mut self, name: N, packfile: Packfile) {
+        self.push_layer(name.into(), packfile, false);
+    }
+
+    /// Checks every artifact of a packfile against its checksum, then adds it on top of the stack.
+    /// Its artifacts are not checked again as they are served.
+    pub fn push_verified<N: Into<String>>(
+        &mut self,
+        name: N,
+        packfile: Packfile,
+    ) -> Result<(), PackfileError> {
+        packfile.verify_artifacts()?;
+        self.push_layer(name.into(), packfile, true);
+        Ok(())
+    }
+
+    fn push_layer(&mut self, name: String, packfile: Packfile, verified: bool) {
+        let layer = PackfileLayer {
+            name,
+            packfile: packfile.with_key(self.key.clone()),
+            enabled: true,
+            verified,
+        };
+        self.invalidate_layer(&layer);
+        self.layers.push(layer);
+    }
+
+    pub fn layers(&self) -> &[PackfileLayer] {
+        &self.layers
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&PackfileLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    /// Enables or disables a layer. Returns false if there is no layer with the given name.
+    ///
+    /// Every asset and path the layer contains is invalidated, so the loader reloads it from
+    /// whichever layer now provides it.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let index = match self.layers.iter().position(|layer| layer.name == name) {
+            Some(index) => index,
+            None => return false,
+        };
+        if self.layers[index].enabled != enabled {
+            self.layers[index].enabled = enabled;
+            let layer = &self.layers[index];
+            self.invalidated_assets
+                .extend(layer.packfile.entries().map(|entry| entry.metadata.id));
+            self.invalidated_paths
+                .extend(layer.packfile.entries().map(|entry| entry.path.clone()));
+        }
+        true
+    }
+
+    fn invalidate_layer(&mut self, layer: &PackfileLayer) {
+        for entry in layer.packfile.entries() {
+            self.invalidated_assets.push(entry.metadata.id);
+            self.invalidated_paths.push(entry.path.clone());
+        }
+    }
+
+    fn enabled_layers(&self) -> impl Iterator<Item = &PackfileLayer> {
+        self.layers.iter().rev().filter(|layer| layer.enabled)
+    }
+
+    /// Returns the topmost layer containing the given asset
+    pub fn layer_of(&self, id: &AssetUuid) -> Option<&PackfileLayer> {
+        self.enabled_layers()
+            .find(|layer| layer.packfile.contains(id))
+    }
+
+    pub fn entry(&self, id: &AssetUuid) -> Option<&PackEntry> {
+        self.layer_of(id).and_then(|layer| layer.packfile.entry(id))
+    }
+
+    pub fn contains(&self, id: &AssetUuid) -> bool {
+        self.layer_of(id).is_some()
+    }
+
+    /// Returns the entries at the given path in the topmost layer that has any
+    pub fn entries_at_path<P: AsRef<Path>>(&self, path: P) -> Vec<&PackEntry> {
+        let path = path.as_ref();
+        self.enabled_layers()
+            .map(|layer| layer.packfile.entries_at_path(path).collect::<Vec<_>>())
+            .find(|entries| !entries.is_empty())
+            .unwrap_or_default()
+    }
+
+    pub fn artifact(&self, id: &AssetUuid) -> Option<Result<ArtifactBytes, PackfileError>> {
+        self.layer_of(id)
+            .and_then(|layer| layer.packfile.artifact(id))
+    }
+
+    /// Returns the artifact of an asset checked against its checksum, which only happens now if its
+    /// layer wasn't checked in full when it was pushed
+    pub fn verified_artifact(
+        &self,
+        id: &AssetUuid,
+    ) -> Option<Result<ArtifactBytes, PackfileError>> {
+        self.layer_of(id).and_then(|layer| {
+            if layer.verified {
+                layer.packfile.artifact(id)
+            } else {
+                layer.packfile.verified_artifact(id)
+            }
+        })
+    }
+
+    pub(crate) fn take_invalidated(&mut self) -> (Vec<AssetUuid>, Vec<PathBuf>) {
+        (
+            std::mem::take(&mut self.invalidated_assets),
+            std::mem::take(&mut self.invalidated_paths),
+        )
+    }
+}
@@ -0,0 +1,99 @@
+use super::{
+    codec, ArtifactCompression, PackEntry, PackIndex, PackfileError, PackfileKey, FORMAT_VERSION,
+    HEADER_LEN, MAGIC,
+};
+use atelier_core::AssetMetadata;
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// Writes assets into a packfile that can be memory-mapped by [Packfile](super::Packfile).
+///
+/// Artifacts are streamed to the writer as they are added; the index is written by [PackfileWriter::finish].
+/// Artifacts can optionally be compressed and encrypted, in which case they are no longer memory-mapped
+/// directly but decoded into an owned buffer on load.
+pub struct PackfileWriter<W: Write> {
+    writer: W,
+    position: u64,
+    index: PackIndex,
+    compression: ArtifactCompression,
+    key: Option<PackfileKey>,
+}
+
+impl<W: Write> PackfileWriter<W> {
+    /// Starts a packfile with a newly generated build id
+    pub fn new(writer: W) -> io::Result<Self> {
+        Self::with_build_id(writer, uuid::Uuid::new_v4())
+    }
+
+    pub fn with_build_id(mut writer: W, build_id: uuid::Uuid) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(build_id.as_bytes())?;
+        Ok(PackfileWriter {
+            writer,
+            position: HEADER_LEN as u64,
+            index: PackIndex::default(),
+            compression: ArtifactCompression::None,
+            key: None,
+        })
+    }
+
+    /// Sets how artifacts added from now on are compressed
+    pub fn set_compression(&mut self, compression: ArtifactCompression) {
+        self.compression = compression;
+    }
+
+    /// Sets the key artifacts added from now on are encrypted with
+    pub fn set_key(&mut self, key: Option<PackfileKey>) {
+        self.key = key;
+    }
+
+    /// Appends an asset and its serialized artifact to the pack.
+    pub fn add_asset<P: Into<PathBuf>>(
+        &mut self,
+        path: P,
+        metadata: AssetMetadata,
+        artifact: &[u8],
+    ) -> io::Result<()> {
+        let (stored, nonce) = match (&self.compression, &self.key) {
+            (ArtifactCompression::None, None) => (None, None),
+            (compression, key) => {
+                let compressed = codec::compress(artifact, *compression)?;
+                match key {
+                    Some(key) => {
+                        let (nonce, encrypted) = codec::encrypt(&compressed, key);
+                        (Some(encrypted), Some(nonce))
+                    }
+                    None => (Some(compressed), None),
+                }
+            }
+        };
+        let stored = stored.as_deref().unwrap_or(artifact);
+        self.writer.write_all(stored)?;
+        self.index.entries.push(PackEntry {
+            path: path.into(),
+            metadata,
+            offset: self.position,
+            len: stored.len() as u64,
+            checksum: crc32fast::hash(stored),
+            compression: self.compression,
+            nonce,
+        });
+        self.position += stored.len() as u64;
+        Ok(())
+    }
+
+    /// Writes the index and trailer, returning the underlying writer.
+    pub fn finish(mut self) -> Result<W, PackfileError> {
+        let index = bincode::serialize(&self.index)?;
+        self.writer.write_all(&index)?;
+        self.writer.write_all(&self.position.to_le_bytes())?;
+        self.writer.write_all(&(index.len() as u64).to_le_bytes())?;
+        self.writer
+            .write_all(&crc32fast::hash(&index).to_le_bytes())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
@@ -0,0 +1,673 @@
+use crate::{
+    audio::{AudioClip, AudioSamples, SampleFormat},
+    image::{Image, MipFilter},
+    mesh::{Material, Mesh},
+};
+use atelier_core::{AssetTypeId, AssetUuid, CompressionType};
+use atelier_importer::{
+    BoxedExportInputs, BoxedImporter, BoxedImporterValue, Error, ExportAsset, ImportOp,
+    ImportedAsset, Result, SerdeObj, SerializedAsset,
+};
+use bevy_log::*;
+use futures_core::future::BoxFuture;
+use futures_io::{AsyncRead, AsyncWrite};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    any::Any,
+    collections::HashMap,
+    fs,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use type_uuid::{TypeUuid, TypeUuidDynamic};
+
+/// A processing step run on imported assets of type `T`, e.g. downscaling textures for a low-end profile.
+///
+/// Its name, version and settings identify a stage in the pipeline cache. Bump the version whenever
+/// the stage starts processing assets differently, so that cached results of the old one are not reused.
+pub trait BuildStage<T>: Send + Sync + 'static {
+    fn name(&self) -> &str;
+    fn version(&self) -> u32;
+    /// The settings assets are processed with, e.g. the stage's fields serialized with bincode
+    fn settings(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn process(&self, asset: &mut T) -> std::result::Result<(), anyhow::Error>;
+}
+
+/// A stage made from a closure. Bump `version` whenever the closure changes what it does,
+/// so that cached results of the old one are not reused.
+pub struct FnStage<T, F> {
+    name: String,
+    version: u32,
+    process: F,
+    marker: PhantomData<fn(&mut T)>,
+}
+
+impl<T, F> FnStage<T, F>
+where
+    F: Fn(&mut T) -> std::result::Result<(), anyhow::Error>,
+{
+    pub fn new<S: Into<String>>(name: S, version: u32, process: F) -> Self {
+        FnStage {
+            name: name.into(),
+            version,
+            process,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, F> BuildStage<T> for FnStage<T, F>
+where
+    T: 'static,
+    F: Fn(&mut T) -> std::result::Result<(), anyhow::Error> + Send + Sync + 'static,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn version(&self) -> u32 {
+        self.version
+    }
+    fn process(&self, asset: &mut T) -> std::result::Result<(), anyhow::Error> {
+        (self.process)(asset)
+    }
+}
+
+/// Halves images until neither side exceeds `max_dimension`
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DownscaleImages {
+    pub max_dimension: u32,
+    pub filter: MipFilter,
+}
+
+impl DownscaleImages {
+    pub fn new(max_dimension: u32) -> Self {
+        DownscaleImages {
+            max_dimension,
+            filter: MipFilter::default(),
+        }
+    }
+}
+
+impl BuildStage<Image> for DownscaleImages {
+    fn name(&self) -> &str {
+        "downscale_images"
+    }
+    fn version(&self) -> u32 {
+        1
+    }
+    fn settings(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap_or_default()
+    }
+    fn process(&self, image: &mut Image) -> std::result::Result<(), anyhow::Error> {
+        image.downscale(self.max_dimension, self.filter);
+        Ok(())
+    }
+}
+
+/// Converts the samples of audio clips to `format`, e.g. [SampleFormat::I16] to halve the size of float clips
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuantizeAudio {
+    pub format: SampleFormat,
+}
+
+impl BuildStage<AudioClip> for QuantizeAudio {
+    fn name(&self) -> &str {
+        "quantize_audio"
+    }
+    fn version(&self) -> u32 {
+        1
+    }
+    fn settings(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap_or_default()
+    }
+    fn process(&self, clip: &mut AudioClip) -> std::result::Result<(), anyhow::Error> {
+        if clip.samples.format() != self.format {
+            clip.samples = AudioSamples::from_f32(clip.samples.to_f32(), self.format);
+        }
+        Ok(())
+    }
+}
+
+/// Removes the names of meshes and materials, which are only useful while debugging
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StripNames;
+
+impl BuildStage<Mesh> for StripNames {
+    fn name(&self) -> &str {
+        "strip_names"
+    }
+    fn version(&self) -> u32 {
+        1
+    }
+    fn process(&self, mesh: &mut Mesh) -> std::result::Result<(), anyhow::Error> {
+        mesh.name = None;
+        Ok(())
+    }
+}
+
+impl BuildStage<Material> for StripNames {
+    fn name(&self) -> &str {
+        "strip_names"
+    }
+    fn version(&self) -> u32 {
+        1
+    }
+    fn process(&self, material: &mut Material) -> std::result::Result<(), anyhow::Error> {
+        material.name = None;
+        Ok(())
+    }
+}
+
+/// Errors that occur while running a build pipeline
+#[derive(Error, Debug)]
+pub enum PipelineError {
+    #[error("Failed to read or write an artifact: {0}")]
+    Artifact(String),
+    #[error("Build stage {stage} failed: {error}")]
+    Stage { stage: String, error: anyhow::Error },
+}
+
+/// The stages for one asset type and profile, with the type erased
+trait ErasedPipeline: Send + Sync {
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// Identifies the stages, their versions and their settings
+    fn cache_key(&self) -> Vec<u8>;
+    /// Runs the stages on an artifact, returning the processed asset and its artifact
+    fn run(
+        &self,
+        artifact: &[u8],
+    ) -> std::result::Result<(Box<dyn SerdeObj>, Vec<u8>), PipelineError>;
+    /// Reads a processed artifact back, e.g. from the cache
+    fn load(&self, artifact: &[u8]) -> std::result::Result<Box<dyn SerdeObj>, PipelineError>;
+}
+
+struct TypedPipeline<T> {
+    stages: Vec<Box<dyn BuildStage<T>>>,
+}
+
+impl<T> ErasedPipeline for TypedPipeline<T>
+where
+    T: TypeUuid + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn cache_key(&self) -> Vec<u8> {
+        let mut key = Vec::new();
+        for stage in &self.stages {
+            let settings = stage.settings();
+            for field in &[stage.name().as_bytes(), settings.as_slice()] {
+                key.extend_from_slice(&(field.len() as u64).to_le_bytes());
+                key.extend_from_slice(field);
+            }
+            key.extend_from_slice(&stage.version().to_le_bytes());
+        }
+        key
+    }
+
+    fn run(
+        &self,
+        artifact: &[u8],
+    ) -> std::result::Result<(Box<dyn SerdeObj>, Vec<u8>), PipelineError> {
+        let mut asset: T = bincode::deserialize(artifact)
+            .map_err(|err| PipelineError::Artifact(err.to_string()))?;
+        for stage in &self.stages {
+            stage
+                .process(&mut asset)
+                .map_err(|error| PipelineError::Stage {
+                    stage: format!("{} v{}", stage.name(), stage.version()),
+                    error,
+                })?;
+        }
+        let processed =
+            bincode::serialize(&asset).map_err(|err| PipelineError::Artifact(err.to_string()))?;
+        Ok((Box::new(asset), processed))
+    }
+
+    fn load(&self, artifact: &[u8]) -> std::result::Result<Box<dyn SerdeObj>, PipelineError> {
+        let asset: T = bincode::deserialize(artifact)
+            .map_err(|err| PipelineError::Artifact(err.to_string()))?;
+        Ok(Box::new(asset))
+    }
+}
+
+/// Processing stages per asset type and target profile, such as "desktop" and "low-end".
+///
+/// ```ignore
+/// let pipelines = BuildPipelines::new()
+///     .with_stage::<Image, _>("low-end", DownscaleImages::new(512))
+///     .with_stage::<AudioClip, _>("low-end", QuantizeAudio { format: SampleFormat::I16 })
+///     .with_stage::<Mesh, _>("low-end", StripNames);
+/// ```
+///
+/// Run them with [AssetServer::set_build_profile](crate::AssetServer::set_build_profile) during
+/// development, or [PackfileBuilder::with_build_profile](crate::packfile::PackfileBuilder::with_build_profile)
+/// when packing.
+#[derive(Default)]
+pub struct BuildPipelines {
+    pipelines: HashMap<(AssetTypeId, String), Box<dyn ErasedPipeline>>,
+}
+
+impl BuildPipelines {
+    pub fn new() -> Self {
+        BuildPipelines::default()
+    }
+
+    /// Appends a stage to the pipeline of `T` for a profile. Stages run in the order they are added.
+    pub fn with_stage<T, S>(mut self, profile: &str, stage: S) -> Self
+    where
+        T: TypeUuid + Serialize + DeserializeOwned + Send + Sync + 'static,
+        S: BuildStage<T>,
+    {
+        self.pipelines
+            .entry((AssetTypeId(T::UUID), profile.to_string()))
+            .or_insert_with(|| Box::new(TypedPipeline::<T> { stages: Vec::new() }))
+            .as_any_mut()
+            .downcast_mut::<TypedPipeline<T>>()
+            .expect("pipelines are keyed by the UUID of their asset type")
+            .stages
+            .push(Box::new(stage));
+        self
+    }
+
+    /// The profiles that have at least one stage
+    pub fn profiles(&self) -> Vec<&str> {
+        let mut profiles: Vec<&str> = self
+            .pipelines
+            .keys()
+            .map(|(_, profile)| profile.as_str())
+            .collect();
+        profiles.sort_unstable();
+        profiles.dedup();
+        profiles
+    }
+
+    fn get(&self, asset_type: AssetTypeId, profile: &str) -> Option<&dyn ErasedPipeline> {
+        self.pipelines
+            .get(&(asset_type, profile.to_string()))
+            .map(|pipeline| pipeline.as_ref())
+    }
+}
+
+/// The pipelines of one profile, ready to run on imported assets.
+///
+/// Processed artifacts are cached on disk per profile and asset, by a SHA-256 hash of the unprocessed
+/// artifact and the stages run on it, so reimporting a file or switching back to a profile doesn't run
+/// the stages again. Only the latest entry of each asset is kept.
+#[derive(Clone)]
+pub(crate) struct PipelineRunner {
+    pipelines: Arc<BuildPipelines>,
+    profile: String,
+    cache_dir: Option<PathBuf>,
+}
+
+impl PipelineRunner {
+    pub fn new(
+        pipelines: Arc<BuildPipelines>,
+        profile: String,
+        cache_dir: Option<PathBuf>,
+    ) -> Self {
+        PipelineRunner {
+            pipelines,
+            profile,
+            cache_dir,
+        }
+    }
+
+    /// Identifies the profile and its stages. Changes whenever the output of the pipelines could.
+    /// It salts importer versions, so it is truncated to their size.
+    pub fn hash(&self) -> u32 {
+        let mut keys: Vec<(AssetTypeId, Vec<u8>)> = self
+            .pipelines
+            .pipelines
+            .iter()
+            .filter(|((_, profile), _)| *profile == self.profile)
+            .map(|((asset_type, _), pipeline)| (*asset_type, pipeline.cache_key()))
+            .collect();
+        keys.sort_by(|(a, _), (b, _)| a.0.cmp(&b.0));
+        let mut parts: Vec<&[u8]> = vec![self.profile.as_bytes()];
+        for (asset_type, key) in &keys {
+            parts.push(&asset_type.0);
+            parts.push(key);
+        }
+        let hash = content_hash(&parts);
+        u32::from_le_bytes([hash[0], hash[1], hash[2], hash[3]])
+    }
+
+    /// The cache directory of an asset in this profile
+    fn asset_cache_dir(&self, id: AssetUuid) -> Option<PathBuf> {
+        self.cache_dir.as_ref().map(|dir| {
+            dir.join(hex(&content_hash(&[self.profile.as_bytes()])[..8]))
+                .join(uuid::Uuid::from_bytes(id.0).to_string())
+        })
+    }
+
+    /// Runs the profile's pipeline on every asset that has one
+    pub fn process(
+        &self,
+        assets: &mut Vec<ImportedAsset>,
+    ) -> std::result::Result<(), PipelineError> {
+        for asset in assets {
+            let asset_type = AssetTypeId(asset.asset_data.uuid());
+            let pipeline = match self.pipelines.get(asset_type, &self.profile) {
+                Some(pipeline) => pipeline,
+                None => continue,
+            };
+            let mut scratch = Vec::new();
+            let artifact = SerializedAsset::create(
+                asset.id,
+                Vec::new(),
+                Vec::new(),
+                &*asset.asset_data,
+                CompressionType::None,
+                &mut scratch,
+            )
+            .map_err(|err| PipelineError::Artifact(format!("{:?}", err)))?
+            .data;
+
+            let key = hex(&content_hash(&[
+                &asset_type.0,
+                &pipeline.cache_key(),
+                &artifact,
+            ]));
+            let cache_path = self.asset_cache_dir(asset.id).map(|dir| dir.join(key));
+            if let Some(path) = &cache_path {
+                remove_stale_entries(path);
+            }
+
+            if let Some(cached) = cache_path.as_ref().and_then(|path| fs::read(path).ok()) {
+                asset.asset_data = pipeline.load(&cached)?;
+                continue;
+            }
+            let (processed, processed_artifact) = pipeline.run(&artifact)?;
+            if let Some(path) = &cache_path {
+                let written = path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(path, &processed_artifact));
+                if let Err(err) = written {
+                    warn!("failed to cache processed artifact {:?}: {}", path, err);
+                }
+            }
+            asset.asset_data = processed;
+        }
+        Ok(())
+    }
+}
+
+/// Hashes a sequence of byte strings, each prefixed with its length so that they can't run together
+fn content_hash(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(&(part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    let mut hash = [0; 32];
+    hash.copy_from_slice(&hasher.finalize());
+    hash
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Removes the cache entries next to `path`, which were made from an older artifact or older stages
+fn remove_stale_entries(path: &Path) {
+    let entries = match path.parent().map(fs::read_dir) {
+        Some(Ok(entries)) => entries,
+        _ => return,
+    };
+    for entry in entries.flatten() {
+        if entry.path() != path {
+            if let Err(err) = fs::remove_file(entry.path()) {
+                warn!(
+                    "failed to remove stale pipeline cache entry {:?}: {}",
+                    entry.path(),
+                    err
+                );
+            }
+        }
+    }
+}
+
+/// Runs a profile's pipelines on the assets of another importer. Used by the asset daemon.
+pub(crate) struct PipelineImporter {
+    inner: Box<dyn BoxedImporter>,
+    runner: PipelineRunner,
+    /// Changes the importer version with the profile, so the daemon reimports when it changes
+    version_salt: u32,
+}
+
+impl PipelineImporter {
+    pub fn new(inner: Box<dyn BoxedImporter>, runner: PipelineRunner) -> Self {
+        let version_salt = runner.hash();
+        PipelineImporter {
+            inner,
+            runner,
+            version_salt,
+        }
+    }
+}
+
+impl TypeUuidDynamic for PipelineImporter {
+    fn uuid(&self) -> type_uuid::Bytes {
+        self.inner.uuid()
+    }
+}
+
+impl BoxedImporter for PipelineImporter {
+    fn import_boxed<'a>(
+        &'a self,
+        op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        options: Box<dyn SerdeObj>,
+        state: Box<dyn SerdeObj>,
+    ) -> BoxFuture<'a, Result<BoxedImporterValue>> {
+        Box::pin(async move {
+            let mut imported = self.inner.import_boxed(op, source, options, state).await?;
+            self.runner
+                .process(&mut imported.value.assets)
+                .map_err(|e| Error::Boxed(Box::new(e)))?;
+            Ok(imported)
+        })
+    }
+
+    fn export_boxed<'a>(
+        &'a self,
+        output: &'a mut (dyn AsyncWrite + Unpin + Send + Sync),
+        options: Box<dyn SerdeObj>,
+        state: Box<dyn SerdeObj>,
+        assets: Vec<ExportAsset>,
+    ) -> BoxFuture<'a, Result<BoxedExportInputs>> {
+        self.inner.export_boxed(output, options, state, assets)
+    }
+
+    fn default_options(&self) -> Box<dyn SerdeObj> {
+        self.inner.default_options()
+    }
+
+    fn default_state(&self) -> Box<dyn SerdeObj> {
+        self.inner.default_state()
+    }
+
+    fn version(&self) -> u32 {
+        self.inner.version() ^ self.version_salt
+    }
+
+    fn deserialize_options<'a>(
+        &self,
+        deserializer: &mut dyn erased_serde::Deserializer<'a>,
+    ) -> Result<Box<dyn SerdeObj>> {
+        self.inner.deserialize_options(deserializer)
+    }
+
+    fn deserialize_state<'a>(
+        &self,
+        deserializer: &mut dyn erased_serde::Deserializer<'a>,
+    ) -> Result<Box<dyn SerdeObj>> {
+        self.inner.deserialize_state(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(TypeUuid, Serialize, Deserialize, Debug, PartialEq)]
+    #[uuid = "52570ed3-9782-4765-9eda-3ee3cd3e6430"]
+    struct Text(String);
+
+    struct Append {
+        suffix: &'static str,
+        version: u32,
+        runs: Arc<AtomicUsize>,
+    }
+
+    impl BuildStage<Text> for Append {
+        fn name(&self) -> &str {
+            "append"
+        }
+        fn version(&self) -> u32 {
+            self.version
+        }
+        fn settings(&self) -> Vec<u8> {
+            self.suffix.as_bytes().to_vec()
+        }
+        fn process(&self, text: &mut Text) -> std::result::Result<(), anyhow::Error> {
+            self.runs.fetch_add(1, Ordering::SeqCst);
+            text.0.push_str(self.suffix);
+            Ok(())
+        }
+    }
+
+    fn append(suffix: &'static str, version: u32, runs: &Arc<AtomicUsize>) -> Append {
+        Append {
+            suffix,
+            version,
+            runs: runs.clone(),
+        }
+    }
+
+    fn runner(pipelines: BuildPipelines, cache_dir: Option<&Path>) -> PipelineRunner {
+        PipelineRunner::new(
+            Arc::new(pipelines),
+            "low-end".to_string(),
+            cache_dir.map(Path::to_path_buf),
+        )
+    }
+
+    /// Runs the pipeline on a text asset and returns the processed text
+    fn process(runner: &PipelineRunner, text: &str) -> String {
+        let mut assets = vec![ImportedAsset {
+            id: AssetUuid([7; 16]),
+            search_tags: vec![],
+            build_deps: vec![],
+            load_deps: vec![],
+            build_pipeline: None,
+            asset_data: Box::new(Text(text.to_string())),
+        }];
+        runner.process(&mut assets).unwrap();
+        let mut scratch = Vec::new();
+        let artifact = SerializedAsset::create(
+            assets[0].id,
+            Vec::new(),
+            Vec::new(),
+            &*assets[0].asset_data,
+            CompressionType::None,
+            &mut scratch,
+        )
+        .unwrap()
+        .data;
+        bincode::deserialize::<Text>(&artifact).unwrap().0
+    }
+
+    fn cache_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("pipeline-cache-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn cache_entries(runner: &PipelineRunner) -> usize {
+        let dir = runner.asset_cache_dir(AssetUuid([7; 16])).unwrap();
+        fs::read_dir(dir).map_or(0, |entries| entries.count())
+    }
+
+    #[test]
+    fn stages_run_in_order_for_their_profile() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let pipelines = BuildPipelines::new()
+            .with_stage::<Text, _>("low-end", append("a", 1, &runs))
+            .with_stage::<Text, _>("low-end", append("b", 1, &runs))
+            .with_stage::<Text, _>("desktop", append("c", 1, &runs));
+        assert_eq!(pipelines.profiles(), vec!["desktop", "low-end"]);
+        assert_eq!(process(&runner(pipelines, None), "x"), "xab");
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn cached_results_are_reused_until_the_artifact_or_stages_change() {
+        let dir = cache_dir();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let pipeline = |suffix, version| {
+            runner(
+                BuildPipelines::new()
+                    .with_stage::<Text, _>("low-end", append(suffix, version, &runs)),
+                Some(&dir),
+            )
+        };
+
+        let first = pipeline("a", 1);
+        assert_eq!(process(&first, "x"), "xa");
+        assert_eq!(process(&first, "x"), "xa");
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        assert_eq!(process(&first, "y"), "ya");
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+        let bumped = pipeline("a", 2);
+        assert_ne!(bumped.hash(), first.hash());
+        assert_eq!(process(&bumped, "y"), "ya");
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+
+        let resettled = pipeline("b", 2);
+        assert_ne!(resettled.hash(), bumped.hash());
+        assert_eq!(process(&resettled, "y"), "yb");
+        assert_eq!(runs.load(Ordering::SeqCst), 4);
+
+        // Only the latest entry of the asset is kept
+        assert_eq!(cache_entries(&resettled), 1);
+        assert_eq!(pipeline("b", 2).hash(), resettled.hash());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stale_entries_are_removed() {
+        let dir = cache_dir();
+        fs::create_dir_all(&dir).unwrap();
+        for name in &["old", "older", "current"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        remove_stale_entries(&dir.join("current"));
+        let remaining: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(remaining, vec!["current"]);
+
+        remove_stale_entries(&dir.join("next"));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn content_hash_separates_parts() {
+        assert_ne!(content_hash(&[b"ab", b"c"]), content_hash(&[b"a", b"bc"]));
+        assert_eq!(hex(&[0x00, 0xab, 0x10]), "00ab10");
+    }
+}
@@ -0,0 +1,356 @@
+use crate::{xor_uuid, ArtifactBytes, NamedState};
+use atelier_importer::{AsyncImporter, Error, ImportOp, ImportedAsset, ImporterValue, Result};
+use bevy_ecs::{FromResources, Resources};
+use bevy_reflect::{
+    serde::{ReflectDeserializer, ReflectSerializer},
+    Reflect, ReflectRef, TypeRegistry, TypeRegistryArc,
+};
+use futures_core::future::BoxFuture;
+use futures_io::AsyncRead;
+use futures_util::AsyncReadExt;
+use serde::{de::DeserializeSeed, Serialize, Serializer};
+use std::marker::PhantomData;
+use thiserror::Error;
+use type_uuid::TypeUuid;
+
+/// Errors that occur while importing or loading a reflect asset
+#[derive(Error, Debug)]
+pub enum ReflectAssetError {
+    #[error("Invalid reflect data: {0}")]
+    Ron(#[from] ron::Error),
+    #[error("Expected a {expected}, found a {found}.")]
+    WrongType { expected: String, found: String },
+    #[error("{type_name} is missing fields: {}", fields.join(", "))]
+    MissingFields {
+        type_name: String,
+        fields: Vec<String>,
+    },
+}
+
+/// Collects the fields of `expected` that `value` doesn't set, recursing into nested structs
+fn missing_fields(
+    expected: &dyn Reflect,
+    value: &dyn Reflect,
+    path: &str,
+    missing: &mut Vec<String>,
+) {
+    let field_path = |name: &str| {
+        if path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", path, name)
+        }
+    };
+    match (expected.reflect_ref(), value.reflect_ref()) {
+        (ReflectRef::Struct(expected), ReflectRef::Struct(value)) => {
+            for index in 0..expected.field_len() {
+                let name = expected.name_at(index).unwrap_or_default();
+                match (expected.field_at(index), value.field(name)) {
+                    (Some(expected), Some(value)) => {
+                        missing_fields(expected, value, &field_path(name), missing)
+                    }
+                    _ => missing.push(field_path(name)),
+                }
+            }
+        }
+        (ReflectRef::TupleStruct(expected), ReflectRef::TupleStruct(value)) => {
+            for index in 0..expected.field_len() {
+                match (expected.field(index), value.field(index)) {
+                    (Some(expected), Some(value)) => {
+                        missing_fields(expected, value, &field_path(&index.to_string()), missing)
+                    }
+                    _ => missing.push(field_path(&index.to_string())),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The artifact of a reflect asset: the asset serialized in the reflect format.
+/// Its type UUID is that of `T`, so it loads into `Assets<T>`.
+pub struct ReflectArtifact<T> {
+    ron: String,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: TypeUuid> TypeUuid for ReflectArtifact<T> {
+    const UUID: type_uuid::Bytes = T::UUID;
+}
+
+impl<T> Serialize for ReflectArtifact<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.ron.serialize(serializer)
+    }
+}
+
+/// Parses a reflected `T`, which must set every field of `T`, since only the fields it sets are
+/// applied to `T::default()`
+fn parse_reflect<T: Reflect + Default>(
+    registry: &TypeRegistry,
+    ron: &str,
+) -> std::result::Result<Box<dyn Reflect>, ReflectAssetError> {
+    let mut deserializer = ron::de::Deserializer::from_str(ron)?;
+    let value = ReflectDeserializer::new(registry).deserialize(&mut deserializer)?;
+    let expected = std::any::type_name::<T>();
+    if value.type_name() != expected {
+        return Err(ReflectAssetError::WrongType {
+            expected: expected.to_string(),
+            found: value.type_name().to_string(),
+        });
+    }
+    let mut missing = Vec::new();
+    missing_fields(&T::default(), &*value, "", &mut missing);
+    if !missing.is_empty() {
+        return Err(ReflectAssetError::MissingFields {
+            type_name: expected.to_string(),
+            fields: missing,
+        });
+    }
+    Ok(value)
+}
+
+/// Builds a `T` from a [ReflectArtifact] by applying its reflected fields to `T::default()`.
+/// Every field is set, so none keeps its default value.
+pub(crate) fn deserialize_reflect<T: Reflect + Default>(
+    registry: &TypeRegistryArc,
+    bytes: ArtifactBytes,
+) -> std::result::Result<T, anyhow::Error> {
+    let ron: String = bincode::deserialize(&bytes)?;
+    let value = parse_reflect::<T>(&registry.read(), &ron)?;
+    let mut asset = T::default();
+    asset.apply(&*value);
+    Ok(asset)
+}
+
+/// Imports a `T` written in the reflect RON format, e.g. `{"type": "game::Config", "struct": {...}}`.
+///
+/// `T` needs no serde derives. Files must set every field of `T`, including those of nested structs;
+/// files missing any fail to import. Add it with [AddAsset::add_reflect_asset](crate::AddAsset::add_reflect_asset)
+/// and register this importer for an extension with [AddAsset::add_importer](crate::AddAsset::add_importer).
+pub struct ReflectImporter<T> {
+    registry: TypeRegistryArc,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> ReflectImporter<T> {
+    pub fn new(registry: TypeRegistryArc) -> Self {
+        ReflectImporter {
+            registry,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> FromResources for ReflectImporter<T> {
+    fn from_resources(resources: &Resources) -> Self {
+        ReflectImporter::new(
+            resources
+                .get::<TypeRegistryArc>()
+                .map(|registry| (*registry).clone())
+                .unwrap_or_default(),
+        )
+    }
+}
+
+impl<T: TypeUuid> TypeUuid for ReflectImporter<T> {
+    const UUID: type_uuid::Bytes = xor_uuid(T::UUID, *b"atelier/reflect ");
+}
+
+impl<T> ReflectImporter<T>
+where
+    T: TypeUuid + Reflect + Default,
+{
+    /// Checks that the file holds a `T` and normalizes it through the reflect serializer
+    fn parse(&self, text: &str) -> std::result::Result<String, ReflectAssetError> {
+        let registry = self.registry.read();
+        let value = parse_reflect::<T>(&registry, text)?;
+        Ok(ron::ser::to_string(&ReflectSerializer::new(
+            &*value, &registry,
+        ))?)
+    }
+}
+
+impl<T> AsyncImporter for ReflectImporter<T>
+where
+    T: TypeUuid + Reflect + Default,
+{
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        2
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = ();
+
+    type State = NamedState;
+
+    /// Parses the file as a reflected `T`.
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        _options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        Box::pin(async move {
+            let id = state.id("asset");
+            let mut text = String::new();
+            source.read_to_string(&mut text).await?;
+            let ron = self.parse(&text).map_err(|e| Error::Boxed(Box::new(e)))?;
+            Ok(ImporterValue {
+                assets: vec![ImportedAsset {
+                    id,
+                    search_tags: vec![],
+                    build_deps: vec![],
+                    load_deps: vec![],
+                    build_pipeline: None,
+                    asset_data: Box::new(ReflectArtifact::<T> {
+                        ron,
+                        marker: PhantomData,
+                    }),
+                }],
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(bevy_reflect::Reflect, Default, Debug, PartialEq)]
+    struct Window {
+        width: u32,
+        height: u32,
+    }
+
+    #[derive(bevy_reflect::Reflect, TypeUuid, Default, Debug, PartialEq)]
+    #[uuid = "d719116c-54db-494b-aace-3967c5836ecd"]
+    struct Config {
+        name: String,
+        window: Window,
+    }
+
+    #[derive(bevy_reflect::Reflect, Default)]
+    struct Pair(u32, u32);
+
+    fn importer() -> ReflectImporter<Config> {
+        let registry = TypeRegistryArc::default();
+        {
+            let mut registry = registry.write();
+            registry.register::<Config>();
+            registry.register::<Window>();
+            registry.register::<Pair>();
+            registry.register::<String>();
+            registry.register::<u32>();
+        }
+        ReflectImporter::new(registry)
+    }
+
+    fn value(type_name: &str, value: &str) -> String {
+        format!(r#"{{ "type": "{}", "value": {} }}"#, type_name, value)
+    }
+
+    fn window(fields: &[(&str, u32)]) -> String {
+        let fields: Vec<_> = fields
+            .iter()
+            .map(|(name, size)| format!(r#""{}": {}"#, name, value("u32", &size.to_string())))
+            .collect();
+        format!(
+            r#"{{ "type": "{}", "struct": {{ {} }} }}"#,
+            std::any::type_name::<Window>(),
+            fields.join(", ")
+        )
+    }
+
+    fn config(name: Option<&str>, window: &str) -> String {
+        let name = name
+            .map(|name| {
+                format!(
+                    r#""name": {}, "#,
+                    value("alloc::string::String", &format!("{:?}", name))
+                )
+            })
+            .unwrap_or_default();
+        format!(
+            r#"{{ "type": "{}", "struct": {{ {}"window": {} }} }}"#,
+            std::any::type_name::<Config>(),
+            name,
+            window
+        )
+    }
+
+    #[test]
+    fn imports_complete_files() {
+        let importer = importer();
+        let file = config(Some("game"), &window(&[("width", 1280), ("height", 720)]));
+        let ron = importer.parse(&file).unwrap();
+        let bytes = ArtifactBytes::Owned(bincode::serialize(&ron).unwrap());
+        let config: Config = deserialize_reflect(&importer.registry, bytes).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                name: "game".to_string(),
+                window: Window {
+                    width: 1280,
+                    height: 720
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_missing_fields() {
+        let importer = importer();
+        let file = config(None, &window(&[("width", 1280)]));
+        match importer.parse(&file) {
+            Err(ReflectAssetError::MissingFields { type_name, fields }) => {
+                assert_eq!(type_name, std::any::type_name::<Config>());
+                assert_eq!(fields, vec!["name", "window.height"]);
+            }
+            other => panic!("expected missing fields, got {:?}", other),
+        }
+
+        let pair = format!(
+            r#"{{ "type": "{}", "tuple_struct": [{}] }}"#,
+            std::any::type_name::<Pair>(),
+            value("u32", "1")
+        );
+        match parse_reflect::<Pair>(&importer.registry.read(), &pair) {
+            Err(ReflectAssetError::MissingFields { fields, .. }) => assert_eq!(fields, vec!["1"]),
+            other => panic!("expected missing fields, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn rejects_other_types() {
+        let importer = importer();
+        let file = window(&[("width", 1280), ("height", 720)]);
+        match importer.parse(&file) {
+            Err(ReflectAssetError::WrongType { expected, found }) => {
+                assert_eq!(expected, std::any::type_name::<Config>());
+                assert_eq!(found, std::any::type_name::<Window>());
+            }
+            other => panic!("expected a wrong type, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_and_unregistered_data() {
+        let importer = importer();
+        assert!(matches!(
+            importer.parse("{ \"type\": "),
+            Err(ReflectAssetError::Ron(_))
+        ));
+        let unregistered = r#"{ "type": "game::Unknown", "struct": {} }"#;
+        assert!(matches!(
+            importer.parse(unregistered),
+            Err(ReflectAssetError::Ron(_))
+        ));
+    }
+}
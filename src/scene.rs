@@ -0,0 +1,576 @@
+use crate::{AssetServer, ImporterExtensions, NamedState};
+use atelier_core::{AssetRef, AssetUuid};
+use atelier_importer::{AsyncImporter, Error, ImportOp, ImportedAsset, ImporterValue, Result};
+use atelier_loader::{
+    crossbeam_channel::unbounded,
+    handle::SerdeContext,
+    storage::{LoadHandle, LoaderInfoProvider},
+};
+use bevy_ecs::{FromResources, ReflectComponent, Resources};
+use bevy_reflect::{
+    serde::ReflectDeserializer, Reflect, ReflectDeserialize, TypeRegistry, TypeRegistryArc,
+};
+use futures_core::future::BoxFuture;
+use futures_io::AsyncRead;
+use futures_util::AsyncReadExt;
+use ron::value::{Number, Value};
+use serde::{
+    de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use type_uuid::*;
+
+/// A reflected component of a [SceneEntity]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SceneComponent {
+    pub type_name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SceneEntity {
+    pub entity: u32,
+    pub components: Vec<SceneComponent>,
+}
+
+/// A scene of reflected components, imported from `.scn` and `.scn.ron` files
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[uuid = "3ee8faa8-00ae-43fa-8e34-a925e8b591dd"]
+pub struct ReflectScene {
+    pub entities: Vec<SceneEntity>,
+    /// The scene file the components are deserialized from, kept as written
+    pub source: String,
+}
+
+impl ReflectScene {
+    /// Deserializes the components of each entity.
+    ///
+    /// Components holding handles can only be deserialized where the loader can resolve them.
+    pub fn reflect_components(
+        &self,
+        registry: &TypeRegistry,
+    ) -> std::result::Result<Vec<(u32, Vec<Box<dyn Reflect>>)>, SceneError> {
+        Ok(deserialize_scene(registry, &self.source)?)
+    }
+}
+
+/// Errors that occur while importing a scene
+#[derive(Error, Debug)]
+pub enum SceneError {
+    #[error("Invalid scene: {0}")]
+    Ron(#[from] ron::Error),
+    #[error("Type {0:?} is not registered.")]
+    UnregisteredType(String),
+    #[error("Type {0:?} is not a component. Add #[reflect(Component)] to it.")]
+    NotAComponent(String),
+    #[error("Type {0:?} is stored as a value but has no registered Deserialize implementation.")]
+    NotDeserializable(String),
+    #[error("Component at index {index} of entity {entity} has no \"type\".")]
+    MissingType { entity: u32, index: usize },
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum EntityField {
+    Entity,
+    Components,
+}
+
+/// Deserializes a scene file with every component reflected by a [ReflectDeserializer], so that
+/// components are read by the RON parser itself rather than picked out of the text
+struct SceneDeserializer<'a> {
+    registry: &'a TypeRegistry,
+}
+
+impl<'a, 'de> DeserializeSeed<'de> for SceneDeserializer<'a> {
+    type Value = Vec<(u32, Vec<Box<dyn Reflect>>)>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for SceneDeserializer<'a> {
+    type Value = Vec<(u32, Vec<Box<dyn Reflect>>)>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of entities")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut seq: A,
+    ) -> std::result::Result<Self::Value, A::Error> {
+        let mut entities = Vec::new();
+        while let Some(entity) = seq.next_element_seed(EntityDeserializer {
+            registry: self.registry,
+        })? {
+            entities.push(entity);
+        }
+        Ok(entities)
+    }
+}
+
+struct EntityDeserializer<'a> {
+    registry: &'a TypeRegistry,
+}
+
+impl<'a, 'de> DeserializeSeed<'de> for EntityDeserializer<'a> {
+    type Value = (u32, Vec<Box<dyn Reflect>>);
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("Entity", &["entity", "components"], self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for EntityDeserializer<'a> {
+    type Value = (u32, Vec<Box<dyn Reflect>>);
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an entity")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(
+        self,
+        mut map: A,
+    ) -> std::result::Result<Self::Value, A::Error> {
+        let mut entity = None;
+        let mut components = None;
+        while let Some(field) = map.next_key()? {
+            match field {
+                EntityField::Entity => entity = Some(map.next_value()?),
+                EntityField::Components => {
+                    components = Some(map.next_value_seed(ComponentsDeserializer {
+                        registry: self.registry,
+                    })?)
+                }
+            }
+        }
+        Ok((
+            entity.ok_or_else(|| de::Error::missing_field("entity"))?,
+            components.ok_or_else(|| de::Error::missing_field("components"))?,
+        ))
+    }
+}
+
+struct ComponentsDeserializer<'a> {
+    registry: &'a TypeRegistry,
+}
+
+impl<'a, 'de> DeserializeSeed<'de> for ComponentsDeserializer<'a> {
+    type Value = Vec<Box<dyn Reflect>>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for ComponentsDeserializer<'a> {
+    type Value = Vec<Box<dyn Reflect>>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of reflected components")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut seq: A,
+    ) -> std::result::Result<Self::Value, A::Error> {
+        let mut components = Vec::new();
+        while let Some(component) =
+            seq.next_element_seed(ReflectDeserializer::new(self.registry))?
+        {
+            components.push(component);
+        }
+        Ok(components)
+    }
+}
+
+fn deserialize_scene(
+    registry: &TypeRegistry,
+    source: &str,
+) -> std::result::Result<Vec<(u32, Vec<Box<dyn Reflect>>)>, ron::Error> {
+    let mut deserializer = ron::de::Deserializer::from_str(source)?;
+    let entities = SceneDeserializer { registry }.deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(entities)
+}
+
+/// Stands in for the loader while a scene is checked on import, so that components holding
+/// handles can be deserialized too. The handles it hands out are never loaded.
+struct ImportLoaderInfo;
+
+impl LoaderInfoProvider for ImportLoaderInfo {
+    fn get_load_handle(&self, _asset_ref: &AssetRef) -> Option<LoadHandle> {
+        Some(LoadHandle(0))
+    }
+
+    fn get_asset_id(&self, _load: LoadHandle) -> Option<AssetUuid> {
+        None
+    }
+}
+
+#[derive(Deserialize)]
+struct SceneFileEntity {
+    entity: u32,
+    components: Vec<Value>,
+}
+
+fn map_entry<'a>(map: &'a ron::value::Map, key: &str) -> Option<&'a Value> {
+    map.iter().find_map(|(k, v)| match k {
+        Value::String(k) if k == key => Some(v),
+        _ => None,
+    })
+}
+
+fn is_handle(type_name: &str) -> bool {
+    type_name.starts_with("atelier_loader::handle::Handle<")
+        || type_name == "atelier_loader::handle::GenericHandle"
+}
+
+struct Validator<'a> {
+    registry: &'a TypeRegistry,
+    asset_root: &'a Path,
+    load_deps: Vec<AssetRef>,
+}
+
+impl<'a> Validator<'a> {
+    /// Checks that every type in a reflected value is registered, and records the assets its handles point to
+    fn visit(&mut self, value: &Value) -> std::result::Result<(), SceneError> {
+        match value {
+            Value::Map(map) => {
+                let type_name = match map_entry(map, "type") {
+                    Some(Value::String(type_name)) => Some(type_name.as_str()),
+                    _ => None,
+                };
+                if let Some(type_name) = type_name {
+                    let registration = self
+                        .registry
+                        .get_with_name(type_name)
+                        .ok_or_else(|| SceneError::UnregisteredType(type_name.to_string()))?;
+                    if let Some(value) = map_entry(map, "value") {
+                        if is_handle(type_name) {
+                            self.handle(value);
+                        } else if registration.data::<ReflectDeserialize>().is_none() {
+                            return Err(SceneError::NotDeserializable(type_name.to_string()));
+                        }
+                        // Values are plain serde data, not reflected
+                        return Ok(());
+                    }
+                }
+                for (_, value) in map.iter() {
+                    self.visit(value)?;
+                }
+            }
+            Value::Seq(values) => {
+                for value in values {
+                    self.visit(value)?;
+                }
+            }
+            Value::Option(Some(value)) => self.visit(value)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Handles are written as a UUID string, a path relative to the asset root, or the UUID's bytes
+    fn handle(&mut self, value: &Value) {
+        let asset_ref = match value {
+            Value::String(s) => match uuid::Uuid::parse_str(s) {
+                Ok(uuid) => AssetRef::Uuid(AssetUuid(*uuid.as_bytes())),
+                Err(_) => AssetRef::Path(self.asset_root.join(s)),
+            },
+            Value::Seq(bytes) if bytes.len() == 16 => {
+                let mut uuid = [0; 16];
+                for (byte, value) in uuid.iter_mut().zip(bytes) {
+                    if let Value::Number(Number::Integer(value)) = value {
+                        *byte = *value as u8;
+                    }
+                }
+                AssetRef::Uuid(AssetUuid(uuid))
+            }
+            _ => return,
+        };
+        if asset_ref != AssetRef::Uuid(AssetUuid::default()) && !self.load_deps.contains(&asset_ref)
+        {
+            self.load_deps.push(asset_ref);
+        }
+    }
+}
+
+/// Imports `.scn` scenes, written in RON, as a [ReflectScene], validating their components against a shared [TypeRegistry].
+///
+/// Importers are chosen by the last extension of a file, so [AssetPlugin](crate::AssetPlugin) registers
+/// this importer for `.ron` too, which imports `.scn.ron` scenes. Register another importer for `"ron"`
+/// to import other RON files with it instead.
+///
+/// Handles in components are load dependencies of the scene.
+#[derive(TypeUuid, Clone)]
+#[uuid = "781d815f-82d9-4819-920c-db2baa7fa348"]
+pub struct SceneImporter {
+    registry: TypeRegistryArc,
+    asset_root: PathBuf,
+}
+
+impl SceneImporter {
+    /// An importer that validates against `registry` and resolves handle paths relative to `asset_root`
+    pub fn new<P: AsRef<Path>>(registry: TypeRegistryArc, asset_root: P) -> Self {
+        SceneImporter {
+            registry,
+            asset_root: asset_root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Checks the types of every component and collects the assets their handles point to
+    fn validate(
+        &self,
+        text: &str,
+    ) -> std::result::Result<(ReflectScene, Vec<AssetRef>), SceneError> {
+        let file: Vec<SceneFileEntity> = ron::de::from_str(text)?;
+        let registry = self.registry.read();
+        let mut validator = Validator {
+            registry: &registry,
+            asset_root: &self.asset_root,
+            load_deps: Vec::new(),
+        };
+        let mut scene = ReflectScene {
+            entities: Vec::new(),
+            source: text.to_string(),
+        };
+        for entity in file {
+            let mut components = Vec::new();
+            for (index, component) in entity.components.iter().enumerate() {
+                let type_name = match component {
+                    Value::Map(map) => match map_entry(map, "type") {
+                        Some(Value::String(type_name)) => Some(type_name.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+                .ok_or(SceneError::MissingType {
+                    entity: entity.entity,
+                    index,
+                })?;
+                let registration = registry
+                    .get_with_name(&type_name)
+                    .ok_or_else(|| SceneError::UnregisteredType(type_name.clone()))?;
+                if registration.data::<ReflectComponent>().is_none() {
+                    return Err(SceneError::NotAComponent(type_name));
+                }
+                validator.visit(component)?;
+                components.push(SceneComponent { type_name });
+            }
+            scene.entities.push(SceneEntity {
+                entity: entity.entity,
+                components,
+            });
+        }
+        Ok((scene, validator.load_deps))
+    }
+
+    /// Validates the scene, then deserializes every component field by field, including those
+    /// holding handles
+    async fn parse(
+        &self,
+        text: &str,
+    ) -> std::result::Result<(ReflectScene, Vec<AssetRef>), SceneError> {
+        let (scene, load_deps) = self.validate(text)?;
+        let (ref_op_tx, _ref_op_rx) = unbounded();
+        SerdeContext::with(&ImportLoaderInfo, ref_op_tx, async {
+            deserialize_scene(&self.registry.read(), text)
+        })
+        .await?;
+        Ok((scene, load_deps))
+    }
+}
+
+impl FromResources for SceneImporter {
+    fn from_resources(resources: &Resources) -> Self {
+        let registry = resources
+            .get::<TypeRegistryArc>()
+            .map(|registry| (*registry).clone())
+            .unwrap_or_default();
+        // Like the default importers, resolve paths against the directory the asset daemon imports from
+        let asset_root = resources
+            .get::<AssetServer>()
+            .and_then(|asset_server| asset_server.asset_dir().map(Path::to_path_buf))
+            .unwrap_or_default();
+        SceneImporter::new(registry, asset_root)
+    }
+}
+
+impl ImporterExtensions for SceneImporter {
+    const EXTENSIONS: &'static [&'static str] = &["scn", "ron"];
+}
+
+impl AsyncImporter for SceneImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        2
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = ();
+
+    type State = NamedState;
+
+    /// Parses and validates the scene.
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        _options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        Box::pin(async move {
+            let mut text = String::new();
+            source.read_to_string(&mut text).await?;
+            let (scene, load_deps) = self
+                .parse(&text)
+                .await
+                .map_err(|e| Error::Boxed(Box::new(e)))?;
+            Ok(ImporterValue {
+                assets: vec![ImportedAsset {
+                    id: state.id("asset"),
+                    search_tags: vec![],
+                    build_deps: vec![],
+                    load_deps,
+                    build_pipeline: None,
+                    asset_data: Box::new(scene),
+                }],
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_reflect::ReflectRef;
+
+    #[derive(bevy_reflect::Reflect, Default)]
+    #[reflect(Component)]
+    struct Label {
+        text: String,
+    }
+
+    #[derive(bevy_reflect::Reflect, Default)]
+    struct NotAComponent {
+        text: String,
+    }
+
+    fn importer() -> SceneImporter {
+        let registry = TypeRegistryArc::default();
+        {
+            let mut registry = registry.write();
+            registry.register::<Label>();
+            registry.register::<NotAComponent>();
+            registry.register::<String>();
+        }
+        SceneImporter::new(registry, "assets")
+    }
+
+    fn scene(type_name: &str, text: &str) -> String {
+        format!(
+            r#"[
+    (
+        entity: 0,
+        // a comment with ] and ) in it
+        components: [
+            {{
+                "type": "{}",
+                "struct": {{
+                    /* and [ another } */
+                    "text": {{
+                        "type": "alloc::string::String",
+                        "value": {},
+                    }},
+                }},
+            }},
+        ],
+    ),
+]"#,
+            type_name, text
+        )
+    }
+
+    fn label_text(importer: &SceneImporter, scene: &ReflectScene) -> String {
+        let entities = scene.reflect_components(&importer.registry.read()).unwrap();
+        assert_eq!(entities.len(), 1);
+        let (entity, components) = &entities[0];
+        assert_eq!(*entity, 0);
+        assert_eq!(components.len(), 1);
+        match components[0].reflect_ref() {
+            ReflectRef::Struct(label) => label
+                .field("text")
+                .and_then(|text| text.downcast_ref::<String>())
+                .cloned()
+                .unwrap(),
+            _ => panic!("expected a struct"),
+        }
+    }
+
+    #[test]
+    fn strings_with_brackets_and_quotes() {
+        let importer = importer();
+        let label = std::any::type_name::<Label>();
+        for (source, text) in &[
+            (r#""a ] b ) c } d""#, "a ] b ) c } d"),
+            (
+                r#""quoted \"]\" and '[' chars""#,
+                r#"quoted "]" and '[' chars"#,
+            ),
+            (r###"r#"raw "]" string"#"###, r#"raw "]" string"#),
+        ] {
+            let (scene, load_deps) =
+                futures_executor::block_on(importer.parse(&scene(label, source))).unwrap();
+            assert!(load_deps.is_empty());
+            assert_eq!(scene.entities[0].components[0].type_name, label);
+            assert_eq!(label_text(&importer, &scene), *text);
+        }
+    }
+
+    #[test]
+    fn rejects_unregistered_types() {
+        let err = importer()
+            .validate(&scene("game::Missing", r#""text""#))
+            .unwrap_err();
+        assert!(matches!(err, SceneError::UnregisteredType(name) if name == "game::Missing"));
+    }
+
+    #[test]
+    fn rejects_types_that_are_not_components() {
+        let name = std::any::type_name::<NotAComponent>();
+        let err = importer().validate(&scene(name, r#""text""#)).unwrap_err();
+        assert!(matches!(err, SceneError::NotAComponent(found) if found == name));
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        let label = std::any::type_name::<Label>();
+        let source = scene(label, "5");
+        let importer = importer();
+        assert!(importer.validate(&source).is_ok());
+        assert!(matches!(
+            futures_executor::block_on(importer.parse(&source)),
+            Err(SceneError::Ron(_))
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::NamedState;
+use atelier_importer::{AsyncImporter, Error, ImportOp, ImportedAsset, ImporterValue, Result};
+use futures_core::future::BoxFuture;
+use futures_io::AsyncRead;
+use futures_util::AsyncReadExt;
+use serde::{de::DeserializeOwned, Serialize};
+use std::marker::PhantomData;
+use thiserror::Error;
+use type_uuid::TypeUuid;
+
+/// An error in a data asset file, located by line and column (both starting at 1)
+#[derive(Error, Debug)]
+#[error("Failed to parse {format} at line {line}, column {column}: {message}")]
+pub struct DataParseError {
+    pub format: &'static str,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// A text format data assets can be written in
+pub trait DataFormat: Send + Sync + 'static {
+    const NAME: &'static str;
+    /// Distinguishes importers of the same asset type in different formats
+    const UUID: type_uuid::Bytes;
+
+    fn parse<T: DeserializeOwned>(text: &str) -> std::result::Result<T, DataParseError>;
+}
+
+pub struct Ron;
+
+impl DataFormat for Ron {
+    const NAME: &'static str = "RON";
+    const UUID: type_uuid::Bytes = *b"atelier/ron     ";
+
+    fn parse<T: DeserializeOwned>(text: &str) -> std::result::Result<T, DataParseError> {
+        ron::de::from_str(text).map_err(|err| DataParseError {
+            format: Self::NAME,
+            line: err.position.line,
+            column: err.position.col,
+            message: err.code.to_string(),
+        })
+    }
+}
+
+pub struct Json;
+
+impl DataFormat for Json {
+    const NAME: &'static str = "JSON";
+    const UUID: type_uuid::Bytes = *b"atelier/json    ";
+
+    fn parse<T: DeserializeOwned>(text: &str) -> std::result::Result<T, DataParseError> {
+        serde_json::from_str(text).map_err(|err| DataParseError {
+            format: Self::NAME,
+            line: err.line(),
+            column: err.column(),
+            message: err.to_string(),
+        })
+    }
+}
+
+pub struct Toml;
+
+impl DataFormat for Toml {
+    const NAME: &'static str = "TOML";
+    const UUID: type_uuid::Bytes = *b"atelier/toml    ";
+
+    fn parse<T: DeserializeOwned>(text: &str) -> std::result::Result<T, DataParseError> {
+        toml::from_str(text).map_err(|err| {
+            // toml reports zero-based positions
+            let (line, column) = err.line_col().map_or((0, 0), |(l, c)| (l + 1, c + 1));
+            DataParseError {
+                format: Self::NAME,
+                line,
+                column,
+                message: err.to_string(),
+            }
+        })
+    }
+}
+
+/// Combines two type UUIDs, e.g. those of an asset type and a format to give their importer its own
+pub const fn xor_uuid(a: type_uuid::Bytes, b: type_uuid::Bytes) -> type_uuid::Bytes {
+    let mut out = [0; 16];
+    let mut i = 0;
+    while i < 16 {
+        out[i] = a[i] ^ b[i];
+        i += 1;
+    }
+    out
+}
+
+/// Imports a single asset of type `T` from a text file in format `F`.
+///
+/// Register it for an extension with [AddAsset::add_importer](crate::AddAsset::add_importer).
+/// The asset daemon picks importers by the last extension of a file, so give each data type its own,
+/// e.g. `sword.item` rather than `sword.item.ron`. Extensions containing a `.` are rejected.
+pub struct SerdeImporter<T, F> {
+    marker: PhantomData<fn() -> (T, F)>,
+}
+
+pub type RonImporter<T> = SerdeImporter<T, Ron>;
+pub type JsonImporter<T> = SerdeImporter<T, Json>;
+pub type TomlImporter<T> = SerdeImporter<T, Toml>;
+
+impl<T, F> Default for SerdeImporter<T, F> {
+    fn default() -> Self {
+        SerdeImporter {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: TypeUuid, F: DataFormat> TypeUuid for SerdeImporter<T, F> {
+    const UUID: type_uuid::Bytes = xor_uuid(T::UUID, F::UUID);
+}
+
+impl<T, F> AsyncImporter for SerdeImporter<T, F>
+where
+    T: TypeUuid + Serialize + DeserializeOwned + Send + Sync + 'static,
+    F: DataFormat,
+{
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        1
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = ();
+
+    type State = NamedState;
+
+    /// Parses the file as a `T`.
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        _options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        Box::pin(async move {
+            let id = state.id("asset");
+            let mut text = String::new();
+            source.read_to_string(&mut text).await?;
+            let asset: T = F::parse(&text).map_err(|e| Error::Boxed(Box::new(e)))?;
+            Ok(ImporterValue {
+                assets: vec![ImportedAsset {
+                    id,
+                    search_tags: vec![],
+                    build_deps: vec![],
+                    load_deps: vec![],
+                    build_pipeline: None,
+                    asset_data: Box::new(asset),
+                }],
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Item {
+        name: String,
+        damage: u32,
+    }
+
+    fn position<F: DataFormat>(text: &str) -> (usize, usize) {
+        let err = F::parse::<Item>(text).unwrap_err();
+        assert_eq!(err.format, F::NAME);
+        (err.line, err.column)
+    }
+
+    #[test]
+    fn parses_each_format() {
+        let sword = Item {
+            name: "sword".to_string(),
+            damage: 3,
+        };
+        assert_eq!(
+            Ron::parse::<Item>("(name: \"sword\", damage: 3)").unwrap(),
+            sword
+        );
+        assert_eq!(
+            Json::parse::<Item>(r#"{"name": "sword", "damage": 3}"#).unwrap(),
+            sword
+        );
+        assert_eq!(
+            Toml::parse::<Item>("name = \"sword\"\ndamage = 3\n").unwrap(),
+            sword
+        );
+    }
+
+    #[test]
+    fn ron_errors_are_located() {
+        let text = "(\n  name: \"sword\",\n  damage: \"high\",\n)";
+        assert_eq!(position::<Ron>(text), (3, 11));
+    }
+
+    #[test]
+    fn json_errors_are_located() {
+        assert_eq!(position::<Json>("{\"name\": \"sword\",\n}"), (2, 1));
+        let err = Json::parse::<Item>("{\"name\": \"sword\",\n}").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Failed to parse JSON at line 2, column 1: "));
+    }
+
+    #[test]
+    fn toml_errors_are_located_from_one() {
+        assert_eq!(position::<Toml>("name = \"sword\"\n= 3\n"), (2, 1));
+    }
+}
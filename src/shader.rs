@@ -0,0 +1,496 @@
+use crate::NamedState;
+use atelier_core::AssetRef;
+use atelier_importer::{AsyncImporter, Error, ImportOp, ImportedAsset, ImporterValue, Result};
+use bevy_log::*;
+use futures_core::future::BoxFuture;
+use futures_io::AsyncRead;
+use futures_util::AsyncReadExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use type_uuid::*;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderLanguage {
+    Glsl,
+    Wgsl,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Compute,
+}
+
+/// Preprocessed shader source
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[uuid = "e2afb8fe-9831-4ab7-9aa0-051caa1c853c"]
+pub struct Shader {
+    pub language: ShaderLanguage,
+    /// The stage implied by the file extension, if any
+    pub stage: Option<ShaderStage>,
+    /// The source with every `#include` replaced by the included file
+    pub source: String,
+    /// Defines from the importer options followed by those `#define`d in the source, in order
+    pub defines: Vec<(String, Option<String>)>,
+    /// Files that were included, relative to the asset root
+    pub includes: Vec<String>,
+    /// Includes that were not found, as the paths they were looked for at relative to the asset root.
+    /// Their `#include` lines are kept, and the shader is reimported once one of the files is created.
+    pub missing_includes: Vec<String>,
+}
+
+/// Import settings for shaders, editable in their `.meta` files
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, Default)]
+#[uuid = "d2896428-1860-46a4-94bb-f957348f97d9"]
+#[serde(default)]
+pub struct ShaderImporterOptions {
+    /// Defines inserted at the top of the source, after any `#version` directive
+    pub defines: Vec<(String, Option<String>)>,
+    /// The shader's directory relative to the asset root. Quoted includes in the shader are looked
+    /// for there first, so shaders outside of the asset root's top level need this to find files
+    /// next to them.
+    pub directory: String,
+}
+
+/// Errors that occur while preprocessing a shader
+#[derive(Error, Debug)]
+pub enum ShaderImportError {
+    #[error("Shader source is not valid UTF-8.")]
+    InvalidUtf8(#[from] std::string::FromUtf8Error),
+    #[error("Failed to read included file {path:?}: {error}")]
+    Include { path: PathBuf, error: io::Error },
+    #[error("Malformed #include on line {line}: expected a quoted or bracketed path.")]
+    MalformedInclude { line: usize },
+    #[error("{path:?} includes itself.")]
+    RecursiveInclude { path: String },
+}
+
+struct Preprocessor<'a> {
+    asset_root: &'a Path,
+    directory: &'a str,
+    output: String,
+    defines: Vec<(String, Option<String>)>,
+    includes: Vec<String>,
+    missing_includes: Vec<String>,
+    stack: Vec<String>,
+}
+
+/// The path of an include directive, and whether it is quoted rather than bracketed
+fn include_path(directive: &str) -> Option<(&str, bool)> {
+    let directive = directive.trim();
+    let (open, close) = match directive.chars().next()? {
+        '"' => ('"', '"'),
+        '<' => ('<', '>'),
+        _ => return None,
+    };
+    let rest = &directive[open.len_utf8()..];
+    rest.find(close).map(|end| (&rest[..end], open == '"'))
+}
+
+/// Joins an include path onto the directory of the including file, resolving `.` and `..`.
+/// `None` if the path leaves the asset root.
+fn join_relative(including: &str, path: &str) -> Option<String> {
+    let directory = including.rsplitn(2, '/').nth(1).unwrap_or("");
+    join_directory(directory, path)
+}
+
+/// Joins an include path onto a directory, resolving `.` and `..`.
+/// `None` if the path leaves the asset root.
+fn join_directory(directory: &str, path: &str) -> Option<String> {
+    let mut parts = Vec::new();
+    for part in directory.split('/').chain(path.split('/')) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+impl<'a> Preprocessor<'a> {
+    fn process(&mut self, source: &str) -> std::result::Result<(), ShaderImportError> {
+        for (index, line) in source.lines().enumerate() {
+            let trimmed = line.trim_start();
+            if let Some(directive) = trimmed.strip_prefix("#include") {
+                let (path, quoted) = include_path(directive)
+                    .ok_or(ShaderImportError::MalformedInclude { line: index + 1 })?;
+                if !self.include(path, quoted)? {
+                    self.output.push_str(line);
+                    self.output.push('\n');
+                }
+                continue;
+            }
+            if let Some(define) = trimmed.strip_prefix("#define") {
+                let mut parts = define.trim().splitn(2, char::is_whitespace);
+                if let Some(name) = parts.next().filter(|name| !name.is_empty()) {
+                    let value = parts.next().map(|value| value.trim().to_string());
+                    self.defines.push((name.to_string(), value));
+                }
+            }
+            self.output.push_str(line);
+            self.output.push('\n');
+        }
+        Ok(())
+    }
+
+    /// Inlines a file, once. Later includes of the same file are skipped, like `#pragma once`.
+    ///
+    /// Quoted paths are looked for next to the including file first, or in the options' directory
+    /// when the including file is the imported shader, then like bracketed paths relative to the
+    /// asset root. Returns `false` if the file doesn't exist.
+    fn include(
+        &mut self,
+        path: &str,
+        quoted: bool,
+    ) -> std::result::Result<bool, ShaderImportError> {
+        let mut candidates = Vec::new();
+        if quoted {
+            let relative = match self.stack.last() {
+                Some(including) => join_relative(including, path),
+                None => join_directory(self.directory, path),
+            };
+            if let Some(relative) = relative {
+                candidates.push(relative);
+            }
+        }
+        if let Some(from_root) = join_relative("", path) {
+            if !candidates.contains(&from_root) {
+                candidates.push(from_root);
+            }
+        }
+        let path = match candidates
+            .iter()
+            .find(|candidate| self.asset_root.join(candidate).is_file())
+        {
+            Some(path) => path.clone(),
+            None => {
+                for candidate in candidates {
+                    if !self.missing_includes.contains(&candidate) {
+                        self.missing_includes.push(candidate);
+                    }
+                }
+                return Ok(false);
+            }
+        };
+        if self.stack.contains(&path) {
+            return Err(ShaderImportError::RecursiveInclude { path });
+        }
+        if self.includes.contains(&path) {
+            return Ok(true);
+        }
+        let full_path = self.asset_root.join(&path);
+        let source =
+            std::fs::read_to_string(&full_path).map_err(|error| ShaderImportError::Include {
+                path: full_path.clone(),
+                error,
+            })?;
+        self.includes.push(path.clone());
+        self.stack.push(path);
+        self.process(&source)?;
+        self.stack.pop();
+        Ok(true)
+    }
+}
+
+/// Preprocesses shader sources, inlining `#include "path"` and `#include <path>`.
+///
+/// Quoted includes are resolved relative to the including file first, then to the asset root.
+/// Importers aren't told the path of the file they import, so includes in the imported shader itself
+/// are resolved relative to the `directory` in its options. Bracketed includes are resolved relative
+/// to the asset root.
+///
+/// Every included file is a build dependency, so editing it reimports the shaders that include it.
+/// So are the paths missing includes were looked for at, so creating one reimports them too.
+#[derive(TypeUuid)]
+#[uuid = "cedc1a24-c9af-40f0-9ec8-e5154f0c59c0"]
+pub struct ShaderImporter {
+    asset_root: PathBuf,
+    language: ShaderLanguage,
+    stage: Option<ShaderStage>,
+}
+
+impl ShaderImporter {
+    pub fn new<P: AsRef<Path>>(
+        asset_root: P,
+        language: ShaderLanguage,
+        stage: Option<ShaderStage>,
+    ) -> Self {
+        ShaderImporter {
+            asset_root: asset_root.as_ref().to_path_buf(),
+            language,
+            stage,
+        }
+    }
+
+    fn preprocess(
+        &self,
+        source: &str,
+        options: &ShaderImporterOptions,
+    ) -> std::result::Result<Shader, ShaderImportError> {
+        let mut preprocessor = Preprocessor {
+            asset_root: &self.asset_root,
+            directory: &options.directory,
+            output: String::new(),
+            defines: options.defines.clone(),
+            includes: Vec::new(),
+            missing_includes: Vec::new(),
+            stack: Vec::new(),
+        };
+        let mut body = source;
+        // GLSL requires #version to come first, so defines go after it
+        if let Some(first) = source.lines().next() {
+            if first.trim_start().starts_with("#version") {
+                preprocessor.output.push_str(first);
+                preprocessor.output.push('\n');
+                body = source[first.len()..].trim_start_matches(|c| c == '\r' || c == '\n');
+            }
+        }
+        if self.language == ShaderLanguage::Glsl {
+            for (name, value) in &options.defines {
+                preprocessor.output.push_str("#define ");
+                preprocessor.output.push_str(name);
+                if let Some(value) = value {
+                    preprocessor.output.push(' ');
+                    preprocessor.output.push_str(value);
+                }
+                preprocessor.output.push('\n');
+            }
+        }
+        preprocessor.process(body)?;
+        Ok(Shader {
+            language: self.language,
+            stage: self.stage,
+            source: preprocessor.output,
+            defines: preprocessor.defines,
+            includes: preprocessor.includes,
+            missing_includes: preprocessor.missing_includes,
+        })
+    }
+}
+
+/// File extensions the shader importer is registered for, with the language and stage of each
+pub const SHADER_EXTENSIONS: &[(&str, ShaderLanguage, Option<ShaderStage>)] = &[
+    ("vert", ShaderLanguage::Glsl, Some(ShaderStage::Vertex)),
+    ("frag", ShaderLanguage::Glsl, Some(ShaderStage::Fragment)),
+    ("comp", ShaderLanguage::Glsl, Some(ShaderStage::Compute)),
+    ("glsl", ShaderLanguage::Glsl, None),
+    ("wgsl", ShaderLanguage::Wgsl, None),
+];
+
+impl AsyncImporter for ShaderImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        3
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = ShaderImporterOptions;
+
+    type State = NamedState;
+
+    /// Preprocesses the shader source.
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        let options = options.clone();
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            source.read_to_end(&mut bytes).await?;
+            let text = String::from_utf8(bytes)
+                .map_err(|e| Error::Boxed(Box::new(ShaderImportError::from(e))))?;
+            let shader = self
+                .preprocess(&text, &options)
+                .map_err(|e| Error::Boxed(Box::new(e)))?;
+            for path in &shader.missing_includes {
+                warn!("shader includes {:?}, which does not exist", path);
+            }
+            let build_deps = shader
+                .includes
+                .iter()
+                .chain(&shader.missing_includes)
+                .map(|path| AssetRef::Path(self.asset_root.join(path)))
+                .collect();
+            Ok(ImporterValue {
+                assets: vec![ImportedAsset {
+                    id: state.id("asset"),
+                    search_tags: vec![],
+                    build_deps,
+                    load_deps: vec![],
+                    build_pipeline: None,
+                    asset_data: Box::new(shader),
+                }],
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn asset_root(files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("shader-{}", uuid::Uuid::new_v4()));
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    fn options(directory: &str) -> ShaderImporterOptions {
+        ShaderImporterOptions {
+            directory: directory.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn join_relative_resolves_dots() {
+        assert_eq!(
+            join_relative("shaders/water.frag", "common.glsl").as_deref(),
+            Some("shaders/common.glsl")
+        );
+        assert_eq!(
+            join_relative("shaders/lib/noise.glsl", "../common.glsl").as_deref(),
+            Some("shaders/common.glsl")
+        );
+        assert_eq!(
+            join_relative("water.frag", "./lib//noise.glsl").as_deref(),
+            Some("lib/noise.glsl")
+        );
+        assert_eq!(join_relative("water.frag", "../common.glsl"), None);
+        assert_eq!(
+            join_directory("shaders/", "common.glsl").as_deref(),
+            Some("shaders/common.glsl")
+        );
+        assert_eq!(
+            join_directory("", "common.glsl").as_deref(),
+            Some("common.glsl")
+        );
+    }
+
+    #[test]
+    fn includes_resolve_against_the_shader_directory() {
+        let root = asset_root(&[
+            ("shaders/water.frag", ""),
+            ("shaders/common.glsl", "float common;"),
+            ("common.glsl", "float root;"),
+        ]);
+        let importer = ShaderImporter::new(&root, ShaderLanguage::Glsl, None);
+        let shader = importer
+            .preprocess("#include \"common.glsl\"\n", &options("shaders"))
+            .unwrap();
+        assert_eq!(shader.source, "float common;\n");
+        assert_eq!(shader.includes, vec!["shaders/common.glsl"]);
+        let shader = importer
+            .preprocess("#include <common.glsl>\n", &options("shaders"))
+            .unwrap();
+        assert_eq!(shader.source, "float root;\n");
+        assert_eq!(shader.includes, vec!["common.glsl"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn files_are_included_once() {
+        let root = asset_root(&[
+            ("lib/a.glsl", "#include \"common.glsl\"\nfloat a;"),
+            ("lib/common.glsl", "float common;"),
+        ]);
+        let importer = ShaderImporter::new(&root, ShaderLanguage::Glsl, None);
+        let shader = importer
+            .preprocess(
+                "#include \"lib/a.glsl\"\n#include <lib/common.glsl>\n",
+                &options(""),
+            )
+            .unwrap();
+        assert_eq!(shader.source, "float common;\nfloat a;\n");
+        assert_eq!(shader.includes, vec!["lib/a.glsl", "lib/common.glsl"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn recursive_includes_are_rejected() {
+        let root = asset_root(&[
+            ("a.glsl", "#include \"b.glsl\""),
+            ("b.glsl", "#include \"a.glsl\""),
+        ]);
+        let importer = ShaderImporter::new(&root, ShaderLanguage::Glsl, None);
+        match importer.preprocess("#include \"a.glsl\"\n", &options("")) {
+            Err(ShaderImportError::RecursiveInclude { path }) => assert_eq!(path, "a.glsl"),
+            other => panic!("expected a recursive include, got {:?}", other.map(|_| ())),
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn defines_follow_the_version_directive() {
+        let importer = ShaderImporter::new(std::env::temp_dir(), ShaderLanguage::Glsl, None);
+        let options = ShaderImporterOptions {
+            defines: vec![
+                ("SHADOWS".to_string(), None),
+                ("LIGHTS".to_string(), Some("4".to_string())),
+            ],
+            ..Default::default()
+        };
+        let shader = importer
+            .preprocess("#version 450\n#define FOG 1\nvoid main() {}\n", &options)
+            .unwrap();
+        assert_eq!(
+            shader.source,
+            "#version 450\n#define SHADOWS\n#define LIGHTS 4\n#define FOG 1\nvoid main() {}\n"
+        );
+        assert_eq!(
+            shader.defines,
+            vec![
+                ("SHADOWS".to_string(), None),
+                ("LIGHTS".to_string(), Some("4".to_string())),
+                ("FOG".to_string(), Some("1".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_includes_are_build_dependencies() {
+        let root = asset_root(&[("shaders/lights.glsl", "float lights;")]);
+        let importer =
+            ShaderImporter::new(&root, ShaderLanguage::Glsl, Some(ShaderStage::Fragment));
+        let mut source: &[u8] = b"#include \"lights.glsl\"\n#include \"fog.glsl\"\n";
+        let mut op = ImportOp::default();
+        let mut state = NamedState::default();
+        let value = futures_executor::block_on(importer.import(
+            &mut op,
+            &mut source,
+            &options("shaders"),
+            &mut state,
+        ))
+        .unwrap();
+        let asset = &value.assets[0];
+        assert_eq!(
+            asset.build_deps,
+            vec![
+                AssetRef::Path(root.join("shaders/lights.glsl")),
+                AssetRef::Path(root.join("shaders/fog.glsl")),
+                AssetRef::Path(root.join("fog.glsl")),
+            ]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
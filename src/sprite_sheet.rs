@@ -0,0 +1,142 @@
+use crate::{image::Image, texture_atlas::AtlasRect, AssetServer};
+use atelier_core::AssetUuid;
+use atelier_loader::handle::Handle;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use type_uuid::*;
+
+/// A named region of a sprite sheet
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NamedFrame {
+    pub name: String,
+    pub rect: AtlasRect,
+}
+
+/// How a sprite sheet is divided into frames, set in the `sheet` field of [ImageImporterOptions](crate::image::ImageImporterOptions)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SpriteSheetLayout {
+    /// Cells of equal size, read row by row starting at the top left
+    Grid {
+        cell_width: u32,
+        cell_height: u32,
+        /// Number of columns, or as many as fit if `None`
+        #[serde(default)]
+        columns: Option<u32>,
+        /// Number of rows, or as many as fit if `None`
+        #[serde(default)]
+        rows: Option<u32>,
+        /// Pixels between neighbouring cells
+        #[serde(default)]
+        padding: u32,
+        /// Pixels between the edge of the image and the first cell
+        #[serde(default)]
+        offset: u32,
+        /// Names for the frames, in order. Unnamed frames are named by their index.
+        #[serde(default)]
+        names: Vec<String>,
+    },
+    /// Explicitly placed frames
+    Frames(Vec<NamedFrame>),
+}
+
+impl SpriteSheetLayout {
+    /// The named frames of a sheet that is `width` by `height` pixels, in order
+    pub fn frames(&self, width: u32, height: u32) -> Vec<NamedFrame> {
+        match self {
+            SpriteSheetLayout::Grid {
+                cell_width,
+                cell_height,
+                columns,
+                rows,
+                padding,
+                offset,
+                names,
+            } => {
+                let fitting = |size: u32, cell: u32| {
+                    if cell == 0 || size < offset + cell {
+                        0
+                    } else {
+                        (size - offset - cell) / (cell + padding) + 1
+                    }
+                };
+                let columns = columns.unwrap_or_else(|| fitting(width, *cell_width));
+                let rows = rows.unwrap_or_else(|| fitting(height, *cell_height));
+                let mut frames = Vec::new();
+                for row in 0..rows {
+                    for column in 0..columns {
+                        let index = frames.len();
+                        frames.push(NamedFrame {
+                            name: names
+                                .get(index)
+                                .cloned()
+                                .unwrap_or_else(|| index.to_string()),
+                            rect: AtlasRect {
+                                x: offset + column * (cell_width + padding),
+                                y: offset + row * (cell_height + padding),
+                                width: *cell_width,
+                                height: *cell_height,
+                            },
+                        });
+                    }
+                }
+                frames
+            }
+            SpriteSheetLayout::Frames(frames) => frames.clone(),
+        }
+    }
+}
+
+/// A frame of a [SpriteSheet]: its region of the sheet and the UUID of its sub-image
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SpriteFrame {
+    pub rect: AtlasRect,
+    pub image: AssetUuid,
+}
+
+/// The frames an image was divided into on import.
+///
+/// Imported alongside the full sheet [Image] and an `Image` per frame. [AssetServer::load] on the
+/// image's path returns the full sheet `Image`; load the sheet with
+/// [AssetServer::load_typed], and the images of its frames with [SpriteSheet::frame_handle].
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[uuid = "a561a9c7-f1f1-4445-a56d-3de13eebdf01"]
+pub struct SpriteSheet {
+    /// The full sheet image
+    pub image: AssetUuid,
+    pub frames: Vec<SpriteFrame>,
+    pub names: HashMap<String, usize>,
+}
+
+impl SpriteSheet {
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    pub fn frame(&self, name: &str) -> Option<&SpriteFrame> {
+        self.index_of(name).map(|index| &self.frames[index])
+    }
+
+    /// The UUID of the sub-image for a frame
+    pub fn frame_image(&self, name: &str) -> Option<AssetUuid> {
+        self.frame(name).map(|frame| frame.image)
+    }
+
+    /// A handle to the sub-image for a frame, which is already loading if the sheet has been loaded
+    pub fn frame_handle(&self, name: &str, asset_server: &AssetServer) -> Option<Handle<Image>> {
+        self.frame_image(name)
+            .map(|image| asset_server.load_by_id(image))
+    }
+
+    /// A handle to the full sheet image
+    pub fn image_handle(&self, asset_server: &AssetServer) -> Handle<Image> {
+        asset_server.load_by_id(self.image)
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
@@ -0,0 +1,350 @@
+use crate::{xor_uuid, NamedState};
+use atelier_importer::{AsyncImporter, Error, ImportOp, ImportedAsset, ImporterValue, Result};
+use futures_core::future::BoxFuture;
+use futures_io::AsyncRead;
+use futures_util::AsyncReadExt;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashMap, fmt, marker::PhantomData};
+use thiserror::Error;
+use type_uuid::*;
+
+/// Rows of type `R` read from a spreadsheet, with lookup by the key column
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Table<R> {
+    pub rows: Vec<R>,
+    /// The name of the column rows are keyed by
+    pub key_column: String,
+    pub keys: HashMap<String, usize>,
+}
+
+impl<R: TypeUuid> TypeUuid for Table<R> {
+    const UUID: type_uuid::Bytes = xor_uuid(R::UUID, *b"atelier/table   ");
+}
+
+impl<R> Table<R> {
+    /// The row whose key column holds `key`
+    pub fn get(&self, key: &str) -> Option<&R> {
+        self.keys.get(key).map(|&index| &self.rows[index])
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.keys.contains_key(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &R> {
+        self.rows.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
+/// Import settings for tables, editable in their `.meta` files
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug)]
+#[uuid = "557becbb-599b-46d2-bc86-69a51969d68d"]
+#[serde(default)]
+pub struct TableImporterOptions {
+    /// The column rows are keyed by. The first column is used if `None`.
+    pub key_column: Option<String>,
+    pub delimiter: char,
+    /// Trims whitespace around headers and cells
+    pub trim: bool,
+}
+
+impl Default for TableImporterOptions {
+    fn default() -> Self {
+        TableImporterOptions {
+            key_column: None,
+            delimiter: ',',
+            trim: true,
+        }
+    }
+}
+
+/// A cell that could not be read as its field of the row type
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellError {
+    /// Line in the file, starting at 1
+    pub line: u64,
+    pub column: Option<String>,
+    pub value: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for CellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}", self.line)?;
+        if let Some(column) = &self.column {
+            write!(f, ", column {:?}", column)?;
+        }
+        if let Some(value) = &self.value {
+            write!(f, " ({:?})", value)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Errors that occur while importing a table
+#[derive(Error, Debug)]
+pub enum TableError {
+    #[error("Invalid CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Delimiter {0:?} is not a single byte.")]
+    InvalidDelimiter(char),
+    #[error("Key column {0:?} does not exist.")]
+    MissingKeyColumn(String),
+    #[error("Key {key:?} on line {line} is already used by line {first_line}.")]
+    DuplicateKey {
+        key: String,
+        line: u64,
+        first_line: u64,
+    },
+    #[error("{} invalid cells:\n{}", .0.len(), .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n"))]
+    Cells(Vec<CellError>),
+}
+
+fn read_table<R: DeserializeOwned>(
+    bytes: &[u8],
+    options: &TableImporterOptions,
+) -> std::result::Result<Table<R>, TableError> {
+    if !options.delimiter.is_ascii() {
+        return Err(TableError::InvalidDelimiter(options.delimiter));
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter as u8)
+        .trim(if options.trim {
+            csv::Trim::All
+        } else {
+            csv::Trim::None
+        })
+        .from_reader(bytes);
+    let headers = reader.headers()?.clone();
+    let key_column = match &options.key_column {
+        Some(name) => name.clone(),
+        None => headers.get(0).unwrap_or_default().to_string(),
+    };
+    let key_index = headers
+        .iter()
+        .position(|header| header == key_column)
+        .ok_or_else(|| TableError::MissingKeyColumn(key_column.clone()))?;
+
+    let mut table = Table {
+        rows: Vec::new(),
+        key_column,
+        keys: HashMap::new(),
+    };
+    let mut key_lines = HashMap::new();
+    let mut errors = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map_or(0, |position| position.line());
+        let row = match record.deserialize::<R>(Some(&headers)) {
+            Ok(row) => row,
+            Err(err) => {
+                let cell = match err.kind() {
+                    csv::ErrorKind::Deserialize { err, .. } => {
+                        let field = err.field().map(|field| field as usize);
+                        CellError {
+                            line,
+                            column: field.and_then(|field| headers.get(field)).map(Into::into),
+                            value: field.and_then(|field| record.get(field)).map(Into::into),
+                            message: err.kind().to_string(),
+                        }
+                    }
+                    _ => CellError {
+                        line,
+                        column: None,
+                        value: None,
+                        message: err.to_string(),
+                    },
+                };
+                errors.push(cell);
+                continue;
+            }
+        };
+        let key = record.get(key_index).unwrap_or_default().to_string();
+        if let Some(&first_line) = key_lines.get(&key) {
+            return Err(TableError::DuplicateKey {
+                key,
+                line,
+                first_line,
+            });
+        }
+        key_lines.insert(key.clone(), line);
+        table.keys.insert(key, table.rows.len());
+        table.rows.push(row);
+    }
+    if !errors.is_empty() {
+        return Err(TableError::Cells(errors));
+    }
+    Ok(table)
+}
+
+/// Imports a CSV file as a [Table] of `R`, one row per line after the header.
+///
+/// Register one per extension with [AddAsset::add_importer](crate::AddAsset::add_importer), so that
+/// each kind of table gets its own row type, e.g. `units.units` and `weapons.weapons`.
+pub struct TableImporter<R> {
+    marker: PhantomData<fn() -> R>,
+}
+
+impl<R> Default for TableImporter<R> {
+    fn default() -> Self {
+        TableImporter {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<R: TypeUuid> TypeUuid for TableImporter<R> {
+    const UUID: type_uuid::Bytes = xor_uuid(R::UUID, *b"atelier/csv     ");
+}
+
+impl<R> AsyncImporter for TableImporter<R>
+where
+    R: TypeUuid + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        1
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = TableImporterOptions;
+
+    type State = NamedState;
+
+    /// Reads every row, reporting all cells that fail to deserialize.
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        let options = options.clone();
+        Box::pin(async move {
+            let id = state.id("asset");
+            let mut bytes = Vec::new();
+            source.read_to_end(&mut bytes).await?;
+            let table: Table<R> =
+                read_table(&bytes, &options).map_err(|e| Error::Boxed(Box::new(e)))?;
+            Ok(ImporterValue {
+                assets: vec![ImportedAsset {
+                    id,
+                    search_tags: vec![],
+                    build_deps: vec![],
+                    load_deps: vec![],
+                    build_pipeline: None,
+                    asset_data: Box::new(table),
+                }],
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Unit {
+        name: String,
+        health: u32,
+    }
+
+    fn read(csv: &str) -> std::result::Result<Table<Unit>, TableError> {
+        read_table(csv.as_bytes(), &TableImporterOptions::default())
+    }
+
+    #[test]
+    fn rows_are_keyed_by_the_first_column() {
+        let table = read("name, health\nknight, 100\narcher, 60\n").unwrap();
+        assert_eq!(table.key_column, "name");
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.get("archer"),
+            Some(&Unit {
+                name: "archer".to_string(),
+                health: 60
+            })
+        );
+        assert!(!table.contains_key("mage"));
+    }
+
+    #[test]
+    fn key_column_option() {
+        let options = TableImporterOptions {
+            key_column: Some("health".to_string()),
+            ..Default::default()
+        };
+        let table: Table<Unit> =
+            read_table(b"name,health\nknight,100\narcher,60\n", &options).unwrap();
+        assert_eq!(
+            table.get("60").map(|unit| unit.name.as_str()),
+            Some("archer")
+        );
+
+        let options = TableImporterOptions {
+            key_column: Some("id".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            read_table::<Unit>(b"name,health\nknight,100\n", &options),
+            Err(TableError::MissingKeyColumn(column)) if column == "id"
+        ));
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let err = read("name,health\nknight,100\narcher,60\nknight,120\n").unwrap_err();
+        match err {
+            TableError::DuplicateKey {
+                key,
+                line,
+                first_line,
+            } => {
+                assert_eq!(key, "knight");
+                assert_eq!(line, 4);
+                assert_eq!(first_line, 2);
+            }
+            err => panic!("expected a duplicate key, got {}", err),
+        }
+    }
+
+    #[test]
+    fn every_bad_cell_is_reported() {
+        let err = read("name,health\nknight,lots\narcher,60\nmage,-5\n").unwrap_err();
+        let cells = match err {
+            TableError::Cells(cells) => cells,
+            err => panic!("expected invalid cells, got {}", err),
+        };
+        assert_eq!(cells.len(), 2);
+        assert_eq!(cells[0].line, 2);
+        assert_eq!(cells[0].column.as_deref(), Some("health"));
+        assert_eq!(cells[0].value.as_deref(), Some("lots"));
+        assert_eq!(cells[1].line, 4);
+        assert_eq!(cells[1].value.as_deref(), Some("-5"));
+    }
+
+    #[test]
+    fn non_ascii_delimiter() {
+        let options = TableImporterOptions {
+            delimiter: '§',
+            ..Default::default()
+        };
+        assert!(matches!(
+            read_table::<Unit>(b"name,health\n", &options),
+            Err(TableError::InvalidDelimiter('§'))
+        ));
+    }
+}
@@ -0,0 +1,456 @@
+use crate::{
+    image::{self, ImageImportError, ImageImporterOptions, IMAGE_EXTENSIONS},
+    NamedState,
+};
+use atelier_core::{AssetRef, AssetUuid};
+use atelier_importer::{AsyncImporter, Error, ImportOp, ImportedAsset, ImporterValue, Result};
+use futures_core::future::BoxFuture;
+use futures_io::AsyncRead;
+use futures_util::AsyncReadExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use type_uuid::*;
+
+/// A rectangle of an atlas image, in pixels
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Where each sprite of a packed texture atlas lies in the atlas image
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[uuid = "64fed229-d69d-41fc-a7a7-196aa1d83396"]
+pub struct TextureAtlasLayout {
+    /// The atlas [Image](crate::image::Image), which is loaded along with the layout
+    pub image: AssetUuid,
+    pub width: u32,
+    pub height: u32,
+    pub rects: Vec<AtlasRect>,
+    pub names: HashMap<String, usize>,
+}
+
+impl TextureAtlasLayout {
+    /// The index of a sprite, named by its path relative to the asset root without the extension
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AtlasRect> {
+        self.index_of(name).map(|index| &self.rects[index])
+    }
+
+    /// The rect of a sprite in normalized texture coordinates: `[min_x, min_y, max_x, max_y]`
+    pub fn uv_rect(&self, index: usize) -> Option<[f32; 4]> {
+        let rect = self.rects.get(index)?;
+        let (width, height) = (self.width as f32, self.height as f32);
+        Some([
+            rect.x as f32 / width,
+            rect.y as f32 / height,
+            (rect.x + rect.width) as f32 / width,
+            (rect.y + rect.height) as f32 / height,
+        ])
+    }
+
+    pub fn len(&self) -> usize {
+        self.rects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+}
+
+/// The contents of an `.atlas` file
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TextureAtlasDescriptor {
+    /// Sprite images or globs matching them, relative to the asset root
+    pub sprites: Vec<String>,
+    /// Empty pixels left between sprites
+    #[serde(default = "default_padding")]
+    pub padding: u32,
+    /// The largest width or height the atlas may grow to
+    #[serde(default = "default_max_size")]
+    pub max_size: u32,
+}
+
+fn default_padding() -> u32 {
+    1
+}
+
+fn default_max_size() -> u32 {
+    4096
+}
+
+/// Errors that occur while packing a texture atlas
+#[derive(Error, Debug)]
+pub enum TextureAtlasError {
+    #[error("Invalid atlas descriptor: {0}")]
+    Descriptor(#[from] ron::Error),
+    #[error("Invalid sprite glob: {0}")]
+    Pattern(#[from] glob::PatternError),
+    #[error("Failed to read sprite {path:?}: {error}")]
+    Io { path: PathBuf, error: io::Error },
+    #[error("Failed to decode sprite {path:?}: {error}")]
+    Image {
+        path: PathBuf,
+        error: ImageImportError,
+    },
+    #[error("Atlas descriptor matches no sprites.")]
+    Empty,
+    #[error("Sprites do not fit into a {max_size}x{max_size} atlas.")]
+    TooLarge { max_size: u32 },
+}
+
+struct Sprite {
+    name: String,
+    path: PathBuf,
+    image: ::image::RgbaImage,
+}
+
+/// Packs the sprites listed by an `.atlas` descriptor into a single [Image](crate::image::Image) and a [TextureAtlasLayout].
+///
+/// Sprites are decoded like the image importer decodes them. The [ImageImporterOptions] in the
+/// atlas's `.meta` file apply to them: sprites are flipped and downscaled before packing, and the
+/// packed RGBA8 atlas is then converted, premultiplied and given mips. `sheet` is ignored.
+///
+/// Every sprite is a build dependency of the atlas, so editing one re-packs it. Globs are only
+/// matched on import, so a sprite added to a matched directory isn't packed until the atlas is
+/// reimported, e.g. by touching the `.atlas` file.
+#[derive(TypeUuid)]
+#[uuid = "a1246f60-5ae2-4eb3-9324-e1d9dee55035"]
+pub struct TextureAtlasImporter {
+    asset_root: PathBuf,
+}
+
+impl TextureAtlasImporter {
+    /// An importer that resolves sprite paths relative to `asset_root`
+    pub fn new<P: AsRef<Path>>(asset_root: P) -> Self {
+        TextureAtlasImporter {
+            asset_root: asset_root.as_ref().to_path_buf(),
+        }
+    }
+
+    fn read_sprites(
+        &self,
+        descriptor: &TextureAtlasDescriptor,
+        options: &ImageImporterOptions,
+    ) -> std::result::Result<Vec<Sprite>, TextureAtlasError> {
+        let mut paths = Vec::new();
+        for pattern in &descriptor.sprites {
+            let pattern = self.asset_root.join(pattern);
+            let matches = glob::glob(&pattern.to_string_lossy())?;
+            for path in matches.filter_map(|entry| entry.ok()) {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        paths.sort();
+
+        let mut sprites = Vec::new();
+        for path in paths {
+            let name = path
+                .strip_prefix(&self.asset_root)
+                .unwrap_or(&path)
+                .with_extension("")
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let bytes = std::fs::read(&path).map_err(|error| TextureAtlasError::Io {
+                path: path.clone(),
+                error,
+            })?;
+            // Decoded as the format the image importer is registered for with the sprite's extension
+            let format = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .and_then(|ext| {
+                    IMAGE_EXTENSIONS
+                        .iter()
+                        .find(|(image_ext, _)| *image_ext == ext)
+                        .map(|(_, format)| *format)
+                });
+            let decoded =
+                image::decode(&bytes, format).map_err(|error| TextureAtlasError::Image {
+                    path: path.clone(),
+                    error,
+                })?;
+            let image = options.transform(decoded).into_rgba8();
+            sprites.push(Sprite { name, path, image });
+        }
+        Ok(sprites)
+    }
+}
+
+/// Packs rectangles into shelves, doubling the atlas size until they fit.
+/// Returns the atlas size and the position of each rectangle.
+pub(crate) fn pack(
+    sizes: &[(u32, u32)],
+    padding: u32,
+    max_size: u32,
+) -> Option<(u32, u32, Vec<(u32, u32)>)> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|&a, &b| {
+        sizes[b]
+            .1
+            .cmp(&sizes[a].1)
+            .then(sizes[b].0.cmp(&sizes[a].0))
+    });
+
+    let area: u64 = sizes
+        .iter()
+        .map(|(w, h)| (w + padding) as u64 * (h + padding) as u64)
+        .sum();
+    let widest = sizes.iter().map(|(w, _)| *w).max().unwrap_or(1);
+    let tallest = sizes.iter().map(|(_, h)| *h).max().unwrap_or(1);
+    let mut width = ((area as f64).sqrt() as u32)
+        .max(widest)
+        .next_power_of_two();
+    let mut height = tallest.next_power_of_two();
+
+    while width <= max_size && height <= max_size {
+        let mut positions = vec![(0, 0); sizes.len()];
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        let mut fits = true;
+        for &index in &order {
+            let (w, h) = sizes[index];
+            if x + w > width {
+                x = 0;
+                y += shelf_height + padding;
+                shelf_height = 0;
+            }
+            if y + h > height {
+                fits = false;
+                break;
+            }
+            positions[index] = (x, y);
+            x += w + padding;
+            shelf_height = shelf_height.max(h);
+        }
+        if fits {
+            return Some((width, height, positions));
+        }
+        if height < width {
+            height *= 2;
+        } else {
+            width *= 2;
+        }
+    }
+    None
+}
+
+impl AsyncImporter for TextureAtlasImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        3
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = ImageImporterOptions;
+
+    type State = NamedState;
+
+    /// Reads the atlas descriptor, packs its sprites and produces the atlas image and layout.
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        let options = options.clone();
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            source.read_to_end(&mut bytes).await?;
+            let descriptor: TextureAtlasDescriptor = ron::de::from_bytes(&bytes)
+                .map_err(|e| Error::Boxed(Box::new(TextureAtlasError::from(e))))?;
+            let sprites = self
+                .read_sprites(&descriptor, &options)
+                .map_err(|e| Error::Boxed(Box::new(e)))?;
+            if sprites.is_empty() {
+                return Err(Error::Boxed(Box::new(TextureAtlasError::Empty)));
+            }
+
+            let sizes: Vec<_> = sprites
+                .iter()
+                .map(|sprite| sprite.image.dimensions())
+                .collect();
+            let (width, height, positions) = pack(&sizes, descriptor.padding, descriptor.max_size)
+                .ok_or_else(|| {
+                    Error::Boxed(Box::new(TextureAtlasError::TooLarge {
+                        max_size: descriptor.max_size,
+                    }))
+                })?;
+
+            let mut atlas = ::image::RgbaImage::new(width, height);
+            let mut rects = Vec::new();
+            let mut names = HashMap::new();
+            for (index, (sprite, &(x, y))) in sprites.iter().zip(&positions).enumerate() {
+                ::image::imageops::replace(&mut atlas, &sprite.image, x as i64, y as i64);
+                rects.push(AtlasRect {
+                    x,
+                    y,
+                    width: sprite.image.width(),
+                    height: sprite.image.height(),
+                });
+                names.insert(sprite.name.clone(), index);
+            }
+
+            state.retain_names(vec!["image", "layout"]);
+            let image_id = state.id("image");
+            let layout_id = state.id("layout");
+            let build_deps: Vec<_> = sprites
+                .iter()
+                .map(|sprite| AssetRef::Path(sprite.path.clone()))
+                .collect();
+            let layout = TextureAtlasLayout {
+                image: image_id,
+                width,
+                height,
+                rects,
+                names,
+            };
+            // A path resolves to the first asset imported from it, so the layout goes first
+            Ok(ImporterValue {
+                assets: vec![
+                    ImportedAsset {
+                        id: layout_id,
+                        search_tags: vec![],
+                        build_deps: build_deps.clone(),
+                        load_deps: vec![AssetRef::Uuid(image_id)],
+                        build_pipeline: None,
+                        asset_data: Box::new(layout),
+                    },
+                    ImportedAsset {
+                        id: image_id,
+                        search_tags: vec![],
+                        build_deps,
+                        load_deps: vec![],
+                        build_pipeline: None,
+                        asset_data: Box::new(
+                            options.finish(::image::DynamicImage::ImageRgba8(atlas)),
+                        ),
+                    },
+                ],
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{image::Image, imported_data};
+    use std::fs;
+
+    fn overlaps(a: (u32, u32, u32, u32), b: (u32, u32, u32, u32)) -> bool {
+        a.0 < b.0 + b.2 && b.0 < a.0 + a.2 && a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+    }
+
+    #[test]
+    fn packed_rects_fit_without_overlapping() {
+        let sizes = [(10, 4), (3, 12), (7, 7), (1, 1), (16, 2), (5, 9), (8, 8)];
+        let padding = 2;
+        let (width, height, positions) = pack(&sizes, padding, 256).unwrap();
+        assert!(width.is_power_of_two() && height.is_power_of_two());
+        // Padding is left to the right and below each rect, so pad them before comparing
+        let padded: Vec<_> = sizes
+            .iter()
+            .zip(&positions)
+            .map(|(&(w, h), &(x, y))| {
+                assert!(x + w <= width && y + h <= height);
+                (x, y, w + padding, h + padding)
+            })
+            .collect();
+        for (index, a) in padded.iter().enumerate() {
+            for b in &padded[index + 1..] {
+                assert!(!overlaps(*a, *b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn packing_fails_past_the_max_size() {
+        assert!(pack(&[(65, 1)], 0, 64).is_none());
+        assert!(pack(&[(32, 32); 5], 0, 64).is_none());
+        assert_eq!(pack(&[(32, 32); 4], 0, 64).unwrap().0, 64);
+    }
+
+    fn asset_root(sprites: &[(&str, u32, u32)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("atlas-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(root.join("sprites")).unwrap();
+        for (path, width, height) in sprites {
+            ::image::RgbaImage::from_pixel(*width, *height, ::image::Rgba([255, 0, 0, 255]))
+                .save(root.join(path))
+                .unwrap();
+        }
+        root
+    }
+
+    fn import(
+        importer: &TextureAtlasImporter,
+        descriptor: &str,
+    ) -> std::result::Result<ImporterValue, atelier_importer::Error> {
+        let mut source = descriptor.as_bytes();
+        futures_executor::block_on(importer.import(
+            &mut ImportOp::default(),
+            &mut source,
+            &ImageImporterOptions::default(),
+            &mut NamedState::default(),
+        ))
+    }
+
+    #[test]
+    fn imports_layout_and_image_of_matched_sprites() {
+        let root = asset_root(&[
+            ("sprites/a.png", 4, 2),
+            ("sprites/b.png", 3, 3),
+            ("sprites/c.png", 2, 5),
+        ]);
+        let importer = TextureAtlasImporter::new(&root);
+        let value = import(&importer, "(sprites: [\"sprites/*.png\"], padding: 1)").unwrap();
+        assert_eq!(value.assets.len(), 2);
+        assert_eq!(value.assets[0].asset_data.uuid(), TextureAtlasLayout::UUID);
+        assert_eq!(value.assets[1].asset_data.uuid(), Image::UUID);
+        assert_eq!(value.assets[0].build_deps.len(), 3);
+
+        let layout: TextureAtlasLayout = imported_data(&value.assets[0]);
+        assert_eq!(layout.image, value.assets[1].id);
+        assert_eq!(
+            value.assets[0].load_deps,
+            vec![AssetRef::Uuid(value.assets[1].id)]
+        );
+        let sizes: Vec<_> = ["sprites/a", "sprites/b", "sprites/c"]
+            .iter()
+            .map(|name| {
+                let rect = layout.get(name).unwrap();
+                (rect.width, rect.height)
+            })
+            .collect();
+        assert_eq!(sizes, vec![(4, 2), (3, 3), (2, 5)]);
+        assert_eq!(layout.uv_rect(3), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn rejects_empty_and_oversized_atlases() {
+        let root = asset_root(&[("sprites/a.png", 4, 2)]);
+        let importer = TextureAtlasImporter::new(&root);
+        assert!(import(&importer, "(sprites: [\"sprites/*.jpg\"])").is_err());
+        assert!(import(&importer, "(sprites: [\"sprites/*.png\"], max_size: 2)").is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
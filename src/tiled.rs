@@ -0,0 +1,1005 @@
+use crate::{image::Image, AssetServer, NamedState};
+use atelier_core::AssetRef;
+use atelier_importer::{AsyncImporter, Error, ImportOp, ImportedAsset, ImporterValue, Result};
+use atelier_loader::handle::Handle;
+use futures_core::future::BoxFuture;
+use futures_io::AsyncRead;
+use futures_util::AsyncReadExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+use thiserror::Error;
+use type_uuid::*;
+
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+const GID_MASK: u32 = !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY);
+
+/// A custom property set in Tiled
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    /// A color in Tiled's `#AARRGGBB` notation
+    Color(String),
+    /// A file path relative to the asset root
+    File(String),
+    /// The id of an object in the map
+    Object(u32),
+}
+
+pub type Properties = HashMap<String, PropertyValue>;
+
+/// A tileset used by a [Tilemap]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct Tileset {
+    /// The global tile id of the first tile in this tileset
+    pub first_gid: u32,
+    pub name: String,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub spacing: u32,
+    pub margin: u32,
+    pub columns: u32,
+    pub tile_count: u32,
+    /// Path of the tileset image relative to the asset root. It is loaded along with the map.
+    pub image: Option<String>,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub properties: Properties,
+}
+
+impl Tileset {
+    /// A handle to the tileset image, which is already loading if the map has been loaded
+    pub fn image_handle(&self, asset_server: &AssetServer) -> Option<Handle<Image>> {
+        self.image.as_ref().map(|path| asset_server.load(path))
+    }
+}
+
+/// A tile placed in a layer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    /// Index into [Tilemap::tilesets]
+    pub tileset: usize,
+    /// Id of the tile within its tileset
+    pub id: u32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub flip_diagonal: bool,
+}
+
+/// A layer of tiles, stored row by row as global tile ids including Tiled's flip flags. 0 is an empty cell.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct TileLayer {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub visible: bool,
+    pub opacity: f32,
+    pub offset: [f32; 2],
+    pub tiles: Vec<u32>,
+    pub properties: Properties,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ObjectShape {
+    Rectangle,
+    Ellipse,
+    Point,
+    /// Points relative to the object's position
+    Polygon(Vec<[f32; 2]>),
+    Polyline(Vec<[f32; 2]>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MapObject {
+    pub id: u32,
+    pub name: String,
+    /// The object's type, called class in newer versions of Tiled
+    pub kind: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub rotation: f32,
+    pub visible: bool,
+    /// Set for tile objects
+    pub gid: Option<u32>,
+    pub shape: ObjectShape,
+    pub properties: Properties,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct ObjectGroup {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    pub offset: [f32; 2],
+    pub objects: Vec<MapObject>,
+    pub properties: Properties,
+}
+
+/// A map authored in Tiled. Group layers are flattened into their tile layers and object groups.
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[uuid = "64f8efdc-f147-4803-85ea-0e3bfeba64b7"]
+pub struct Tilemap {
+    pub orientation: String,
+    pub width: u32,
+    pub height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tilesets: Vec<Tileset>,
+    pub layers: Vec<TileLayer>,
+    pub object_groups: Vec<ObjectGroup>,
+    pub properties: Properties,
+}
+
+impl Tilemap {
+    /// Splits a global tile id into its tileset and flip flags
+    pub fn resolve_gid(&self, gid: u32) -> Option<Tile> {
+        let id = gid & GID_MASK;
+        if id == 0 {
+            return None;
+        }
+        let tileset = self
+            .tilesets
+            .iter()
+            .rposition(|tileset| tileset.first_gid <= id)?;
+        Some(Tile {
+            tileset,
+            id: id - self.tilesets[tileset].first_gid,
+            flip_x: gid & FLIPPED_HORIZONTALLY != 0,
+            flip_y: gid & FLIPPED_VERTICALLY != 0,
+            flip_diagonal: gid & FLIPPED_DIAGONALLY != 0,
+        })
+    }
+
+    pub fn tile(&self, layer: usize, x: u32, y: u32) -> Option<Tile> {
+        let layer = self.layers.get(layer)?;
+        if x >= layer.width || y >= layer.height {
+            return None;
+        }
+        self.resolve_gid(*layer.tiles.get((y * layer.width + x) as usize)?)
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&TileLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn object_group(&self, name: &str) -> Option<&ObjectGroup> {
+        self.object_groups.iter().find(|group| group.name == name)
+    }
+}
+
+/// Import settings for Tiled maps, editable in their `.meta` files
+#[derive(TypeUuid, Serialize, Deserialize, Clone, Debug, Default)]
+#[uuid = "174a4f9b-f11b-406a-8637-972d47640cb2"]
+#[serde(default)]
+pub struct TiledImporterOptions {
+    /// The map's directory relative to the asset root. Tiled writes paths relative to the map file,
+    /// so maps outside of the asset root's top level need this to find their tilesets and images.
+    pub directory: String,
+}
+
+/// Errors that occur while importing a Tiled map
+#[derive(Error, Debug)]
+pub enum TiledError {
+    #[error("Invalid TMX: {0}")]
+    Xml(#[from] roxmltree::Error),
+    #[error("Invalid JSON map: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to read {path:?}: {error}")]
+    Io { path: PathBuf, error: io::Error },
+    #[error("Invalid map: {0}")]
+    Invalid(String),
+    #[error("Infinite maps are not supported.")]
+    Infinite,
+}
+
+fn invalid<S: Into<String>>(message: S) -> TiledError {
+    TiledError::Invalid(message.into())
+}
+
+/// Joins a path written in a map to the directory it is relative to, resolving `..` lexically
+fn resolve(dir: &Path, path: &str) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in dir.join(path).components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::CurDir => {}
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+fn to_asset_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Tracks the files a map depends on while it is parsed
+struct MapReader<'a> {
+    asset_root: &'a Path,
+    build_deps: Vec<PathBuf>,
+    images: Vec<String>,
+}
+
+impl<'a> MapReader<'a> {
+    fn read_external(&mut self, path: &Path) -> std::result::Result<String, TiledError> {
+        let full_path = self.asset_root.join(path);
+        let text = std::fs::read_to_string(&full_path).map_err(|error| TiledError::Io {
+            path: full_path.clone(),
+            error,
+        })?;
+        self.build_deps.push(full_path);
+        Ok(text)
+    }
+
+    fn image(&mut self, dir: &Path, source: &str) -> String {
+        let path = to_asset_path(&resolve(dir, source));
+        if !self.images.contains(&path) {
+            self.images.push(path.clone());
+        }
+        path
+    }
+
+    fn property_value(&mut self, dir: &Path, kind: &str, value: &str) -> PropertyValue {
+        match kind {
+            "bool" => PropertyValue::Bool(value == "true"),
+            "int" => PropertyValue::Int(value.parse().unwrap_or_default()),
+            "float" => PropertyValue::Float(value.parse().unwrap_or_default()),
+            "color" => PropertyValue::Color(value.to_string()),
+            "file" => PropertyValue::File(to_asset_path(&resolve(dir, value))),
+            "object" => PropertyValue::Object(value.parse().unwrap_or_default()),
+            _ => PropertyValue::String(value.to_string()),
+        }
+    }
+}
+
+fn decode_tile_data(
+    data: &str,
+    encoding: Option<&str>,
+    compression: Option<&str>,
+) -> std::result::Result<Vec<u32>, TiledError> {
+    match encoding {
+        Some("csv") => data
+            .split(',')
+            .map(|gid| {
+                gid.trim()
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid tile id {:?}", gid.trim())))
+            })
+            .collect(),
+        Some("base64") => {
+            let bytes = base64::decode(data.trim())
+                .map_err(|err| invalid(format!("invalid base64 tile data: {}", err)))?;
+            let bytes = match compression {
+                None | Some("") => bytes,
+                Some(compression) => {
+                    let mut decompressed = Vec::new();
+                    let result = match compression {
+                        "zlib" => flate2::read::ZlibDecoder::new(&bytes[..])
+                            .read_to_end(&mut decompressed),
+                        "gzip" => {
+                            flate2::read::GzDecoder::new(&bytes[..]).read_to_end(&mut decompressed)
+                        }
+                        "zstd" => zstd::stream::read::Decoder::new(&bytes[..])
+                            .and_then(|mut decoder| decoder.read_to_end(&mut decompressed)),
+                        other => return Err(invalid(format!("unknown compression {:?}", other))),
+                    };
+                    result
+                        .map_err(|err| invalid(format!("invalid compressed tile data: {}", err)))?;
+                    decompressed
+                }
+            };
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+                .collect())
+        }
+        other => Err(invalid(format!("unsupported tile encoding {:?}", other))),
+    }
+}
+
+fn parse_points(points: &str) -> Vec<[f32; 2]> {
+    points
+        .split_whitespace()
+        .filter_map(|point| {
+            let mut coords = point.split(',').map(|coord| coord.parse().unwrap_or(0.0));
+            Some([coords.next()?, coords.next()?])
+        })
+        .collect()
+}
+
+mod tmx {
+    use super::*;
+    use roxmltree::Node;
+
+    fn attr<T: std::str::FromStr>(node: Node, name: &str) -> Option<T> {
+        node.attribute(name).and_then(|value| value.parse().ok())
+    }
+
+    fn string(node: Node, name: &str) -> String {
+        node.attribute(name).unwrap_or_default().to_string()
+    }
+
+    fn properties(reader: &mut MapReader, dir: &Path, node: Node) -> Properties {
+        let mut properties = Properties::new();
+        let children = node
+            .children()
+            .filter(|child| child.has_tag_name("properties"))
+            .flat_map(|child| child.children())
+            .filter(|child| child.has_tag_name("property"));
+        for property in children {
+            let value = property
+                .attribute("value")
+                .or_else(|| property.text())
+                .unwrap_or_default();
+            let kind = property.attribute("type").unwrap_or("string");
+            properties.insert(
+                string(property, "name"),
+                reader.property_value(dir, kind, value),
+            );
+        }
+        properties
+    }
+
+    fn tileset(
+        reader: &mut MapReader,
+        dir: &Path,
+        node: Node,
+        first_gid: u32,
+    ) -> std::result::Result<Tileset, TiledError> {
+        let image = node.children().find(|child| child.has_tag_name("image"));
+        Ok(Tileset {
+            first_gid,
+            name: string(node, "name"),
+            tile_width: attr(node, "tilewidth").unwrap_or(0),
+            tile_height: attr(node, "tileheight").unwrap_or(0),
+            spacing: attr(node, "spacing").unwrap_or(0),
+            margin: attr(node, "margin").unwrap_or(0),
+            columns: attr(node, "columns").unwrap_or(0),
+            tile_count: attr(node, "tilecount").unwrap_or(0),
+            image: image
+                .and_then(|image| image.attribute("source"))
+                .map(|source| reader.image(dir, source)),
+            image_width: image.and_then(|image| attr(image, "width")).unwrap_or(0),
+            image_height: image.and_then(|image| attr(image, "height")).unwrap_or(0),
+            properties: properties(reader, dir, node),
+        })
+    }
+
+    /// Reads a `.tsx` or `.tsj` tileset, recording it as a build dependency
+    pub(super) fn external_tileset(
+        reader: &mut MapReader,
+        dir: &Path,
+        source: &str,
+        first_gid: u32,
+    ) -> std::result::Result<Tileset, TiledError> {
+        let path = resolve(dir, source);
+        let text = reader.read_external(&path)?;
+        let tileset_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        if path
+            .extension()
+            .map_or(false, |ext| ext == "tsj" || ext == "json")
+        {
+            let value: Value = serde_json::from_str(&text)?;
+            return Ok(json::tileset(reader, tileset_dir, &value, first_gid));
+        }
+        let document = roxmltree::Document::parse(&text)?;
+        tileset(reader, tileset_dir, document.root_element(), first_gid)
+    }
+
+    fn offset(node: Node) -> [f32; 2] {
+        [
+            attr(node, "offsetx").unwrap_or(0.0),
+            attr(node, "offsety").unwrap_or(0.0),
+        ]
+    }
+
+    fn tile_layer(
+        reader: &mut MapReader,
+        dir: &Path,
+        node: Node,
+    ) -> std::result::Result<TileLayer, TiledError> {
+        let data = node
+            .children()
+            .find(|child| child.has_tag_name("data"))
+            .ok_or_else(|| invalid("tile layer without data"))?;
+        if data.children().any(|child| child.has_tag_name("chunk")) {
+            return Err(TiledError::Infinite);
+        }
+        let tiles = match data.attribute("encoding") {
+            None => data
+                .children()
+                .filter(|child| child.has_tag_name("tile"))
+                .map(|tile| attr(tile, "gid").unwrap_or(0))
+                .collect(),
+            encoding => decode_tile_data(
+                data.text().unwrap_or_default(),
+                encoding,
+                data.attribute("compression"),
+            )?,
+        };
+        Ok(TileLayer {
+            name: string(node, "name"),
+            width: attr(node, "width").unwrap_or(0),
+            height: attr(node, "height").unwrap_or(0),
+            visible: node.attribute("visible") != Some("0"),
+            opacity: attr(node, "opacity").unwrap_or(1.0),
+            offset: offset(node),
+            tiles,
+            properties: properties(reader, dir, node),
+        })
+    }
+
+    fn object(reader: &mut MapReader, dir: &Path, node: Node) -> MapObject {
+        let child = |name: &str| node.children().find(|child| child.has_tag_name(name));
+        let shape = if let Some(polygon) = child("polygon") {
+            ObjectShape::Polygon(parse_points(
+                polygon.attribute("points").unwrap_or_default(),
+            ))
+        } else if let Some(polyline) = child("polyline") {
+            ObjectShape::Polyline(parse_points(
+                polyline.attribute("points").unwrap_or_default(),
+            ))
+        } else if child("ellipse").is_some() {
+            ObjectShape::Ellipse
+        } else if child("point").is_some() {
+            ObjectShape::Point
+        } else {
+            ObjectShape::Rectangle
+        };
+        MapObject {
+            id: attr(node, "id").unwrap_or(0),
+            name: string(node, "name"),
+            kind: node
+                .attribute("type")
+                .or_else(|| node.attribute("class"))
+                .unwrap_or_default()
+                .to_string(),
+            x: attr(node, "x").unwrap_or(0.0),
+            y: attr(node, "y").unwrap_or(0.0),
+            width: attr(node, "width").unwrap_or(0.0),
+            height: attr(node, "height").unwrap_or(0.0),
+            rotation: attr(node, "rotation").unwrap_or(0.0),
+            visible: node.attribute("visible") != Some("0"),
+            gid: attr(node, "gid"),
+            shape,
+            properties: properties(reader, dir, node),
+        }
+    }
+
+    fn layers(
+        reader: &mut MapReader,
+        dir: &Path,
+        node: Node,
+        map: &mut Tilemap,
+    ) -> std::result::Result<(), TiledError> {
+        for child in node.children() {
+            match child.tag_name().name() {
+                "layer" => map.layers.push(tile_layer(reader, dir, child)?),
+                "objectgroup" => map.object_groups.push(ObjectGroup {
+                    name: string(child, "name"),
+                    visible: child.attribute("visible") != Some("0"),
+                    opacity: attr(child, "opacity").unwrap_or(1.0),
+                    offset: offset(child),
+                    objects: child
+                        .children()
+                        .filter(|object| object.has_tag_name("object"))
+                        .map(|object| self::object(reader, dir, object))
+                        .collect(),
+                    properties: properties(reader, dir, child),
+                }),
+                "group" => layers(reader, dir, child, map)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn map(
+        reader: &mut MapReader,
+        dir: &Path,
+        text: &str,
+    ) -> std::result::Result<Tilemap, TiledError> {
+        let document = roxmltree::Document::parse(text)?;
+        let root = document.root_element();
+        if !root.has_tag_name("map") {
+            return Err(invalid("root element is not <map>"));
+        }
+        if root.attribute("infinite") == Some("1") {
+            return Err(TiledError::Infinite);
+        }
+        let mut map = Tilemap {
+            orientation: string(root, "orientation"),
+            width: attr(root, "width").unwrap_or(0),
+            height: attr(root, "height").unwrap_or(0),
+            tile_width: attr(root, "tilewidth").unwrap_or(0),
+            tile_height: attr(root, "tileheight").unwrap_or(0),
+            properties: properties(reader, dir, root),
+            ..Default::default()
+        };
+        for node in root
+            .children()
+            .filter(|child| child.has_tag_name("tileset"))
+        {
+            let first_gid = attr(node, "firstgid").unwrap_or(1);
+            map.tilesets.push(match node.attribute("source") {
+                Some(source) => external_tileset(reader, dir, source, first_gid)?,
+                None => tileset(reader, dir, node, first_gid)?,
+            });
+        }
+        layers(reader, dir, root, &mut map)?;
+        Ok(map)
+    }
+}
+
+mod json {
+    use super::*;
+
+    fn u32_field(value: &Value, name: &str) -> u32 {
+        value[name].as_u64().unwrap_or(0) as u32
+    }
+
+    fn f32_field(value: &Value, name: &str, default: f32) -> f32 {
+        value[name].as_f64().map_or(default, |v| v as f32)
+    }
+
+    fn string(value: &Value, name: &str) -> String {
+        value[name].as_str().unwrap_or_default().to_string()
+    }
+
+    fn properties(reader: &mut MapReader, dir: &Path, value: &Value) -> Properties {
+        let mut properties = Properties::new();
+        for property in value["properties"].as_array().into_iter().flatten() {
+            let kind = property["type"].as_str().unwrap_or("string");
+            let raw = match &property["value"] {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            properties.insert(
+                string(property, "name"),
+                reader.property_value(dir, kind, &raw),
+            );
+        }
+        properties
+    }
+
+    pub(super) fn tileset(
+        reader: &mut MapReader,
+        dir: &Path,
+        value: &Value,
+        first_gid: u32,
+    ) -> Tileset {
+        Tileset {
+            first_gid,
+            name: string(value, "name"),
+            tile_width: u32_field(value, "tilewidth"),
+            tile_height: u32_field(value, "tileheight"),
+            spacing: u32_field(value, "spacing"),
+            margin: u32_field(value, "margin"),
+            columns: u32_field(value, "columns"),
+            tile_count: u32_field(value, "tilecount"),
+            image: value["image"]
+                .as_str()
+                .map(|source| reader.image(dir, source)),
+            image_width: u32_field(value, "imagewidth"),
+            image_height: u32_field(value, "imageheight"),
+            properties: properties(reader, dir, value),
+        }
+    }
+
+    fn object(reader: &mut MapReader, dir: &Path, value: &Value) -> MapObject {
+        let points = |name: &str| -> Vec<[f32; 2]> {
+            value[name]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|point| [f32_field(point, "x", 0.0), f32_field(point, "y", 0.0)])
+                .collect()
+        };
+        let shape = if value["polygon"].is_array() {
+            ObjectShape::Polygon(points("polygon"))
+        } else if value["polyline"].is_array() {
+            ObjectShape::Polyline(points("polyline"))
+        } else if value["ellipse"].as_bool() == Some(true) {
+            ObjectShape::Ellipse
+        } else if value["point"].as_bool() == Some(true) {
+            ObjectShape::Point
+        } else {
+            ObjectShape::Rectangle
+        };
+        MapObject {
+            id: u32_field(value, "id"),
+            name: string(value, "name"),
+            kind: value["type"]
+                .as_str()
+                .or_else(|| value["class"].as_str())
+                .unwrap_or_default()
+                .to_string(),
+            x: f32_field(value, "x", 0.0),
+            y: f32_field(value, "y", 0.0),
+            width: f32_field(value, "width", 0.0),
+            height: f32_field(value, "height", 0.0),
+            rotation: f32_field(value, "rotation", 0.0),
+            visible: value["visible"].as_bool().unwrap_or(true),
+            gid: value["gid"].as_u64().map(|gid| gid as u32),
+            shape,
+            properties: properties(reader, dir, value),
+        }
+    }
+
+    fn layers(
+        reader: &mut MapReader,
+        dir: &Path,
+        value: &Value,
+        map: &mut Tilemap,
+    ) -> std::result::Result<(), TiledError> {
+        for layer in value["layers"].as_array().into_iter().flatten() {
+            let offset = [
+                f32_field(layer, "offsetx", 0.0),
+                f32_field(layer, "offsety", 0.0),
+            ];
+            match layer["type"].as_str() {
+                Some("tilelayer") => {
+                    if layer["chunks"].is_array() {
+                        return Err(TiledError::Infinite);
+                    }
+                    let tiles = match &layer["data"] {
+                        Value::Array(gids) => gids
+                            .iter()
+                            .map(|gid| gid.as_u64().unwrap_or(0) as u32)
+                            .collect(),
+                        Value::String(data) => decode_tile_data(
+                            data,
+                            layer["encoding"].as_str(),
+                            layer["compression"].as_str(),
+                        )?,
+                        _ => return Err(invalid("tile layer without data")),
+                    };
+                    map.layers.push(TileLayer {
+                        name: string(layer, "name"),
+                        width: u32_field(layer, "width"),
+                        height: u32_field(layer, "height"),
+                        visible: layer["visible"].as_bool().unwrap_or(true),
+                        opacity: f32_field(layer, "opacity", 1.0),
+                        offset,
+                        tiles,
+                        properties: properties(reader, dir, layer),
+                    });
+                }
+                Some("objectgroup") => map.object_groups.push(ObjectGroup {
+                    name: string(layer, "name"),
+                    visible: layer["visible"].as_bool().unwrap_or(true),
+                    opacity: f32_field(layer, "opacity", 1.0),
+                    offset,
+                    objects: layer["objects"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .map(|object| self::object(reader, dir, object))
+                        .collect(),
+                    properties: properties(reader, dir, layer),
+                }),
+                Some("group") => layers(reader, dir, layer, map)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn map(
+        reader: &mut MapReader,
+        dir: &Path,
+        text: &str,
+    ) -> std::result::Result<Tilemap, TiledError> {
+        let value: Value = serde_json::from_str(text)?;
+        if value["infinite"].as_bool() == Some(true) {
+            return Err(TiledError::Infinite);
+        }
+        let mut map = Tilemap {
+            orientation: string(&value, "orientation"),
+            width: u32_field(&value, "width"),
+            height: u32_field(&value, "height"),
+            tile_width: u32_field(&value, "tilewidth"),
+            tile_height: u32_field(&value, "tileheight"),
+            properties: properties(reader, dir, &value),
+            ..Default::default()
+        };
+        for tileset in value["tilesets"].as_array().into_iter().flatten() {
+            let first_gid = tileset["firstgid"].as_u64().unwrap_or(1) as u32;
+            map.tilesets.push(match tileset["source"].as_str() {
+                Some(source) => super::tmx::external_tileset(reader, dir, source, first_gid)?,
+                None => self::tileset(reader, dir, tileset, first_gid),
+            });
+        }
+        layers(reader, dir, &value, &mut map)?;
+        Ok(map)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TiledFormat {
+    Tmx,
+    Json,
+}
+
+fn read_map(
+    reader: &mut MapReader,
+    dir: &Path,
+    text: &str,
+    format: TiledFormat,
+) -> std::result::Result<Tilemap, TiledError> {
+    let map = match format {
+        TiledFormat::Tmx => tmx::map(reader, dir, text)?,
+        TiledFormat::Json => json::map(reader, dir, text)?,
+    };
+    for layer in &map.layers {
+        let expected = layer.width as usize * layer.height as usize;
+        if layer.tiles.len() != expected {
+            return Err(invalid(format!(
+                "layer {:?} has {} tiles instead of {}",
+                layer.name,
+                layer.tiles.len(),
+                expected
+            )));
+        }
+    }
+    Ok(map)
+}
+
+/// Imports maps saved by Tiled as a [Tilemap].
+///
+/// External tilesets are build dependencies of the map, and tileset images are loaded along with it.
+#[derive(TypeUuid)]
+#[uuid = "06ff026f-7b93-4ee6-bb6b-31963211fa89"]
+pub struct TiledImporter {
+    asset_root: PathBuf,
+    format: TiledFormat,
+}
+
+impl TiledImporter {
+    /// An importer that resolves paths relative to `asset_root`
+    pub fn new<P: AsRef<Path>>(asset_root: P, format: TiledFormat) -> Self {
+        TiledImporter {
+            asset_root: asset_root.as_ref().to_path_buf(),
+            format,
+        }
+    }
+}
+
+impl AsyncImporter for TiledImporter {
+    fn version_static() -> u32
+    where
+        Self: Sized,
+    {
+        1
+    }
+    fn version(&self) -> u32 {
+        Self::version_static()
+    }
+
+    type Options = TiledImporterOptions;
+
+    type State = NamedState;
+
+    /// Parses the map and its external tilesets.
+    fn import<'a>(
+        &'a self,
+        _op: &'a mut ImportOp,
+        source: &'a mut (dyn AsyncRead + Unpin + Send + Sync),
+        options: &Self::Options,
+        state: &'a mut Self::State,
+    ) -> BoxFuture<'a, Result<ImporterValue>> {
+        let dir = PathBuf::from(&options.directory);
+        Box::pin(async move {
+            let mut text = String::new();
+            source.read_to_string(&mut text).await?;
+            let mut reader = MapReader {
+                asset_root: &self.asset_root,
+                build_deps: Vec::new(),
+                images: Vec::new(),
+            };
+            let map = read_map(&mut reader, &dir, &text, self.format)
+                .map_err(|e| Error::Boxed(Box::new(e)))?;
+            Ok(ImporterValue {
+                assets: vec![ImportedAsset {
+                    id: state.id("asset"),
+                    search_tags: vec![],
+                    build_deps: reader.build_deps.into_iter().map(AssetRef::Path).collect(),
+                    load_deps: reader
+                        .images
+                        .iter()
+                        .map(|image| AssetRef::Path(self.asset_root.join(image)))
+                        .collect(),
+                    build_pipeline: None,
+                    asset_data: Box::new(map),
+                }],
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map orientation="orthogonal" width="2" height="2" tilewidth="16" tileheight="16" infinite="0">
+ <properties>
+  <property name="music" type="file" value="../audio/theme.ogg"/>
+ </properties>
+ <tileset firstgid="1" name="terrain" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="../images/terrain.png" width="32" height="32"/>
+ </tileset>
+ <tileset firstgid="5" source="props.tsx"/>
+ <group name="world">
+  <layer name="ground" width="2" height="2">
+   <data encoding="csv">
+1,2147483650,
+0,6
+</data>
+  </layer>
+ </group>
+ <objectgroup name="spawns">
+  <object id="1" name="player" type="spawn" x="8" y="24"><point/></object>
+ </objectgroup>
+</map>
+"#;
+
+    const TILESET: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset name="props" tilewidth="16" tileheight="16" tilecount="2" columns="2">
+ <image source="props.png" width="32" height="16"/>
+</tileset>
+"#;
+
+    /// An asset root with `maps/props.tsx`
+    fn asset_root() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("tiled-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("maps")).unwrap();
+        std::fs::write(root.join("maps/props.tsx"), TILESET).unwrap();
+        root
+    }
+
+    fn read(
+        root: &Path,
+        text: &str,
+        format: TiledFormat,
+    ) -> std::result::Result<(Tilemap, Vec<PathBuf>, Vec<String>), TiledError> {
+        let mut reader = MapReader {
+            asset_root: root,
+            build_deps: Vec::new(),
+            images: Vec::new(),
+        };
+        let map = read_map(&mut reader, Path::new("maps"), text, format)?;
+        Ok((map, reader.build_deps, reader.images))
+    }
+
+    fn tile(tileset: usize, id: u32, flip_x: bool) -> Tile {
+        Tile {
+            tileset,
+            id,
+            flip_x,
+            flip_y: false,
+            flip_diagonal: false,
+        }
+    }
+
+    #[test]
+    fn reads_tmx_with_external_tileset() {
+        let root = asset_root();
+        let (map, build_deps, images) = read(&root, MAP, TiledFormat::Tmx).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!((map.width, map.height), (2, 2));
+        assert_eq!(map.tilesets.len(), 2);
+        assert_eq!(map.tilesets[0].image.as_deref(), Some("images/terrain.png"));
+        assert_eq!(map.tilesets[1].name, "props");
+        assert_eq!(map.tilesets[1].first_gid, 5);
+        assert_eq!(map.tilesets[1].image.as_deref(), Some("maps/props.png"));
+        assert_eq!(images, vec!["images/terrain.png", "maps/props.png"]);
+        assert_eq!(build_deps, vec![root.join("maps/props.tsx")]);
+        assert_eq!(
+            map.properties.get("music"),
+            Some(&PropertyValue::File("audio/theme.ogg".to_string()))
+        );
+
+        assert_eq!(map.layer("ground").unwrap().tiles.len(), 4);
+        assert_eq!(map.tile(0, 0, 0), Some(tile(0, 0, false)));
+        assert_eq!(map.tile(0, 1, 0), Some(tile(0, 1, true)));
+        assert_eq!(map.tile(0, 0, 1), None);
+        assert_eq!(map.tile(0, 1, 1), Some(tile(1, 1, false)));
+        assert_eq!(map.tile(0, 2, 0), None);
+        assert_eq!(map.tile(1, 0, 0), None);
+
+        let player = &map.object_group("spawns").unwrap().objects[0];
+        assert_eq!(
+            (player.name.as_str(), player.kind.as_str()),
+            ("player", "spawn")
+        );
+        assert_eq!(player.shape, ObjectShape::Point);
+    }
+
+    #[test]
+    fn reads_json_maps() {
+        let text = r#"{
+            "orientation": "orthogonal", "width": 2, "height": 1,
+            "tilewidth": 16, "tileheight": 16,
+            "tilesets": [
+                {"firstgid": 1, "name": "terrain", "tilecount": 4, "image": "terrain.png"},
+                {"firstgid": 5, "source": "props.tsx"}
+            ],
+            "layers": [
+                {"type": "tilelayer", "name": "ground", "width": 2, "height": 1, "data": [5, 1073741825]}
+            ]
+        }"#;
+        let root = asset_root();
+        let (map, _, images) = read(&root, text, TiledFormat::Json).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(images, vec!["maps/terrain.png", "maps/props.png"]);
+        assert_eq!(map.tile(0, 0, 0), Some(tile(1, 0, false)));
+        let flipped = map.tile(0, 1, 0).unwrap();
+        assert_eq!((flipped.tileset, flipped.id), (0, 0));
+        assert!(flipped.flip_y && !flipped.flip_x && !flipped.flip_diagonal);
+    }
+
+    #[test]
+    fn resolves_gids_across_tilesets() {
+        let map = Tilemap {
+            tilesets: vec![
+                Tileset {
+                    first_gid: 1,
+                    ..Default::default()
+                },
+                Tileset {
+                    first_gid: 17,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(map.resolve_gid(0), None);
+        assert_eq!(map.resolve_gid(FLIPPED_HORIZONTALLY), None);
+        assert_eq!(map.resolve_gid(16), Some(tile(0, 15, false)));
+        assert_eq!(map.resolve_gid(17), Some(tile(1, 0, false)));
+        let flipped = map
+            .resolve_gid(20 | FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY)
+            .unwrap();
+        assert_eq!((flipped.tileset, flipped.id), (1, 3));
+        assert!(flipped.flip_x && flipped.flip_y && flipped.flip_diagonal);
+    }
+
+    #[test]
+    fn rejects_layers_with_missing_tiles() {
+        let text = r#"<map width="2" height="2"><layer name="ground" width="2" height="2"><data encoding="csv">1,1,1</data></layer></map>"#;
+        let root = asset_root();
+        let result = read(&root, text, TiledFormat::Tmx);
+        std::fs::remove_dir_all(&root).unwrap();
+        match result {
+            Err(TiledError::Invalid(message)) => {
+                assert_eq!(message, "layer \"ground\" has 3 tiles instead of 4")
+            }
+            other => panic!("expected an invalid map error, got {:?}", other.map(|_| ())),
+        }
+        assert_eq!(
+            read(&root, "<tileset/>", TiledFormat::Tmx)
+                .err()
+                .map(|e| e.to_string()),
+            Some("Invalid map: root element is not <map>".to_string())
+        );
+    }
+}
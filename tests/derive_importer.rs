@@ -0,0 +1,178 @@
+//! Importers generated by `#[derive(Importer)]`, run on files the way the asset daemon runs them
+
+use atelier_core::CompressionType;
+use atelier_importer::{AsyncImporter, ImportOp, ImporterValue, SerializedAsset};
+use bevy_atelier::{Importer, ImporterExtensions, NamedState};
+use futures_util::io::AllowStdIo;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{env, fmt, fs};
+use type_uuid::{TypeUuid, TypeUuidDynamic};
+
+#[derive(TypeUuid, Serialize, Deserialize, Importer, Debug, PartialEq)]
+#[uuid = "2df674be-8bfc-405c-9d57-e2e6617ddd9b"]
+#[importer(extensions("item", ".Items"), version = 3, validate = "Item::validate")]
+pub struct Item {
+    name: String,
+    damage: u32,
+}
+
+#[derive(Debug)]
+pub struct TooMuchDamage(u32);
+
+impl fmt::Display for TooMuchDamage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} damage is more than 100", self.0)
+    }
+}
+
+impl std::error::Error for TooMuchDamage {}
+
+impl Item {
+    fn validate(&self) -> Result<(), TooMuchDamage> {
+        if self.damage > 100 {
+            Err(TooMuchDamage(self.damage))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(TypeUuid, Serialize, Deserialize, Importer, Debug, PartialEq)]
+#[uuid = "49163b87-3a8d-42c5-9faa-5652a113ced4"]
+#[importer(extension = "stats", format = "json")]
+pub struct Stats {
+    health: u32,
+    speed: f32,
+}
+
+#[derive(TypeUuid, Serialize, Deserialize, Importer, Debug, PartialEq)]
+#[uuid = "ff8735a3-9751-431d-8a0f-981db7b81a00"]
+#[importer(
+    extension = "cfg",
+    format = "toml",
+    name = "SettingsLoader",
+    uuid = "699a5fcc-3b71-4c86-80f3-045f5e75e1ff"
+)]
+pub struct Settings {
+    volume: f32,
+    fullscreen: bool,
+}
+
+/// Writes `contents` to a file and imports it
+fn import<I>(
+    importer: &I,
+    contents: &str,
+    state: &mut NamedState,
+) -> atelier_importer::Result<ImporterValue>
+where
+    I: AsyncImporter<Options = (), State = NamedState>,
+{
+    let path = env::temp_dir().join(format!("derive-importer-{}", uuid::Uuid::new_v4()));
+    fs::write(&path, contents).unwrap();
+    let mut source = AllowStdIo::new(fs::File::open(&path).unwrap());
+    let imported = futures_executor::block_on(importer.import(
+        &mut ImportOp::default(),
+        &mut source,
+        &(),
+        state,
+    ));
+    fs::remove_file(path).unwrap();
+    imported
+}
+
+/// The single asset an importer produced, read back from its artifact
+fn asset<T: TypeUuid + DeserializeOwned>(value: &ImporterValue) -> T {
+    assert_eq!(value.assets.len(), 1);
+    let asset = &value.assets[0];
+    assert_eq!(asset.asset_data.uuid(), T::UUID);
+    let mut scratch = Vec::new();
+    let serialized = SerializedAsset::create(
+        asset.id,
+        Vec::new(),
+        Vec::new(),
+        &*asset.asset_data,
+        CompressionType::None,
+        &mut scratch,
+    )
+    .unwrap();
+    bincode::deserialize(&serialized.data).unwrap()
+}
+
+#[test]
+fn imports_ron() {
+    assert_eq!(ItemImporter::EXTENSIONS, ["item", "items"]);
+    assert_eq!(ItemImporter::version_static(), 3);
+
+    let mut state = NamedState::default();
+    let value = import(&ItemImporter, "(name: \"sword\", damage: 12)", &mut state).unwrap();
+    assert_eq!(
+        asset::<Item>(&value),
+        Item {
+            name: "sword".to_string(),
+            damage: 12,
+        }
+    );
+
+    // The asset keeps its UUID when the file is imported again
+    let id = value.assets[0].id;
+    let value = import(&ItemImporter, "(name: \"axe\", damage: 20)", &mut state).unwrap();
+    assert_eq!(value.assets[0].id, id);
+}
+
+#[test]
+fn rejects_files_that_fail_validation_or_parsing() {
+    let mut state = NamedState::default();
+    assert!(import(&ItemImporter, "(name: \"sword\", damage: 500)", &mut state).is_err());
+    assert!(import(&ItemImporter, "(name: \"sword\")", &mut state).is_err());
+    assert!(import(&StatsImporter, "{\"health\": 10,}", &mut state).is_err());
+}
+
+#[test]
+fn imports_json() {
+    assert_eq!(StatsImporter::EXTENSIONS, ["stats"]);
+    assert_eq!(StatsImporter::version_static(), 1);
+    let value = import(
+        &StatsImporter,
+        r#"{"health": 10, "speed": 1.5}"#,
+        &mut NamedState::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        asset::<Stats>(&value),
+        Stats {
+            health: 10,
+            speed: 1.5,
+        }
+    );
+}
+
+#[test]
+fn imports_toml_with_named_importer() {
+    assert_eq!(SettingsLoader::EXTENSIONS, ["cfg"]);
+    assert_eq!(
+        SettingsLoader::UUID,
+        *uuid::Uuid::parse_str("699a5fcc-3b71-4c86-80f3-045f5e75e1ff")
+            .unwrap()
+            .as_bytes()
+    );
+    let value = import(
+        &SettingsLoader,
+        "volume = 0.5\nfullscreen = true\n",
+        &mut NamedState::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        asset::<Settings>(&value),
+        Settings {
+            volume: 0.5,
+            fullscreen: true,
+        }
+    );
+}
+
+#[test]
+fn derived_importer_uuids_differ_from_their_assets() {
+    assert_ne!(ItemImporter::UUID, Item::UUID);
+    assert_ne!(StatsImporter::UUID, Stats::UUID);
+    assert_ne!(ItemImporter::UUID, StatsImporter::UUID);
+}